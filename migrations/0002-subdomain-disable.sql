@@ -0,0 +1,2 @@
+ALTER TABLE subdomains ADD COLUMN disabled_reason text;
+ALTER TABLE subdomains ADD COLUMN reenable_at bigint;
@@ -0,0 +1,6 @@
+CREATE TABLE encryption (
+  id INTEGER PRIMARY KEY CHECK (id = 1),
+  key_source text not null,
+  salt text,
+  key_check text not null
+);
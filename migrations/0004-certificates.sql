@@ -0,0 +1,24 @@
+CREATE TABLE certificates (
+  id INTEGER PRIMARY KEY,
+  subdomain text not null references subdomains(name),
+  serial text not null,
+  issuer text not null,
+  not_before bigint not null,
+  not_after bigint not null,
+  key_type text not null,
+  acme_account bigint references acme_accounts(id),
+  cert text not null,
+  deploy_status text not null default 'pending',
+  deploy_error text,
+  deployed_at bigint,
+  created_at bigint not null
+);
+
+CREATE INDEX certificates_subdomain ON certificates(subdomain);
+
+CREATE TRIGGER subdomains_delete_certificates AFTER DELETE ON subdomains
+BEGIN
+  DELETE FROM certificates WHERE subdomain = OLD.name;
+END;
+
+ALTER TABLE subdomains ADD COLUMN serial text;
@@ -0,0 +1,2 @@
+ALTER TABLE certificates ADD COLUMN revoked_at bigint;
+ALTER TABLE certificates ADD COLUMN revocation_reason text;
@@ -0,0 +1,61 @@
+CREATE TABLE subdomain_endpoints (
+  subdomain text not null references subdomains(name),
+  endpoint bigint not null references endpoints(id),
+  PRIMARY KEY (subdomain, endpoint)
+);
+
+CREATE INDEX subdomain_endpoints_endpoint ON subdomain_endpoints(endpoint);
+
+INSERT INTO subdomain_endpoints (subdomain, endpoint) SELECT name, endpoint FROM subdomains;
+
+CREATE TABLE certificate_deploys (
+  certificate bigint not null references certificates(id),
+  endpoint bigint not null references endpoints(id),
+  status text not null,
+  error text,
+  deployed_at bigint,
+  attempted_at bigint not null,
+  PRIMARY KEY (certificate, endpoint)
+);
+
+CREATE TRIGGER certificates_delete_deploys AFTER DELETE ON certificates
+BEGIN
+  DELETE FROM certificate_deploys WHERE certificate = OLD.id;
+END;
+
+-- Earlier deploys went to the host the subdomain uses now.
+INSERT INTO certificate_deploys (certificate, endpoint, status, error, deployed_at, attempted_at)
+  SELECT c.id, sd.endpoint, c.deploy_status, c.deploy_error, c.deployed_at,
+    COALESCE(c.deployed_at, c.created_at)
+  FROM certificates c
+  JOIN subdomains sd ON sd.name=c.subdomain
+  WHERE c.deploy_status != 'pending';
+
+-- SQLite can't drop a column that references another table, so rebuild subdomains without it.
+CREATE TABLE subdomains_new (
+  name text not null primary key,
+  acme_account bigint not null references acme_accounts(id),
+  dns_provider bigint not null references dns_providers (id),
+  last_cert text,
+  expires bigint,
+  enabled boolean not null default true,
+  disabled_reason text,
+  reenable_at bigint,
+  serial text
+);
+
+INSERT INTO subdomains_new
+  (name, acme_account, dns_provider, last_cert, expires, enabled, disabled_reason, reenable_at, serial)
+  SELECT name, acme_account, dns_provider, last_cert, expires, enabled, disabled_reason, reenable_at, serial
+  FROM subdomains;
+
+DROP TABLE subdomains;
+ALTER TABLE subdomains_new RENAME TO subdomains;
+
+CREATE INDEX subdomains_expires ON subdomains(expires);
+
+CREATE TRIGGER subdomains_delete_certificates AFTER DELETE ON subdomains
+BEGIN
+  DELETE FROM certificates WHERE subdomain = OLD.name;
+  DELETE FROM subdomain_endpoints WHERE subdomain = OLD.name;
+END;
@@ -0,0 +1,2 @@
+ALTER TABLE subdomains ADD COLUMN renewal_failures bigint not null default 0;
+ALTER TABLE subdomains ADD COLUMN last_renewal_error text;
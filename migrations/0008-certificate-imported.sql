@@ -0,0 +1,7 @@
+ALTER TABLE certificates ADD COLUMN imported boolean not null default false;
+UPDATE certificates SET imported = true WHERE acme_account IS NULL;
+
+-- History rows of accounts that have already been deleted would otherwise be credited to
+-- whichever account reuses the id.
+UPDATE certificates SET acme_account = NULL
+  WHERE acme_account IS NOT NULL AND acme_account NOT IN (SELECT id FROM acme_accounts);
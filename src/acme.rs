@@ -1,6 +1,8 @@
+pub mod client;
+
 use std::{sync::Arc, time::Duration};
 
-use backoff::{future::retry, ExponentialBackoff, ExponentialBackoffBuilder};
+use backoff::{future::retry, ExponentialBackoffBuilder};
 use eyre::{eyre, Report, Result};
 use indicatif::ProgressBar;
 use instant_acme::{
@@ -15,7 +17,7 @@ use tracing::{event, Level};
 use crate::{cmd::State, dns::DnsProvider, Certificate};
 
 #[derive(
-    AsRefStr, Debug, Display, EnumIter, EnumString, EnumVariantNames, Serialize, Deserialize,
+    AsRefStr, Clone, Debug, Display, EnumIter, EnumString, EnumVariantNames, Serialize, Deserialize,
 )]
 pub enum AcmeProvider {
     LetsEncrypt,
@@ -43,7 +45,7 @@ pub async fn get_certificate(
     dns_provider: Box<dyn DnsProvider>,
     acme_account: instant_acme::Account,
     subdomain: String,
-) -> Result<(Certificate, i64)> {
+) -> Result<Certificate> {
     let progress = state.progress.add(
         ProgressBar::new_spinner()
             .with_prefix(subdomain.clone())
@@ -51,16 +53,17 @@ pub async fn get_certificate(
     );
 
     progress.enable_steady_tick(Duration::from_millis(125));
+    let settings = &state.settings;
 
     let identifiers = vec![instant_acme::Identifier::Dns(subdomain.clone())];
 
-    let (mut order, state) = acme_account
+    let (mut order, order_state) = acme_account
         .new_order(&NewOrder {
             identifiers: &identifiers,
         })
         .await?;
 
-    let authorizations = order.authorizations(&state.authorizations).await?;
+    let authorizations = order.authorizations(&order_state.authorizations).await?;
     let mut challenges = Vec::with_capacity(authorizations.len());
 
     // Get all the challenges first.
@@ -97,10 +100,11 @@ pub async fn get_certificate(
 
         progress.set_message("Waiting for DNS record to propagate");
 
+        let dns_check = &settings.dns_check;
         let boff = ExponentialBackoffBuilder::new()
-            .with_initial_interval(Duration::from_secs(2))
-            .with_max_interval(Duration::from_secs(60))
-            .with_max_elapsed_time(Some(Duration::from_secs(600)))
+            .with_initial_interval(Duration::from_secs(dns_check.initial_interval_secs))
+            .with_max_interval(Duration::from_secs(dns_check.max_interval_secs))
+            .with_max_elapsed_time(Some(Duration::from_secs(dns_check.timeout_secs)))
             .build();
 
         retry(boff, || async {
@@ -119,7 +123,7 @@ pub async fn get_certificate(
 
         progress
             .set_message("DNS record found. Waiting additional time to make sure of propagation");
-        tokio::time::sleep(Duration::from_secs(15)).await;
+        tokio::time::sleep(settings.propagation_delay()).await;
 
         order.set_challenge_ready(challenge_url).await?;
 
@@ -145,7 +149,19 @@ pub async fn get_certificate(
             }
         };
 
-        dns_provider.cleanup(&dns_record_id).await?;
+        // A leftover record doesn't stop the certificate from being issued, so only report it.
+        if let Err(e) = dns_provider.cleanup(&dns_record_id).await {
+            let e = e.wrap_err(format!("Failed to remove challenge record {key}"));
+            event!(Level::WARN, "{e:#}");
+            crate::notify::send(
+                &state,
+                crate::notify::Event::ChallengeCleanupFailed {
+                    subdomain: &subdomain,
+                    error: &e,
+                },
+            )
+            .await;
+        }
 
         match result {
             Ok(state) => match state.status {
@@ -168,23 +184,14 @@ pub async fn get_certificate(
     params.distinguished_name = DistinguishedName::new();
     let cert = rcgen::Certificate::from_params(params).unwrap();
     let csr = cert.serialize_request_der()?;
-    let cert_chain_pem = order.finalize(&csr, &state.finalize).await?;
-
-    let first_cert_container = x509_parser::pem::Pem::iter_from_buffer(cert_chain_pem.as_bytes())
-        .next()
-        .ok_or_else(|| eyre!("Certificate was empty"))??;
-    let first_cert = first_cert_container.parse_x509()?;
-    let expires = first_cert.tbs_certificate.validity.not_after.timestamp();
+    let cert_chain_pem = order.finalize(&csr, &order_state.finalize).await?;
 
     progress.finish_with_message("Certificate obtained");
 
-    Ok((
-        Certificate {
-            cert: cert_chain_pem,
-            key: cert.serialize_private_key_pem(),
-        },
-        expires,
-    ))
+    Ok(Certificate {
+        cert: cert_chain_pem,
+        key: cert.serialize_private_key_pem(),
+    })
 }
 
 async fn check_order_result(order: &mut Order) -> Result<Option<OrderState>> {
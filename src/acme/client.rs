@@ -0,0 +1,239 @@
+//! A minimal client for the ACME requests that instant-acme doesn't support, such as updating an
+//! account or revoking a certificate. Requests are signed as described in RFC 8555 section 6.2.
+
+use eyre::{eyre, Result};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum::{AsRefStr, EnumString, EnumVariantNames};
+
+use super::AcmeProvider;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    revoke_cert: String,
+}
+
+/// Why a certificate is being revoked. These are the reasons that Let's Encrypt accepts from
+/// subscribers, from RFC 5280 section 5.3.1.
+#[derive(AsRefStr, Clone, Copy, Debug, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "camelCase")]
+pub enum RevocationReason {
+    KeyCompromise,
+    Superseded,
+    CessationOfOperation,
+}
+
+impl RevocationReason {
+    fn code(&self) -> u8 {
+        match self {
+            RevocationReason::KeyCompromise => 1,
+            RevocationReason::Superseded => 4,
+            RevocationReason::CessationOfOperation => 5,
+        }
+    }
+}
+
+/// The parts of [instant_acme::AccountCredentials] that we need. That type doesn't expose its
+/// fields, but they can be read from its serialized form.
+#[derive(Deserialize)]
+struct StoredAccountCredentials {
+    id: String,
+    key_pkcs8: String,
+}
+
+/// A key to sign requests with.
+pub struct Signer {
+    key: EcdsaKeyPair,
+    /// The account URL, when signing as an account.
+    account_url: Option<String>,
+}
+
+impl Signer {
+    /// Sign requests as the account with the given serialized credentials.
+    pub fn from_account_creds(creds: &str) -> Result<Signer> {
+        let creds: StoredAccountCredentials = serde_json::from_str(creds)?;
+        let pkcs8 = base64::decode_config(creds.key_pkcs8, base64::URL_SAFE_NO_PAD)?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8)
+            .map_err(|e| eyre!("Failed to read account key: {e}"))?;
+
+        Ok(Signer {
+            key,
+            account_url: Some(creds.id),
+        })
+    }
+
+    /// Sign requests with a certificate's private key, in PKCS#8 PEM format. This can only be used
+    /// to revoke that certificate.
+    pub fn from_private_key_pem(pem: &str) -> Result<Signer> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes())
+            .map_err(|e| eyre!("Failed to read private key: {e}"))?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pem.contents)
+            .map_err(|_| {
+                eyre!("Only ECDSA P-256 certificate keys in PKCS#8 format are supported")
+            })?;
+
+        Ok(Signer {
+            key,
+            account_url: None,
+        })
+    }
+
+    fn jwk(&self) -> serde_json::Value {
+        // The public key is an uncompressed point: 0x04 followed by the X and Y coordinates.
+        let (x, y) = self.key.public_key().as_ref()[1..].split_at(32);
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": base64::encode_config(x, base64::URL_SAFE_NO_PAD),
+            "y": base64::encode_config(y, base64::URL_SAFE_NO_PAD),
+        })
+    }
+}
+
+pub struct AcmeClient {
+    client: Client,
+    directory: Directory,
+    rng: SystemRandom,
+}
+
+impl AcmeClient {
+    pub async fn new(provider: &AcmeProvider) -> Result<AcmeClient> {
+        let client = Client::builder().user_agent(crate::USER_AGENT).build()?;
+        let directory = client
+            .get(provider.url())
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Directory>()
+            .await?;
+
+        Ok(AcmeClient {
+            client,
+            directory,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    async fn new_nonce(&self) -> Result<String> {
+        let response = self
+            .client
+            .head(&self.directory.new_nonce)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        response
+            .headers()
+            .get("Replay-Nonce")
+            .and_then(|nonce| nonce.to_str().ok())
+            .map(|nonce| nonce.to_string())
+            .ok_or_else(|| eyre!("ACME server did not return a nonce"))
+    }
+
+    fn sign(
+        &self,
+        signer: &Signer,
+        nonce: &str,
+        url: &str,
+        payload: &impl Serialize,
+    ) -> Result<serde_json::Value> {
+        let mut protected = json!({
+            "alg": "ES256",
+            "nonce": nonce,
+            "url": url,
+        });
+
+        match &signer.account_url {
+            Some(account_url) => protected["kid"] = json!(account_url),
+            None => protected["jwk"] = signer.jwk(),
+        };
+
+        let protected =
+            base64::encode_config(serde_json::to_vec(&protected)?, base64::URL_SAFE_NO_PAD);
+        let payload = base64::encode_config(serde_json::to_vec(payload)?, base64::URL_SAFE_NO_PAD);
+
+        let signature = signer
+            .key
+            .sign(&self.rng, format!("{protected}.{payload}").as_bytes())
+            .map_err(|e| eyre!("Failed to sign request: {e}"))?;
+
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD),
+        }))
+    }
+
+    /// Send a signed request, and return an error if it fails.
+    async fn post(&self, signer: &Signer, url: &str, payload: &impl Serialize) -> Result<()> {
+        // A nonce can be rejected if it went stale, so retry once with a new one.
+        let mut retried = false;
+        loop {
+            let nonce = self.new_nonce().await?;
+            let body = self.sign(signer, &nonce, url, payload)?;
+
+            let response = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/jose+json")
+                .body(serde_json::to_vec(&body)?)
+                .send()
+                .await?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+
+            let problem = response.json::<instant_acme::Problem>().await;
+            match problem {
+                Ok(problem)
+                    if !retried
+                        && status == StatusCode::BAD_REQUEST
+                        && problem.r#type == "urn:ietf:params:acme:error:badNonce" =>
+                {
+                    retried = true;
+                }
+                Ok(problem) => return Err(eyre!("ACME request failed: {}", problem.detail)),
+                Err(_) => return Err(eyre!("ACME request failed: {status}")),
+            }
+        }
+    }
+
+    /// Replace the contact addresses on an account.
+    pub async fn update_contacts(&self, signer: &Signer, contacts: &[String]) -> Result<()> {
+        let account_url = signer
+            .account_url
+            .as_deref()
+            .ok_or_else(|| eyre!("Contacts can only be updated when signing as an account"))?;
+
+        self.post(signer, account_url, &json!({ "contact": contacts }))
+            .await
+    }
+
+    /// Revoke a certificate, given in DER format. The signer can be the account that issued the
+    /// certificate, or the certificate's own key.
+    pub async fn revoke_certificate(
+        &self,
+        signer: &Signer,
+        cert_der: &[u8],
+        reason: Option<RevocationReason>,
+    ) -> Result<()> {
+        let mut payload = json!({
+            "certificate": base64::encode_config(cert_der, base64::URL_SAFE_NO_PAD),
+        });
+        if let Some(reason) = reason {
+            payload["reason"] = json!(reason.code());
+        }
+
+        self.post(signer, &self.directory.revoke_cert, &payload)
+            .await
+    }
+}
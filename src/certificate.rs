@@ -0,0 +1,176 @@
+use eyre::{eyre, Result};
+use ring::signature::{
+    EcdsaKeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING,
+    ECDSA_P384_SHA384_FIXED_SIGNING,
+};
+use serde::{Deserialize, Serialize};
+use x509_parser::{extensions::GeneralName, public_key::PublicKey};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Certificate {
+    pub cert: String,
+    pub key: String,
+}
+
+impl Certificate {
+    pub fn get_leaf_certificate(&self) -> &str {
+        self.cert
+            .split_inclusive("-----END CERTIFICATE-----\n")
+            .next()
+            .unwrap()
+    }
+
+    pub fn get_certificate_chain(&self) -> &str {
+        self.cert
+            .split_once("-----END CERTIFICATE-----\n")
+            .map(|(_, chain)| chain)
+            .unwrap_or("")
+    }
+
+    /// Return the leaf certificate in DER format.
+    pub fn leaf_der(&self) -> Result<Vec<u8>> {
+        let pem = x509_parser::pem::Pem::iter_from_buffer(self.cert.as_bytes())
+            .next()
+            .ok_or_else(|| eyre!("Certificate was empty"))??;
+        Ok(pem.contents)
+    }
+
+    /// Read the details of the leaf certificate.
+    pub fn info(&self) -> Result<CertificateInfo> {
+        CertificateInfo::from_der(&self.leaf_der()?)
+    }
+
+    /// Return the DNS names that the leaf certificate is valid for. These come from the subject
+    /// alternative names, or from the common name if the certificate has none.
+    pub fn dns_names(&self) -> Result<Vec<String>> {
+        let der = self.leaf_der()?;
+        let (_, cert) = x509_parser::parse_x509_certificate(&der)?;
+
+        if let Some(san) = cert.subject_alternative_name()? {
+            return Ok(san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect());
+        }
+
+        Ok(cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok().map(|s| s.to_string()))
+            .collect())
+    }
+
+    /// Check whether the leaf certificate is valid for a name, including through a wildcard.
+    pub fn covers(&self, name: &str) -> Result<bool> {
+        let name = name.to_ascii_lowercase();
+        let covered = self.dns_names()?.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.strip_prefix("*.") {
+                Some(parent) => name
+                    .split_once('.')
+                    .map(|(_, rest)| rest == parent)
+                    .unwrap_or(false),
+                None => pattern == name,
+            }
+        });
+
+        Ok(covered)
+    }
+
+    /// Check that the private key belongs to the leaf certificate.
+    pub fn check_key(&self) -> Result<()> {
+        let der = self.leaf_der()?;
+        let (_, cert) = x509_parser::parse_x509_certificate(&der)?;
+
+        if public_key_from_private(&self.key)? != *cert.public_key().subject_public_key.data {
+            return Err(eyre!("The private key does not match the certificate"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the public key for a PEM private key, in the same form that certificates hold it.
+fn public_key_from_private(pem: &str) -> Result<Vec<u8>> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes())
+        .map_err(|e| eyre!("Failed to read private key: {e}"))?;
+    let der = &pem.contents;
+
+    let public_key = if let Ok(key) =
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, der)
+    {
+        key.public_key().as_ref().to_vec()
+    } else if let Ok(key) = EcdsaKeyPair::from_pkcs8(&ECDSA_P384_SHA384_FIXED_SIGNING, der) {
+        key.public_key().as_ref().to_vec()
+    } else if let Ok(key) = RsaKeyPair::from_pkcs8(der).or_else(|_| RsaKeyPair::from_der(der)) {
+        key.public_key().as_ref().to_vec()
+    } else {
+        return Err(eyre!(
+            "Unsupported private key. Keys must be ECDSA P-256, ECDSA P-384, or RSA, in PKCS#8 format. An EC PRIVATE KEY can be converted with `openssl pkcs8 -topk8 -nocrypt`."
+        ));
+    };
+
+    Ok(public_key)
+}
+
+/// The details of a certificate that are kept in its history.
+#[derive(Debug, Clone)]
+pub struct CertificateInfo {
+    pub serial: String,
+    pub issuer: String,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub key_type: String,
+}
+
+impl CertificateInfo {
+    /// Read the details of a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<CertificateInfo> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)?;
+
+        let public_key = cert.public_key();
+        let key_type = match public_key.parsed() {
+            Ok(key @ PublicKey::EC(_)) => format!("ECDSA P-{}", key.key_size()),
+            Ok(key @ PublicKey::RSA(_)) => format!("RSA {}", key.key_size()),
+            _ => "unknown".to_string(),
+        };
+
+        Ok(CertificateInfo {
+            serial: format_serial(cert.raw_serial()),
+            issuer: cert.issuer().to_string(),
+            not_before: cert.validity().not_before.timestamp(),
+            not_after: cert.validity().not_after.timestamp(),
+            key_type,
+        })
+    }
+}
+
+/// Format a serial number as uppercase hex, the same way OpenSSL shows it.
+fn format_serial(raw: &[u8]) -> String {
+    let serial = raw
+        .iter()
+        .skip_while(|b| **b == 0)
+        .map(|b| format!("{b:02X}"))
+        .collect::<String>();
+
+    if serial.is_empty() {
+        "0".to_string()
+    } else {
+        serial
+    }
+}
+
+/// Convert a serial number given on the command line, which may contain colons or leading
+/// zeroes, into the format used by [CertificateInfo].
+pub fn normalize_serial(serial: &str) -> String {
+    let serial = serial.replace(':', "").to_uppercase();
+    match serial.trim_start_matches('0') {
+        "" => "0".to_string(),
+        serial => serial.to_string(),
+    }
+}
@@ -1,32 +1,213 @@
-use eyre::Result;
-use std::sync::Arc;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use eyre::{eyre, Result};
+use std::{io::IsTerminal, str::FromStr, sync::Arc};
+use strum::VariantNames;
+use time::{macros::format_description, OffsetDateTime};
 
-use crate::{cmd::State, db::PoolExtInteract};
+use crate::{
+    cmd::State,
+    db::{DbObject, PoolExtInteract},
+};
+
+/// Returns true if stdin is a terminal, and so we can prompt for values that were not passed on
+/// the command line.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal()
+}
+
+/// Use the value from the command line if present. Otherwise prompt for it when running
+/// interactively, or fail with an error naming the missing option.
+pub fn value_or_prompt<T>(
+    value: Option<T>,
+    flag: &str,
+    prompt: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    match value {
+        Some(value) => Ok(value),
+        None if is_interactive() => prompt(),
+        None => Err(missing_option(flag)),
+    }
+}
+
+fn missing_option(flag: &str) -> eyre::Report {
+    eyre!("Missing required option {flag}, and stdin is not a terminal so it can't be prompted for")
+}
+
+/// A clap value parser that accepts any variant name of a provider enum.
+pub fn variant_parser<E>() -> impl TypedValueParser<Value = E>
+where
+    E: FromStr + VariantNames + Clone + Send + Sync + 'static,
+{
+    PossibleValuesParser::new(E::VARIANTS).map(|s| {
+        E::from_str(&s)
+            .map_err(|_| ())
+            .expect("possible values are all valid variants")
+    })
+}
+
+async fn name_exists(state: &Arc<State>, sql_table: &str, name: String) -> Result<bool> {
+    let statement = format!("SELECT name FROM {sql_table} WHERE name = ?");
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(&statement)?;
+            let exists = stmt.exists([&name])?;
+            Ok::<_, eyre::Report>(exists)
+        })
+        .await
+}
+
+pub async fn get_unique_name(
+    state: &Arc<State>,
+    name: Option<String>,
+    prompt: &str,
+    sql_table: &str,
+) -> Result<String> {
+    if let Some(name) = name {
+        if name_exists(state, sql_table, name.clone()).await? {
+            return Err(eyre!("The name {name} is already in use"));
+        }
+
+        return Ok(name);
+    }
+
+    if !is_interactive() {
+        return Err(missing_option("--name"));
+    }
 
-pub async fn get_unique_name(state: &Arc<State>, prompt: &str, sql_table: &str) -> Result<String> {
     loop {
         let potential_name: String = dialoguer::Input::new()
             .with_prompt(prompt)
             .interact_text()?;
 
         // Better to use dialoguer's validate functionality but this is easier for MVP
-        let exists = {
-            let potential_name = potential_name.clone();
-            let statement = format!("SELECT name FROM {sql_table} WHERE name = ?");
-            state
-                .pool
-                .interact(move |conn| {
-                    let mut stmt = conn.prepare_cached(&statement)?;
-                    let exists = stmt.exists([&potential_name])?;
-                    Ok::<_, eyre::Report>(exists)
-                })
-                .await?
-        };
-
-        if exists {
+        if name_exists(state, sql_table, potential_name.clone()).await? {
             println!("This name is already in use. Please try again.")
         } else {
             return Ok(potential_name);
         }
     }
 }
+
+/// Find an object by the name given on the command line, or let the user pick one from a list.
+/// Returns the index of the selected object.
+pub fn select_object(
+    objects: &[DbObject],
+    name: Option<&str>,
+    flag: &str,
+    prompt: &str,
+    default: usize,
+) -> Result<usize> {
+    if let Some(name) = name {
+        return objects
+            .iter()
+            .position(|o| o.name == name)
+            .ok_or_else(|| eyre!("No object named {name} was found for {flag}"));
+    }
+
+    value_or_prompt(None, flag, || {
+        let idx = dialoguer::Select::new()
+            .with_prompt(prompt)
+            .items(&objects.iter().map(|o| &o.name).collect::<Vec<_>>())
+            .default(default)
+            .interact()?;
+        Ok(idx)
+    })
+}
+
+/// Like [select_object], but for picking one or more objects. `defaults` marks the objects that
+/// start out selected in the prompt. Returns the indexes of the selected objects.
+pub fn select_objects(
+    objects: &[DbObject],
+    names: &[String],
+    flag: &str,
+    prompt: &str,
+    defaults: &[bool],
+) -> Result<Vec<usize>> {
+    if !names.is_empty() {
+        let mut idxs = Vec::with_capacity(names.len());
+        for name in names {
+            let idx = select_object(objects, Some(name), flag, prompt, 0)?;
+            if !idxs.contains(&idx) {
+                idxs.push(idx);
+            }
+        }
+        return Ok(idxs);
+    }
+
+    value_or_prompt(None, flag, || loop {
+        let idxs = dialoguer::MultiSelect::new()
+            .with_prompt(format!("{prompt} (space to select, enter to confirm)"))
+            .items(&objects.iter().map(|o| &o.name).collect::<Vec<_>>())
+            .defaults(defaults)
+            .interact()?;
+
+        if idxs.is_empty() {
+            println!("Please select at least one.");
+        } else {
+            return Ok(idxs);
+        }
+    })
+}
+
+/// Print rows of values with each column aligned.
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths = headers.iter().map(|h| h.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(|c| c.as_str()).collect()));
+    }
+}
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    let formatter = format_description!("[year]-[month]-[day] [hour]:[minute] UTC");
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|t| t.format(&formatter).ok())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Describe a certificate's expiration time, including how far away it is.
+pub fn describe_expiration(expires: Option<i64>) -> String {
+    match expires {
+        None | Some(0) => "unknown".to_string(),
+        Some(expires) => {
+            let days = (expires - OffsetDateTime::now_utc().unix_timestamp()) / 86400;
+            let relative = if days < 0 {
+                format!("expired {} days ago", -days)
+            } else {
+                format!("in {days} days")
+            };
+
+            format!("{} ({relative})", format_timestamp(expires))
+        }
+    }
+}
+
+/// Parse a date from the command line, given either as `YYYY-MM-DD` (midnight UTC) or as an RFC
+/// 3339 timestamp.
+pub fn parse_date(value: &str) -> Result<OffsetDateTime, String> {
+    if let Ok(date) = time::Date::parse(value, format_description!("[year]-[month]-[day]")) {
+        return Ok(date.midnight().assume_utc());
+    }
+
+    OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+        .map_err(|_| format!("Invalid date {value}. Use YYYY-MM-DD or an RFC 3339 timestamp"))
+}
@@ -1,19 +1,36 @@
 pub mod acme_account;
+pub mod config;
+pub mod db;
 pub mod dns;
 pub mod endpoint;
 pub mod init;
+pub mod object;
 pub mod subdomain;
 
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use clap::{Parser, Subcommand};
 use deadpool_sqlite::Pool;
 use eyre::Result;
 use indicatif::MultiProgress;
 
+use crate::{crypto::Secrets, settings::Settings};
+
 #[derive(Parser, Debug)]
 #[command(about)]
 struct Args {
+    /// Use the database at this path, instead of the default location
+    #[clap(long, global = true, env = "RSR_DB")]
+    db: Option<PathBuf>,
+
+    /// Use a named profile, which has its own database
+    #[clap(long, global = true, env = "RSR_PROFILE")]
+    profile: Option<String>,
+
+    /// Read settings from this file, instead of config.toml in the config directory
+    #[clap(long, global = true, env = "RSR_CONFIG")]
+    config: Option<PathBuf>,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -30,15 +47,24 @@ enum Commands {
     Endpoint(endpoint::EndpointArgs),
     /// Set up a new account, DNS provider, and host in one command
     Init(init::InitArgs),
+    /// Manage the database
+    Db(db::DbArgs),
+    /// Show the settings in use
+    Config(config::ConfigArgs),
 }
 
 pub struct State {
+    pub db_path: PathBuf,
+    pub config_path: PathBuf,
+    pub profile: Option<String>,
+    pub settings: Settings,
     pub pool: Pool,
     pub progress: MultiProgress,
+    pub secrets: Secrets,
 }
 
 impl State {
-    pub fn hide_progress(&self) -> ProgressHider {
+    pub fn hide_progress(&self) -> ProgressHider<'_> {
         ProgressHider::new(&self.progress)
     }
 }
@@ -61,12 +87,26 @@ impl<'a> Drop for ProgressHider<'a> {
     }
 }
 
-pub async fn run(pool: Pool) -> Result<()> {
+pub async fn run() -> Result<()> {
     let args = Args::parse();
 
+    let config_path = args
+        .config
+        .unwrap_or_else(|| crate::db::config_dir().join("config.toml"));
+    let settings = Settings::load(&config_path, args.profile.as_deref())?;
+
+    let db_path = crate::db::db_path(args.db, args.profile.as_deref())?;
+    let pool = crate::db::create_db(&db_path).await?;
+
+    let secrets = Secrets::load(&pool).await?;
     let state = Arc::new(State {
+        db_path,
+        config_path,
+        profile: args.profile,
+        settings,
         pool,
         progress: MultiProgress::new(),
+        secrets,
     });
 
     match args.command {
@@ -75,6 +115,8 @@ pub async fn run(pool: Pool) -> Result<()> {
         Commands::Account(args) => acme_account::run(state, args).await?,
         Commands::Dns(args) => dns::run(state, args).await?,
         Commands::Endpoint(args) => endpoint::run(state, args).await?,
+        Commands::Db(args) => db::run(state, args).await?,
+        Commands::Config(args) => config::run(state, args).await?,
     };
 
     Ok(())
@@ -1,14 +1,24 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use clap::{Args, Subcommand};
-use eyre::Result;
+use eyre::{eyre, Result};
 use indicatif::ProgressBar;
 use rusqlite::params;
 use strum::IntoEnumIterator;
 
-use crate::{acme::AcmeProvider, db::PoolExtInteract};
+use crate::{
+    acme::{
+        client::{AcmeClient, Signer},
+        AcmeProvider,
+    },
+    cli::{is_interactive, value_or_prompt, variant_parser},
+    db::{ObjectTable, PoolExtInteract},
+};
 
-use super::State;
+use super::{
+    object::{self, DeleteArgs, ShowArgs},
+    State,
+};
 
 #[derive(Debug, Args)]
 pub struct AccountArgs {
@@ -19,28 +29,56 @@ pub struct AccountArgs {
 #[derive(Debug, Subcommand)]
 enum Commands {
     #[clap(about = "Add a new account")]
-    New,
+    New(NewAccountArgs),
+    #[clap(about = "List accounts")]
+    List,
+    #[clap(about = "Show an account and the subdomains that use it")]
+    Show(ShowArgs),
+    #[clap(about = "Delete an account")]
+    Delete(DeleteArgs),
+    #[clap(about = "Change an account's label or contact emails")]
+    Edit(EditAccountArgs),
 }
 
-pub async fn new_account(state: Arc<State>) -> Result<()> {
+#[derive(Debug, Default, Args)]
+pub struct NewAccountArgs {
+    /// A label for this account
+    #[clap(long)]
+    name: Option<String>,
+
+    /// The ACME provider to use
+    #[clap(long, value_parser = variant_parser::<AcmeProvider>())]
+    provider: Option<AcmeProvider>,
+
+    /// The email address to put on the account
+    #[clap(long)]
+    email: Option<String>,
+}
+
+pub async fn new_account(state: Arc<State>, args: NewAccountArgs) -> Result<()> {
     let name = crate::cli::get_unique_name(
         &state,
+        args.name,
         "Give this ACME (Let's Encrypt) account a label",
         "acme_accounts",
     )
     .await?;
 
-    let providers = AcmeProvider::iter().map(|p| p.label()).collect::<Vec<_>>();
-    let provider_select = dialoguer::Select::new()
-        .with_prompt("Which ACME provider do you want to use?")
-        .items(&providers)
-        .default(0)
-        .interact()?;
-    let provider = AcmeProvider::iter().nth(provider_select).unwrap();
-
-    let email: String = dialoguer::Input::new()
-        .with_prompt("What email address should be on the account?")
-        .interact_text()?;
+    let provider = value_or_prompt(args.provider, "--provider", || {
+        let providers = AcmeProvider::iter().map(|p| p.label()).collect::<Vec<_>>();
+        let provider_select = dialoguer::Select::new()
+            .with_prompt("Which ACME provider do you want to use?")
+            .items(&providers)
+            .default(0)
+            .interact()?;
+        Ok(AcmeProvider::iter().nth(provider_select).unwrap())
+    })?;
+
+    let email = value_or_prompt(args.email, "--email", || {
+        Ok(dialoguer::Input::new()
+            .with_prompt("What email address should be on the account?")
+            .interact_text()?)
+    })?;
 
     let progress = state
         .progress
@@ -58,7 +96,9 @@ pub async fn new_account(state: Arc<State>) -> Result<()> {
     )
     .await?;
 
-    let creds = serde_json::to_string(&account.credentials())?;
+    let creds = state
+        .secrets
+        .seal(serde_json::to_string(&account.credentials())?)?;
 
     state
         .pool
@@ -78,9 +118,77 @@ pub async fn new_account(state: Arc<State>) -> Result<()> {
     Ok(())
 }
 
+#[derive(Debug, Args)]
+pub struct EditAccountArgs {
+    /// The account to edit
+    name: String,
+
+    /// A new label for the account
+    #[clap(long)]
+    label: Option<String>,
+
+    /// Replace the account's contact emails with this address. This can be given multiple times.
+    #[clap(long = "email")]
+    emails: Vec<String>,
+}
+
+pub async fn edit_account(state: Arc<State>, args: EditAccountArgs) -> Result<()> {
+    let table = ObjectTable::AcmeAccounts;
+    let object = crate::db::get_object(&state, table, args.name).await?;
+
+    let (label, emails) = if args.label.is_some() || !args.emails.is_empty() {
+        (args.label, args.emails)
+    } else if is_interactive() {
+        let label: String = dialoguer::Input::new()
+            .with_prompt("Label")
+            .default(object.name.clone())
+            .interact_text()?;
+
+        let emails: String = dialoguer::Input::new()
+            .with_prompt(
+                "New contact emails, separated by commas (or blank to keep the current ones)",
+            )
+            .allow_empty(true)
+            .interact_text()?;
+
+        let emails = emails
+            .split(',')
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty())
+            .collect::<Vec<_>>();
+
+        (Some(label), emails)
+    } else {
+        return Err(eyre!("Nothing to change. Pass --label or --email"));
+    };
+
+    if !emails.is_empty() {
+        let progress = state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Updating account contacts..."));
+
+        let provider = AcmeProvider::from_str(&object.provider)?;
+        let client = AcmeClient::new(&provider).await?;
+        let signer = Signer::from_account_creds(&state.secrets.open(object.creds.clone())?)?;
+        let contacts = emails
+            .iter()
+            .map(|email| format!("mailto:{email}"))
+            .collect::<Vec<_>>();
+        client.update_contacts(&signer, &contacts).await?;
+
+        progress.finish_and_clear();
+    }
+
+    object::save_edit(&state, table, &object, label, None).await
+}
+
 pub async fn run(state: Arc<State>, args: AccountArgs) -> Result<()> {
     match args.command {
-        Commands::New => new_account(state).await?,
+        Commands::New(args) => new_account(state, args).await?,
+        Commands::List => object::list(state, ObjectTable::AcmeAccounts).await?,
+        Commands::Show(args) => object::show(state, ObjectTable::AcmeAccounts, args).await?,
+        Commands::Delete(args) => object::delete(state, ObjectTable::AcmeAccounts, args).await?,
+        Commands::Edit(args) => edit_account(state, args).await?,
     };
 
     Ok(())
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use clap::{Args, Subcommand};
+use eyre::{eyre, Result};
+
+use crate::{cli::print_table, notify::Event, settings::SettingsLayers};
+
+use super::State;
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Show the value of each setting and where it came from
+    Show,
+    /// Print the location of the config file in use
+    Path,
+    /// Send a test notification through each configured channel
+    TestNotify,
+}
+
+fn show(state: &State) -> Result<()> {
+    let layers = SettingsLayers::load(&state.config_path, state.profile.as_deref())?;
+    let rows = layers
+        .describe()?
+        .into_iter()
+        .map(|(key, value, source)| vec![key.to_string(), value.unwrap_or_default(), source])
+        .collect::<Vec<_>>();
+    print_table(&["Setting", "Value", "Source"], &rows);
+
+    Ok(())
+}
+
+pub async fn run(state: Arc<State>, args: ConfigArgs) -> Result<()> {
+    match args.command {
+        Commands::Show => show(&state)?,
+        Commands::Path => println!("{}", state.config_path.display()),
+        Commands::TestNotify => {
+            if !crate::notify::send(&state, Event::Test).await {
+                return Err(eyre!("No notifications are configured"));
+            }
+        }
+    };
+
+    Ok(())
+}
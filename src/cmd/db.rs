@@ -0,0 +1,168 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::{ArgGroup, Args, Subcommand};
+use eyre::{eyre, Result};
+use rusqlite::{params, Transaction};
+
+use crate::{
+    cli::value_or_prompt,
+    crypto::{
+        generate_salt, is_encrypted, read_key_file, write_key_file, Cipher, EncryptionConfig,
+        KeySource,
+    },
+    db::PoolExtTransaction,
+};
+
+use super::State;
+
+#[derive(Args, Debug)]
+pub struct DbArgs {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Encrypt the stored credentials and certificate keys with a new key
+    ///
+    /// This also encrypts databases that were created before encryption was supported. The
+    /// current key, if any, is read from the usual place.
+    Rekey(RekeyArgs),
+    /// Print the location of the database in use
+    Path,
+}
+
+#[derive(Debug, Args)]
+#[clap(group(ArgGroup::new("new_key").required(true).args(["passphrase", "key_file", "decrypt"])))]
+pub struct RekeyArgs {
+    /// Derive the new key from a passphrase, read from $RSR_NEW_PASSPHRASE or prompted for.
+    /// The passphrase must then be given in $RSR_PASSPHRASE or at a prompt when the database is
+    /// used.
+    #[clap(long)]
+    passphrase: bool,
+
+    /// Use the key in this file, or generate a new key and write it here if the file doesn't
+    /// exist. The key must then be given in $RSR_KEY or $RSR_KEY_FILE when the database is used.
+    #[clap(long)]
+    key_file: Option<PathBuf>,
+
+    /// Remove the encryption and store everything as plain text
+    #[clap(long)]
+    decrypt: bool,
+}
+
+/// Decrypt every non-empty value in a column with the old key, and encrypt it with the new one.
+fn reencrypt_column(
+    tx: &Transaction,
+    table: &str,
+    column: &str,
+    old: Option<&Cipher>,
+    new: Option<&Cipher>,
+) -> Result<()> {
+    let values = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT rowid, {column} FROM {table} WHERE {column} IS NOT NULL AND {column} != ''"
+        ))?;
+        let values = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        values
+    };
+
+    let mut update = tx.prepare(&format!("UPDATE {table} SET {column}=? WHERE rowid=?"))?;
+    for (rowid, value) in values {
+        let plaintext = if is_encrypted(&value) {
+            old.ok_or_else(|| eyre!("Found an encrypted value in {table}, but the database has no encryption key configured"))?
+                .decrypt(&value)?
+        } else {
+            value
+        };
+
+        let value = match new {
+            Some(cipher) => cipher.encrypt(&plaintext)?,
+            None => plaintext,
+        };
+
+        update.execute(params![value, rowid])?;
+    }
+
+    Ok(())
+}
+
+async fn rekey(state: Arc<State>, args: RekeyArgs) -> Result<()> {
+    let old_cipher = state.secrets.cipher()?.cloned();
+
+    let (new_cipher, new_config) = if args.passphrase {
+        let passphrase = match std::env::var("RSR_NEW_PASSPHRASE") {
+            Ok(passphrase) => passphrase,
+            Err(_) => value_or_prompt(None, "$RSR_NEW_PASSPHRASE", || {
+                Ok(dialoguer::Password::new()
+                    .with_prompt("New passphrase")
+                    .with_confirmation("Confirm the passphrase", "The passphrases don't match")
+                    .interact()?)
+            })?,
+        };
+
+        let salt = generate_salt()?;
+        let cipher = Cipher::from_passphrase(&passphrase, &base64::decode(&salt)?)?;
+        let config = EncryptionConfig::new(KeySource::Passphrase, Some(salt), &cipher)?;
+        (Some(cipher), Some(config))
+    } else if let Some(path) = args.key_file {
+        let cipher = if path.exists() {
+            read_key_file(&path)?
+        } else {
+            let cipher = Cipher::generate()?;
+            write_key_file(&path, &cipher)?;
+            println!("Wrote a new key to {}", path.display());
+            cipher
+        };
+
+        let config = EncryptionConfig::new(KeySource::Key, None, &cipher)?;
+        (Some(cipher), Some(config))
+    } else {
+        (None, None)
+    };
+
+    let encrypting = new_config.is_some();
+    state
+        .pool
+        .transaction(move |tx| {
+            let old = old_cipher.as_ref();
+            let new = new_cipher.as_ref();
+            reencrypt_column(tx, "acme_accounts", "creds", old, new)?;
+            reencrypt_column(tx, "dns_providers", "creds", old, new)?;
+            reencrypt_column(tx, "endpoints", "creds", old, new)?;
+            reencrypt_column(tx, "subdomains", "last_cert", old, new)?;
+            reencrypt_column(tx, "certificates", "cert", old, new)?;
+
+            tx.execute("DELETE FROM encryption", [])?;
+            if let Some(config) = new_config {
+                tx.execute(
+                    "INSERT INTO encryption (id, key_source, salt, key_check) VALUES (1, ?, ?, ?)",
+                    params![config.key_source.as_ref(), config.salt, config.key_check],
+                )?;
+            }
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await?;
+
+    if encrypting {
+        println!("The database is now encrypted with the new key");
+    } else {
+        println!("The database is no longer encrypted");
+    }
+
+    Ok(())
+}
+
+pub async fn run(state: Arc<State>, args: DbArgs) -> Result<()> {
+    match args.command {
+        Commands::Rekey(args) => rekey(state, args).await?,
+        Commands::Path => println!("{}", state.db_path.display()),
+    };
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use clap::{Args, Subcommand};
 use eyre::Result;
@@ -6,12 +6,15 @@ use rusqlite::params;
 use strum::{IntoEnumIterator, VariantNames};
 
 use crate::{
-    cli::get_unique_name,
-    db::PoolExtInteract,
+    cli::{get_unique_name, value_or_prompt, variant_parser},
+    db::{ObjectTable, PoolExtInteract},
     dns::{vercel::VercelDnsCreds, DnsProviderType},
 };
 
-use super::State;
+use super::{
+    object::{self, DeleteArgs, EditArgs, ShowArgs},
+    State,
+};
 
 #[derive(Args, Debug)]
 pub struct DnsArgs {
@@ -22,24 +25,87 @@ pub struct DnsArgs {
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// Add a new DNS provider
-    New,
+    New(NewDnsProviderArgs),
+    /// List DNS providers
+    List,
+    /// Show a DNS provider and the subdomains that use it
+    Show(ShowArgs),
+    /// Delete a DNS provider
+    Delete(DeleteArgs),
+    /// Change a DNS provider's label or API token
+    Edit(EditArgs),
 }
 
-pub async fn new_dns_provider(state: Arc<State>) -> Result<String> {
-    let name = get_unique_name(&state, "Give this DNS provider a label", "dns_providers").await?;
+#[derive(Args, Debug, Default)]
+pub struct NewDnsProviderArgs {
+    /// A label for this DNS provider
+    #[clap(long)]
+    name: Option<String>,
+
+    /// The DNS provider type
+    #[clap(long, value_parser = variant_parser::<DnsProviderType>())]
+    provider: Option<DnsProviderType>,
 
-    let selection = dialoguer::Select::new()
-        .with_prompt("What DNS provider do you want to use?")
-        .items(DnsProviderType::VARIANTS)
-        .interact()?;
-    let dns_provider_type = DnsProviderType::iter().nth(selection).unwrap();
+    /// The API token for the provider. This can also refer to a secret stored elsewhere, as
+    /// env:NAME, file:/path, or cmd:<command>
+    #[clap(long)]
+    token: Option<String>,
+
+    /// Read the API token from the provider's environment variable when it is used, instead of
+    /// storing it
+    #[clap(long, conflicts_with = "token")]
+    token_from_env: bool,
+}
 
+/// Build the credentials to store from the command-line options, prompting for the token if
+/// needed.
+fn creds_from_args(
+    dns_provider_type: DnsProviderType,
+    token: Option<String>,
+    token_from_env: bool,
+) -> Result<String> {
     let creds_str = match dns_provider_type {
-        DnsProviderType::Vercel => VercelDnsCreds::from_console()?
-            .map(|creds| serde_json::to_string(&creds))
-            .transpose()?,
+        DnsProviderType::Vercel => {
+            let creds = if token_from_env {
+                None
+            } else {
+                value_or_prompt(
+                    token.map(|token| Some(VercelDnsCreds::new(token))),
+                    "--token or --token-from-env",
+                    VercelDnsCreds::from_console,
+                )?
+            };
+
+            creds
+                .map(|creds| serde_json::to_string(&creds))
+                .transpose()?
+                .unwrap_or_default()
+        }
     };
 
+    Ok(creds_str)
+}
+
+pub async fn new_dns_provider(state: Arc<State>, args: NewDnsProviderArgs) -> Result<String> {
+    let name = get_unique_name(
+        &state,
+        args.name,
+        "Give this DNS provider a label",
+        "dns_providers",
+    )
+    .await?;
+
+    let dns_provider_type = value_or_prompt(args.provider, "--provider", || {
+        let selection = dialoguer::Select::new()
+            .with_prompt("What DNS provider do you want to use?")
+            .items(DnsProviderType::VARIANTS)
+            .interact()?;
+        Ok(DnsProviderType::iter().nth(selection).unwrap())
+    })?;
+
+    let creds_str = creds_from_args(dns_provider_type.clone(), args.token, args.token_from_env)?;
+    let creds_str = state.secrets.seal(creds_str)?;
+
     {
         let name = name.clone();
         state
@@ -57,9 +123,32 @@ pub async fn new_dns_provider(state: Arc<State>) -> Result<String> {
     Ok(name)
 }
 
+async fn edit_dns_provider(state: Arc<State>, args: EditArgs) -> Result<()> {
+    let table = ObjectTable::DnsProviders;
+    let object = crate::db::get_object(&state, table, args.name.clone()).await?;
+    let dns_provider_type = DnsProviderType::from_str(&object.provider)?;
+
+    let edit = object::resolve_edit(&object, &args)?;
+    let creds = if edit.replace_creds {
+        let creds = creds_from_args(dns_provider_type.clone(), args.token, args.token_from_env)?;
+        crate::dns::validate_creds(dns_provider_type, creds.clone()).await?;
+        Some(creds)
+    } else {
+        None
+    };
+
+    object::save_edit(&state, table, &object, edit.label, creds).await
+}
+
 pub async fn run(state: Arc<State>, args: DnsArgs) -> Result<()> {
     match args.command {
-        Commands::New => new_dns_provider(state).await?,
+        Commands::New(args) => {
+            new_dns_provider(state, args).await?;
+        }
+        Commands::List => object::list(state, ObjectTable::DnsProviders).await?,
+        Commands::Show(args) => object::show(state, ObjectTable::DnsProviders, args).await?,
+        Commands::Delete(args) => object::delete(state, ObjectTable::DnsProviders, args).await?,
+        Commands::Edit(args) => edit_dns_provider(state, args).await?,
     };
 
     Ok(())
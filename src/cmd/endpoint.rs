@@ -1,17 +1,32 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use clap::{Args, Subcommand};
-use eyre::Result;
+use eyre::{eyre, Result, WrapErr};
 use rusqlite::params;
+use serde::de::DeserializeOwned;
 use strum::{IntoEnumIterator, VariantNames};
 
 use crate::{
-    cli::get_unique_name,
-    db::PoolExtInteract,
-    deploy::{digitalocean::DigitalOceanCreds, EndpointProviderType},
+    cli::{get_unique_name, is_interactive, value_or_prompt, variant_parser},
+    db::{ObjectTable, PoolExtInteract},
+    deploy::{
+        aws::AwsConfig,
+        digitalocean::{DigitalOceanCreds, DigitalOceanMode},
+        fly::FlyCreds,
+        heroku::HerokuCreds,
+        kubernetes::KubernetesConfig,
+        local_file::LocalFileConfig,
+        ssh::{default_port, read_private_key, SshConfig},
+        vercel::VercelCreds,
+        webhook::{default_max_retry_secs, WebhookConfig, WebhookFormat},
+        EndpointProviderType,
+    },
 };
 
-use super::State;
+use super::{
+    object::{self, DeleteArgs, ShowArgs},
+    State,
+};
 
 #[derive(Args, Debug)]
 pub struct EndpointArgs {
@@ -22,24 +37,740 @@ pub struct EndpointArgs {
 #[derive(Debug, Subcommand)]
 enum Commands {
     #[clap(about = "Add a new endpoint account")]
-    New,
+    New(Box<NewEndpointArgs>),
+    #[clap(about = "List endpoint accounts")]
+    List,
+    #[clap(about = "Show an endpoint account and the subdomains that use it")]
+    Show(ShowArgs),
+    #[clap(about = "Delete an endpoint account")]
+    Delete(DeleteArgs),
+    #[clap(about = "Change an endpoint account's label or settings")]
+    Edit(Box<EditEndpointArgs>),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct NewEndpointArgs {
+    /// A label for this host
+    #[clap(long)]
+    name: Option<String>,
+
+    /// The host provider type
+    #[clap(long, value_parser = variant_parser::<EndpointProviderType>())]
+    provider: Option<EndpointProviderType>,
+
+    #[clap(flatten)]
+    creds: ProviderArgs,
+}
+
+/// Changes to an endpoint. Provider options that aren't given keep their current values, and an
+/// empty value, such as `--chain-path ''`, removes an optional setting.
+#[derive(Args, Debug)]
+pub struct EditEndpointArgs {
+    /// The name to edit
+    name: String,
+
+    /// A new label
+    #[clap(long)]
+    label: Option<String>,
+
+    #[clap(flatten)]
+    creds: ProviderArgs,
+}
+
+/// Replace a setting with a value from the command line. An empty value clears it.
+fn merge_option(setting: &mut Option<String>, value: Option<String>) {
+    if let Some(value) = value {
+        *setting = (!value.is_empty()).then_some(value);
+    }
+}
+
+/// The provider-specific options for an endpoint.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct ProviderArgs {
+    /// The API token for the provider. This can also refer to a secret stored elsewhere, as
+    /// env:NAME, file:/path, or cmd:<command>
+    #[clap(long)]
+    token: Option<String>,
+
+    /// Read the API token from the provider's environment variable when it is used, instead of
+    /// storing it
+    #[clap(long, conflicts_with = "token")]
+    token_from_env: bool,
+
+    /// For DigitalOcean, deploy to the load balancers that use the subdomain's previous
+    /// certificate instead of a Spaces CDN endpoint
+    #[clap(long)]
+    load_balancer: bool,
+
+    /// For DigitalOcean, deploy to the subdomain's Spaces CDN endpoint. This is the default, so
+    /// it's only needed to switch an existing endpoint back from --load-balancer.
+    #[clap(long, conflicts_with = "load_balancer")]
+    cdn: bool,
+
+    /// For Heroku and Fly.io, the app that serves the subdomain
+    #[clap(long)]
+    app: Option<String>,
+
+    #[clap(flatten)]
+    aws: AwsArgs,
+
+    #[clap(flatten)]
+    kubernetes: KubernetesArgs,
+
+    #[clap(flatten)]
+    ssh: SshArgs,
+
+    #[clap(flatten)]
+    files: FileArgs,
+
+    #[clap(flatten)]
+    vercel: VercelArgs,
+
+    #[clap(flatten)]
+    webhook: WebhookArgs,
+}
+
+/// Options for the Webhook provider.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct WebhookArgs {
+    /// The URL to POST the certificate bundle to
+    #[clap(long)]
+    webhook_url: Option<String>,
+
+    /// Send the bundle as a multipart form instead of JSON
+    #[clap(long)]
+    multipart: bool,
+
+    /// Send the bundle as JSON. This is the default, so it's only needed to switch an existing
+    /// endpoint back from --multipart.
+    #[clap(long, conflicts_with = "multipart")]
+    json: bool,
+
+    /// A secret to sign each request with, as an HMAC-SHA256 of the body in the X-Signature-256
+    /// header. This can also refer to a secret stored elsewhere, as env:NAME, file:/path, or
+    /// cmd:<command>.
+    #[clap(long)]
+    webhook_secret: Option<String>,
+
+    /// A header to send with each request, as `Name: value`. The value can refer to a secret
+    /// stored elsewhere. Can be given more than once. When editing, this replaces the header with
+    /// the same name, and an empty value removes it.
+    #[clap(long = "header", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// How long to keep retrying when the webhook can't be reached or returns a server error.
+    /// Defaults to 120 seconds.
+    #[clap(long)]
+    max_retry_secs: Option<u64>,
+
+    /// Allow a plain http webhook URL, even though the request carries the private key. When
+    /// editing, this has to be given again along with a new --webhook-url.
+    #[clap(long)]
+    allow_insecure: bool,
+}
+
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value
+        .split_once(':')
+        .ok_or_else(|| format!("{value} is not in the form `Name: value`"))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("The header name is empty".to_string());
+    }
+
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+impl WebhookArgs {
+    /// Build the configuration from the command line, or return `None` if no URL was given.
+    fn into_config(self) -> Option<WebhookConfig> {
+        Some(WebhookConfig {
+            url: self.webhook_url?,
+            format: if self.multipart {
+                WebhookFormat::Multipart
+            } else {
+                WebhookFormat::Json
+            },
+            secret: self.webhook_secret,
+            headers: self
+                .headers
+                .into_iter()
+                .filter(|(_, value)| !value.is_empty())
+                .collect(),
+            max_retry_secs: self.max_retry_secs.unwrap_or_else(default_max_retry_secs),
+            allow_insecure: self.allow_insecure,
+        })
+    }
+
+    fn merge_into(self, config: &mut WebhookConfig) {
+        if let Some(url) = self.webhook_url {
+            config.url = url;
+            config.allow_insecure = self.allow_insecure;
+        } else if self.allow_insecure {
+            config.allow_insecure = true;
+        }
+        if self.multipart {
+            config.format = WebhookFormat::Multipart;
+        } else if self.json {
+            config.format = WebhookFormat::Json;
+        }
+        merge_option(&mut config.secret, self.webhook_secret);
+        for (name, value) in self.headers {
+            if value.is_empty() {
+                config.headers.remove(&name);
+            } else {
+                config.headers.insert(name, value);
+            }
+        }
+        if let Some(max_retry_secs) = self.max_retry_secs {
+            config.max_retry_secs = max_retry_secs;
+        }
+    }
+}
+
+/// Options for the Vercel provider.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct VercelArgs {
+    /// The Vercel team that owns the project, if the token can access more than one
+    #[clap(long)]
+    vercel_team: Option<String>,
+
+    /// The Vercel project to add the subdomain to as a custom domain, if it isn't already
+    #[clap(long)]
+    vercel_project: Option<String>,
+}
+
+/// Options for the Kubernetes provider.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct KubernetesArgs {
+    /// The kubeconfig file to use. Defaults to $KUBECONFIG or ~/.kube/config, or the service
+    /// account when running inside the cluster.
+    #[clap(long)]
+    kubeconfig: Option<String>,
+
+    /// The kubeconfig context to use, instead of the current one
+    #[clap(long)]
+    context: Option<String>,
+
+    /// Always use the service account of the pod this runs in
+    #[clap(long, conflicts_with_all = ["kubeconfig", "context"])]
+    in_cluster: bool,
+
+    /// The namespace of the Secret
+    #[clap(long)]
+    namespace: Option<String>,
+
+    /// The name of the TLS Secret to write, which may contain {subdomain}. Defaults to
+    /// {subdomain}-tls.
+    #[clap(long)]
+    secret_name: Option<String>,
+
+    /// A Deployment in the same namespace to restart after the Secret is updated
+    #[clap(long)]
+    deployment: Option<String>,
+}
+
+impl KubernetesArgs {
+    /// Build the configuration from the command line, or return `None` if no options were given.
+    fn into_config(self) -> Option<KubernetesConfig> {
+        if self.kubeconfig.is_none()
+            && self.context.is_none()
+            && !self.in_cluster
+            && self.namespace.is_none()
+            && self.secret_name.is_none()
+            && self.deployment.is_none()
+        {
+            return None;
+        }
+
+        let defaults = KubernetesConfig::default();
+        Some(KubernetesConfig {
+            kubeconfig: self.kubeconfig,
+            context: self.context,
+            in_cluster: self.in_cluster,
+            namespace: self.namespace.unwrap_or(defaults.namespace),
+            secret_name: self.secret_name.unwrap_or(defaults.secret_name),
+            deployment: self.deployment,
+        })
+    }
+
+    fn merge_into(self, config: &mut KubernetesConfig) {
+        if self.in_cluster {
+            config.in_cluster = true;
+            config.kubeconfig = None;
+            config.context = None;
+        } else if self.kubeconfig.is_some() || self.context.is_some() {
+            config.in_cluster = false;
+        }
+        merge_option(&mut config.kubeconfig, self.kubeconfig);
+        merge_option(&mut config.context, self.context);
+        if let Some(namespace) = self.namespace {
+            config.namespace = namespace;
+        }
+        if let Some(secret_name) = self.secret_name {
+            config.secret_name = secret_name;
+        }
+        merge_option(&mut config.deployment, self.deployment);
+    }
+}
+
+/// Options for the Aws provider. Anything not given comes from the usual AWS configuration.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct AwsArgs {
+    /// The AWS region to import certificates into
+    #[clap(long)]
+    aws_region: Option<String>,
+
+    /// A profile from the AWS config files
+    #[clap(long)]
+    aws_profile: Option<String>,
+
+    /// The AWS access key ID
+    #[clap(long, requires = "aws_secret_access_key")]
+    aws_access_key_id: Option<String>,
+
+    /// The AWS secret access key. This can also refer to a secret stored elsewhere, as env:NAME,
+    /// file:/path, or cmd:<command>
+    #[clap(long, requires = "aws_access_key_id")]
+    aws_secret_access_key: Option<String>,
+
+    /// The ARN of the ACM certificate to reimport over. Without this, the imported certificate
+    /// for the subdomain is found by its domain name.
+    #[clap(long)]
+    certificate_arn: Option<String>,
+
+    /// An ALB listener to use newly-imported certificates for
+    #[clap(long, conflicts_with = "distribution_id")]
+    listener_arn: Option<String>,
+
+    /// A CloudFront distribution to use newly-imported certificates for
+    #[clap(long)]
+    distribution_id: Option<String>,
+}
+
+impl AwsArgs {
+    /// Build the configuration from the command line, or return `None` if no options were given.
+    fn into_config(self) -> Option<AwsConfig> {
+        let config = AwsConfig {
+            region: self.aws_region,
+            profile: self.aws_profile,
+            access_key_id: self.aws_access_key_id,
+            secret_access_key: self.aws_secret_access_key,
+            certificate_arn: self.certificate_arn,
+            listener_arn: self.listener_arn,
+            distribution_id: self.distribution_id,
+        };
+
+        let empty = config.region.is_none()
+            && config.profile.is_none()
+            && config.access_key_id.is_none()
+            && config.certificate_arn.is_none()
+            && config.listener_arn.is_none()
+            && config.distribution_id.is_none();
+        (!empty).then_some(config)
+    }
+
+    fn merge_into(self, config: &mut AwsConfig) {
+        merge_option(&mut config.region, self.aws_region);
+        merge_option(&mut config.profile, self.aws_profile);
+        merge_option(&mut config.access_key_id, self.aws_access_key_id);
+        merge_option(&mut config.secret_access_key, self.aws_secret_access_key);
+        merge_option(&mut config.certificate_arn, self.certificate_arn);
+        merge_option(&mut config.listener_arn, self.listener_arn);
+        merge_option(&mut config.distribution_id, self.distribution_id);
+    }
 }
 
-pub async fn new_endpoint(state: Arc<State>) -> Result<String> {
-    let name = get_unique_name(&state, "Give this host a label", "endpoints").await?;
+/// Options for the Ssh provider.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct SshArgs {
+    /// The host to connect to over SSH
+    #[clap(long)]
+    host: Option<String>,
 
-    let selection = dialoguer::Select::new()
-        .with_prompt("What host provider are you using?")
-        .items(EndpointProviderType::VARIANTS)
-        .interact()?;
-    let endpoint_type = EndpointProviderType::iter().nth(selection).unwrap();
+    /// The SSH port. Defaults to 22.
+    #[clap(long)]
+    port: Option<u16>,
+
+    /// The user to log in as. Defaults to root.
+    #[clap(long)]
+    user: Option<String>,
+
+    /// A private key file to log in with. This can also refer to a secret stored elsewhere, as
+    /// env:NAME, file:/path, or cmd:<command>. Without this, ssh-agent is used.
+    #[clap(long)]
+    private_key: Option<String>,
+
+    /// The passphrase for the private key, or a reference to it
+    #[clap(long, requires = "private_key")]
+    passphrase: Option<String>,
+
+    /// The SHA256 fingerprint of the host's key, as shown by `ssh-keygen -l`. If this isn't
+    /// given, the host must be in the known_hosts file.
+    #[clap(long)]
+    host_key: Option<String>,
+
+    /// The known_hosts file to check the host's key against. Defaults to ~/.ssh/known_hosts.
+    #[clap(long)]
+    known_hosts: Option<String>,
+}
+
+impl SshArgs {
+    /// Build the configuration from the command line, or return `None` if no host was given.
+    fn into_config(self, files: FileArgs) -> Result<Option<SshConfig>> {
+        let Some(host) = self.host else {
+            return Ok(None);
+        };
+
+        let files = files.into_config().ok_or_else(|| {
+            eyre!("Pass the remote paths to write to, such as --fullchain-path and --key-path")
+        })?;
+
+        Ok(Some(SshConfig {
+            host,
+            port: self.port.unwrap_or_else(default_port),
+            user: self.user.unwrap_or_else(|| "root".to_string()),
+            private_key: self
+                .private_key
+                .as_deref()
+                .map(read_private_key)
+                .transpose()?,
+            passphrase: self.passphrase,
+            host_key: self.host_key,
+            known_hosts: self.known_hosts,
+            files,
+        }))
+    }
+
+    fn merge_into(self, config: &mut SshConfig) -> Result<()> {
+        if let Some(host) = self.host {
+            config.host = host;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(user) = self.user {
+            config.user = user;
+        }
+        if let Some(private_key) = self.private_key {
+            config.private_key = match private_key.as_str() {
+                "" => None,
+                path => Some(read_private_key(path)?),
+            };
+            // A passphrase belongs to the key it unlocks.
+            if self.passphrase.is_none() {
+                config.passphrase = None;
+            }
+        }
+        merge_option(&mut config.passphrase, self.passphrase);
+        merge_option(&mut config.host_key, self.host_key);
+        merge_option(&mut config.known_hosts, self.known_hosts);
+        Ok(())
+    }
+}
+
+/// Options for the LocalFile and Ssh providers. Paths may contain `{subdomain}`, which is
+/// replaced by the name of the subdomain being deployed.
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct FileArgs {
+    /// Where to write the leaf certificate
+    #[clap(long)]
+    cert_path: Option<String>,
+
+    /// Where to write the intermediate certificates
+    #[clap(long)]
+    chain_path: Option<String>,
+
+    /// Where to write the leaf certificate followed by the intermediates
+    #[clap(long)]
+    fullchain_path: Option<String>,
+
+    /// Where to write the private key
+    #[clap(long)]
+    key_path: Option<String>,
+
+    /// Where to write the private key followed by the full chain, as HAProxy expects
+    #[clap(long)]
+    combined_path: Option<String>,
+
+    /// The owner of the written files, as user, user:group, or :group
+    #[clap(long)]
+    owner: Option<String>,
+
+    /// The mode of files that only contain certificates, in octal
+    #[clap(long, value_parser = parse_mode)]
+    cert_mode: Option<u32>,
+
+    /// The mode of files that contain the private key, in octal
+    #[clap(long, value_parser = parse_mode)]
+    key_mode: Option<u32>,
+
+    /// A shell command to run after writing the files, such as `systemctl reload nginx`. If it
+    /// fails, the previous files are put back.
+    #[clap(long)]
+    reload_command: Option<String>,
+}
+
+fn parse_mode(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("{value} is not an octal file mode"))
+}
+
+impl FileArgs {
+    /// Build the configuration from the command line, or return `None` if no paths were given.
+    fn into_config(self) -> Option<LocalFileConfig> {
+        if self.cert_path.is_none()
+            && self.chain_path.is_none()
+            && self.fullchain_path.is_none()
+            && self.key_path.is_none()
+            && self.combined_path.is_none()
+        {
+            return None;
+        }
+
+        Some(LocalFileConfig {
+            cert_path: self.cert_path,
+            chain_path: self.chain_path,
+            fullchain_path: self.fullchain_path,
+            key_path: self.key_path,
+            combined_path: self.combined_path,
+            owner: self.owner,
+            cert_mode: self.cert_mode,
+            key_mode: self.key_mode,
+            reload_command: self.reload_command,
+        })
+    }
+
+    fn merge_into(self, config: &mut LocalFileConfig) {
+        merge_option(&mut config.cert_path, self.cert_path);
+        merge_option(&mut config.chain_path, self.chain_path);
+        merge_option(&mut config.fullchain_path, self.fullchain_path);
+        merge_option(&mut config.key_path, self.key_path);
+        merge_option(&mut config.combined_path, self.combined_path);
+        merge_option(&mut config.owner, self.owner);
+        if self.cert_mode.is_some() {
+            config.cert_mode = self.cert_mode;
+        }
+        if self.key_mode.is_some() {
+            config.key_mode = self.key_mode;
+        }
+        merge_option(&mut config.reload_command, self.reload_command);
+    }
+}
+
+/// The token from the command line, or a reference to the provider's environment variable.
+fn token_or_env(token: Option<String>, token_from_env: bool, var: &str) -> Option<String> {
+    if token_from_env {
+        Some(format!("env:{var}"))
+    } else {
+        token
+    }
+}
+
+/// Build the credentials to store from the command-line options, prompting for the token if
+/// needed.
+async fn creds_from_args(
+    endpoint_type: EndpointProviderType,
+    args: ProviderArgs,
+) -> Result<String> {
+    let ProviderArgs {
+        token,
+        token_from_env,
+        load_balancer,
+        cdn,
+        app,
+        aws,
+        kubernetes,
+        ssh,
+        files,
+        vercel,
+        webhook,
+    } = args;
 
     let creds_str = match endpoint_type {
-        EndpointProviderType::DigitalOcean => DigitalOceanCreds::from_console()?
-            .map(|creds| serde_json::to_string(&creds))
-            .transpose()?,
+        EndpointProviderType::Aws => {
+            if token.is_some() || token_from_env {
+                return Err(eyre!(
+                    "Aws endpoints use --aws-access-key-id and --aws-secret-access-key, or the usual AWS credentials, not an API token"
+                ));
+            }
+
+            // Everything is optional, so without a terminal an empty configuration just uses
+            // the AWS defaults.
+            let config = match aws.into_config() {
+                Some(config) => config,
+                None if is_interactive() => AwsConfig::from_console()?,
+                None => AwsConfig::default(),
+            };
+            config.check()?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Fly => {
+            let creds = match (token_or_env(token, token_from_env, "FLY_API_TOKEN"), app) {
+                (Some(token), Some(app)) => FlyCreds::new(token, app),
+                (token, app) => {
+                    let missing = if app.is_none() {
+                        "--app"
+                    } else {
+                        "--token or --token-from-env"
+                    };
+                    value_or_prompt(None, missing, || FlyCreds::from_console(token, app))?
+                }
+            };
+            serde_json::to_string(&creds)?
+        }
+        EndpointProviderType::Heroku => {
+            let creds = match (token_or_env(token, token_from_env, "HEROKU_API_KEY"), app) {
+                (Some(token), Some(app)) => HerokuCreds::new(token, app),
+                (token, app) => {
+                    let missing = if app.is_none() {
+                        "--app"
+                    } else {
+                        "--token or --token-from-env"
+                    };
+                    value_or_prompt(None, missing, || HerokuCreds::from_console(token, app))?
+                }
+            };
+            serde_json::to_string(&creds)?
+        }
+        EndpointProviderType::Kubernetes => {
+            if token.is_some() || token_from_env {
+                return Err(eyre!(
+                    "Kubernetes endpoints log in with a kubeconfig or the in-cluster service account, not an API token"
+                ));
+            }
+
+            let config = match kubernetes.into_config() {
+                Some(config) => config,
+                None if is_interactive() => KubernetesConfig::from_console()?,
+                None => KubernetesConfig::default(),
+            };
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::DigitalOcean => {
+            let mode = if load_balancer {
+                DigitalOceanMode::LoadBalancer
+            } else if !cdn && token.is_none() && !token_from_env && is_interactive() {
+                DigitalOceanCreds::mode_from_console()?
+            } else {
+                DigitalOceanMode::Cdn
+            };
+
+            let creds = if token_from_env {
+                None
+            } else {
+                value_or_prompt(
+                    token.map(|token| Some(DigitalOceanCreds::new(token))),
+                    "--token or --token-from-env",
+                    DigitalOceanCreds::from_console,
+                )?
+            };
+
+            match (creds, mode) {
+                (Some(mut creds), mode) => {
+                    creds.mode = mode;
+                    serde_json::to_string(&creds)?
+                }
+                (None, DigitalOceanMode::Cdn) => String::new(),
+                // The mode has to be stored somewhere, so refer to the environment variable
+                // instead of leaving the credentials empty.
+                (None, mode) => {
+                    let mut creds = DigitalOceanCreds::new("env:DIGITAL_OCEAN_TOKEN".to_string());
+                    creds.mode = mode;
+                    serde_json::to_string(&creds)?
+                }
+            }
+        }
+        EndpointProviderType::LocalFile => {
+            if token.is_some() || token_from_env {
+                return Err(eyre!("LocalFile endpoints don't use an API token"));
+            }
+
+            let config = value_or_prompt(
+                files.into_config(),
+                "--fullchain-path and --key-path",
+                LocalFileConfig::from_console,
+            )?;
+            config.validate()?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Ssh => {
+            if token.is_some() || token_from_env {
+                return Err(eyre!(
+                    "Ssh endpoints log in with --private-key or ssh-agent, not an API token"
+                ));
+            }
+
+            let config =
+                value_or_prompt(ssh.into_config(files)?, "--host", SshConfig::from_console)?;
+            config.files.check_paths()?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Webhook => {
+            if token.is_some() || token_from_env {
+                return Err(eyre!(
+                    "Webhook endpoints use --webhook-secret and --header, not an API token"
+                ));
+            }
+
+            let config = value_or_prompt(
+                webhook.into_config(),
+                "--webhook-url",
+                WebhookConfig::from_console,
+            )?;
+            config.validate().await?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Vercel => {
+            let creds = if token_from_env {
+                None
+            } else {
+                value_or_prompt(
+                    token.map(|token| Some(VercelCreds::new(token))),
+                    "--token or --token-from-env",
+                    VercelCreds::from_console,
+                )?
+            };
+
+            let scoped = vercel.vercel_team.is_some() || vercel.vercel_project.is_some();
+            match creds {
+                Some(mut creds) => {
+                    creds.team_id = vercel.vercel_team;
+                    creds.project = vercel.vercel_project;
+                    serde_json::to_string(&creds)?
+                }
+                None if !scoped => String::new(),
+                // The team and project have to be stored somewhere, so refer to the environment
+                // variable instead of leaving the credentials empty.
+                None => {
+                    let mut creds = VercelCreds::new("env:VERCEL_TOKEN".to_string());
+                    creds.team_id = vercel.vercel_team;
+                    creds.project = vercel.vercel_project;
+                    serde_json::to_string(&creds)?
+                }
+            }
+        }
     };
 
+    Ok(creds_str)
+}
+
+pub async fn new_endpoint(state: Arc<State>, args: NewEndpointArgs) -> Result<String> {
+    let name = get_unique_name(&state, args.name, "Give this host a label", "endpoints").await?;
+
+    let endpoint_type = value_or_prompt(args.provider, "--provider", || {
+        let selection = dialoguer::Select::new()
+            .with_prompt("What host provider are you using?")
+            .items(EndpointProviderType::VARIANTS)
+            .interact()?;
+        Ok(EndpointProviderType::iter().nth(selection).unwrap())
+    })?;
+
+    let creds_str = creds_from_args(endpoint_type.clone(), args.creds).await?;
+    let creds_str = state.secrets.seal(creds_str)?;
+
     {
         let name = name.clone();
         state
@@ -57,9 +788,196 @@ pub async fn new_endpoint(state: Arc<State>) -> Result<String> {
     Ok(name)
 }
 
+/// Read an endpoint's stored settings so that some of them can be changed. Secret references
+/// inside them are kept as they are.
+fn stored_settings<T: DeserializeOwned>(creds: &str) -> Result<T> {
+    if crate::creds::is_reference(creds) {
+        return Err(eyre!(
+            "This endpoint's settings are read from {creds}. Change them there, or run `endpoint edit` in a terminal to replace them."
+        ));
+    }
+
+    serde_json::from_str(creds).wrap_err("Failed to read the endpoint's settings")
+}
+
+/// The token an endpoint was stored with, without looking up what it refers to. `None` means it
+/// is read from the provider's environment variable.
+fn stored_token(creds: &str) -> Option<String> {
+    if creds.is_empty() {
+        return None;
+    }
+
+    match serde_json::from_str::<serde_json::Value>(creds) {
+        Ok(creds) => creds.get("token")?.as_str().map(|s| s.to_string()),
+        // A bare token, or a reference to one
+        Err(_) => Some(creds.to_string()),
+    }
+}
+
+/// Apply the options given on the command line over an endpoint's stored settings. `creds` is
+/// the stored value, already decrypted.
+async fn merge_creds(
+    endpoint_type: EndpointProviderType,
+    creds: &str,
+    args: ProviderArgs,
+) -> Result<String> {
+    let creds_str = match endpoint_type {
+        EndpointProviderType::Aws
+        | EndpointProviderType::Kubernetes
+        | EndpointProviderType::LocalFile
+        | EndpointProviderType::Ssh
+        | EndpointProviderType::Webhook
+            if args.token.is_some() || args.token_from_env =>
+        {
+            return Err(eyre!(
+                "{endpoint_type} endpoints don't use an API token. Pass the provider's own options instead."
+            ));
+        }
+        EndpointProviderType::Aws => {
+            let mut config = match creds {
+                "" => AwsConfig::default(),
+                creds => stored_settings(creds)?,
+            };
+            args.aws.merge_into(&mut config);
+            config.check()?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Kubernetes => {
+            let mut config = match creds {
+                "" => KubernetesConfig::default(),
+                creds => stored_settings(creds)?,
+            };
+            args.kubernetes.merge_into(&mut config);
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::LocalFile => {
+            let mut config: LocalFileConfig = stored_settings(creds)?;
+            args.files.merge_into(&mut config);
+            config.validate()?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Ssh => {
+            let mut config: SshConfig = stored_settings(creds)?;
+            args.ssh.merge_into(&mut config)?;
+            args.files.merge_into(&mut config.files);
+            config.files.check_paths()?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::Webhook => {
+            let mut config: WebhookConfig = stored_settings(creds)?;
+            args.webhook.merge_into(&mut config);
+            config.validate().await?;
+            serde_json::to_string(&config)?
+        }
+        EndpointProviderType::DigitalOcean
+        | EndpointProviderType::Fly
+        | EndpointProviderType::Heroku
+        | EndpointProviderType::Vercel => {
+            // These are built the same way as a new endpoint, with anything not given on the
+            // command line filled in from the stored settings.
+            let mut args = args;
+            if args.token.is_none() && !args.token_from_env {
+                match stored_token(creds) {
+                    Some(token) => args.token = Some(token),
+                    None => args.token_from_env = true,
+                }
+            }
+
+            match endpoint_type {
+                EndpointProviderType::DigitalOcean => {
+                    if !args.load_balancer && !args.cdn {
+                        args.load_balancer = serde_json::from_str::<DigitalOceanCreds>(creds)
+                            .map(|creds| creds.mode == DigitalOceanMode::LoadBalancer)
+                            .unwrap_or(false);
+                    }
+                    args.cdn = !args.load_balancer;
+                }
+                EndpointProviderType::Fly => {
+                    if args.app.is_none() {
+                        args.app = serde_json::from_str::<FlyCreds>(creds)
+                            .map(|creds| creds.app)
+                            .ok();
+                    }
+                }
+                EndpointProviderType::Heroku => {
+                    if args.app.is_none() {
+                        args.app = serde_json::from_str::<HerokuCreds>(creds)
+                            .map(|creds| creds.app)
+                            .ok();
+                    }
+                }
+                _ => {
+                    if let Ok(stored) = serde_json::from_str::<VercelCreds>(creds) {
+                        let mut team_id = stored.team_id;
+                        let mut project = stored.project;
+                        merge_option(&mut team_id, args.vercel.vercel_team.take());
+                        merge_option(&mut project, args.vercel.vercel_project.take());
+                        args.vercel = VercelArgs {
+                            vercel_team: team_id,
+                            vercel_project: project,
+                        };
+                    }
+                }
+            }
+
+            creds_from_args(endpoint_type, args).await?
+        }
+    };
+
+    Ok(creds_str)
+}
+
+async fn edit_endpoint(state: Arc<State>, args: EditEndpointArgs) -> Result<()> {
+    let table = ObjectTable::Endpoints;
+    let object = crate::db::get_object(&state, table, args.name.clone()).await?;
+    let endpoint_type = EndpointProviderType::from_str(&object.provider)?;
+
+    let (label, creds) = if args.label.is_some() || args.creds != ProviderArgs::default() {
+        let creds = if args.creds != ProviderArgs::default() {
+            let stored = state.secrets.open(object.creds.clone())?;
+            Some(merge_creds(endpoint_type.clone(), &stored, args.creds).await?)
+        } else {
+            None
+        };
+        (args.label, creds)
+    } else if is_interactive() {
+        let label: String = dialoguer::Input::new()
+            .with_prompt("Label")
+            .default(object.name.clone())
+            .interact_text()?;
+
+        let replace = dialoguer::Confirm::new()
+            .with_prompt("Replace all of the endpoint's settings?")
+            .default(false)
+            .interact()?;
+        let creds = if replace {
+            Some(creds_from_args(endpoint_type.clone(), ProviderArgs::default()).await?)
+        } else {
+            None
+        };
+        (Some(label), creds)
+    } else {
+        return Err(eyre!(
+            "Nothing to change. Pass --label or the options for the provider's settings"
+        ));
+    };
+
+    if let Some(creds) = &creds {
+        crate::deploy::validate_creds(endpoint_type, creds.clone()).await?;
+    }
+
+    object::save_edit(&state, table, &object, label, creds).await
+}
+
 pub async fn run(state: Arc<State>, args: EndpointArgs) -> Result<()> {
     match args.command {
-        Commands::New => new_endpoint(state).await?,
+        Commands::New(args) => {
+            new_endpoint(state, *args).await?;
+        }
+        Commands::List => object::list(state, ObjectTable::Endpoints).await?,
+        Commands::Show(args) => object::show(state, ObjectTable::Endpoints, args).await?,
+        Commands::Delete(args) => object::delete(state, ObjectTable::Endpoints, args).await?,
+        Commands::Edit(args) => edit_endpoint(state, *args).await?,
     };
 
     Ok(())
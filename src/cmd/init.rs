@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use clap::Args;
-use eyre::Result;
+use eyre::{eyre, Result};
 
 use super::{acme_account::new_account, dns::new_dns_provider, endpoint::new_endpoint, State};
 
@@ -9,8 +9,14 @@ use super::{acme_account::new_account, dns::new_dns_provider, endpoint::new_endp
 pub struct InitArgs {}
 
 pub async fn run(state: Arc<State>, _args: InitArgs) -> Result<()> {
-    new_account(state.clone()).await?;
-    new_dns_provider(state.clone()).await?;
-    new_endpoint(state.clone()).await?;
+    if !crate::cli::is_interactive() {
+        return Err(eyre!(
+            "init only runs interactively. Use `account new`, `dns new`, and `endpoint new` with their command-line options instead."
+        ));
+    }
+
+    new_account(state.clone(), Default::default()).await?;
+    new_dns_provider(state.clone(), Default::default()).await?;
+    new_endpoint(state.clone(), Default::default()).await?;
     Ok(())
 }
@@ -0,0 +1,187 @@
+//! Commands shared by the objects that subdomains link to: ACME accounts, DNS providers, and hosts.
+
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::{eyre, Result};
+
+use crate::{
+    cli::{get_unique_name, is_interactive, print_table},
+    creds::is_reference,
+    crypto::is_encrypted,
+    db::{DbObject, ObjectTable},
+};
+
+use super::State;
+
+#[derive(Debug, Args)]
+pub struct ShowArgs {
+    /// The name to show
+    name: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteArgs {
+    /// The name to delete
+    name: String,
+
+    /// Also delete any subdomains that use this
+    #[clap(long)]
+    cascade: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct EditArgs {
+    /// The name to edit
+    pub name: String,
+
+    /// A new label
+    #[clap(long)]
+    pub label: Option<String>,
+
+    /// A new API token for the provider. This can also refer to a secret stored elsewhere, as
+    /// env:NAME, file:/path, or cmd:<command>. It is checked with the provider before saving.
+    #[clap(long)]
+    pub token: Option<String>,
+
+    /// Read the API token from the provider's environment variable when it is used, instead of
+    /// storing it
+    #[clap(long, conflicts_with = "token")]
+    pub token_from_env: bool,
+}
+
+/// The changes to make from an [EditArgs].
+pub struct ObjectEdit {
+    pub label: Option<String>,
+    pub replace_creds: bool,
+}
+
+/// Figure out what to change, prompting for it if nothing was given on the command line.
+pub fn resolve_edit(object: &DbObject, args: &EditArgs) -> Result<ObjectEdit> {
+    if args.label.is_some() || args.token.is_some() || args.token_from_env {
+        return Ok(ObjectEdit {
+            label: args.label.clone(),
+            replace_creds: args.token.is_some() || args.token_from_env,
+        });
+    }
+
+    if !is_interactive() {
+        return Err(eyre!(
+            "Nothing to change. Pass --label, --token, or --token-from-env"
+        ));
+    }
+
+    let label: String = dialoguer::Input::new()
+        .with_prompt("Label")
+        .default(object.name.clone())
+        .interact_text()?;
+
+    let replace_creds = dialoguer::Confirm::new()
+        .with_prompt("Replace the API token?")
+        .default(false)
+        .interact()?;
+
+    Ok(ObjectEdit {
+        label: Some(label),
+        replace_creds,
+    })
+}
+
+/// Save a new label and credentials. Unchanged values are passed as `None`.
+pub async fn save_edit(
+    state: &Arc<State>,
+    table: ObjectTable,
+    object: &DbObject,
+    label: Option<String>,
+    creds: Option<String>,
+) -> Result<()> {
+    let label = match label.filter(|label| label != &object.name) {
+        Some(label) => Some(get_unique_name(state, Some(label), "", table.table()).await?),
+        None => None,
+    };
+
+    let creds = creds.map(|creds| state.secrets.seal(creds)).transpose()?;
+    crate::db::update_object(state, table, object.id, label, creds).await?;
+    println!("Updated {} {}", table.label(), object.name);
+
+    Ok(())
+}
+
+fn describe_creds(object: &DbObject) -> String {
+    if object.creds.is_empty() {
+        return "read from the environment".to_string();
+    } else if is_encrypted(&object.creds) {
+        return "stored in the database, encrypted".to_string();
+    } else if is_reference(&object.creds) {
+        return format!("read from {}", object.creds);
+    }
+
+    let token = serde_json::from_str::<serde_json::Value>(&object.creds)
+        .ok()
+        .and_then(|creds| creds.get("token")?.as_str().map(|s| s.to_string()));
+    match token {
+        Some(token) if is_reference(&token) => format!("read from {token}"),
+        _ => "stored in the database".to_string(),
+    }
+}
+
+pub async fn list(state: Arc<State>, table: ObjectTable) -> Result<()> {
+    let objects = crate::db::list_objects(&state, table).await?;
+    if objects.is_empty() {
+        println!("No {}s found", table.label());
+        return Ok(());
+    }
+
+    let rows = objects
+        .into_iter()
+        .map(|o| vec![o.name, o.provider])
+        .collect::<Vec<_>>();
+    print_table(&["Name", "Provider"], &rows);
+
+    Ok(())
+}
+
+pub async fn show(state: Arc<State>, table: ObjectTable, args: ShowArgs) -> Result<()> {
+    let object = crate::db::get_object(&state, table, args.name).await?;
+    let subdomains = crate::db::subdomains_using(&state, table, object.id).await?;
+
+    println!("Name:        {}", object.name);
+    println!("Provider:    {}", object.provider);
+
+    // Encrypted account credentials can only show the URL when the key is available.
+    let account_url = match table {
+        ObjectTable::AcmeAccounts => state
+            .secrets
+            .open(object.creds.clone())
+            .ok()
+            .and_then(|creds| serde_json::from_str::<serde_json::Value>(&creds).ok())
+            .and_then(|creds| creds.get("id")?.as_str().map(|s| s.to_string())),
+        _ => None,
+    };
+
+    match account_url {
+        Some(url) => println!("Account URL: {url}"),
+        None => println!("Credentials: {}", describe_creds(&object)),
+    }
+
+    if subdomains.is_empty() {
+        println!("Subdomains:  none");
+    } else {
+        println!("Subdomains:  {}", subdomains.join(", "));
+    }
+
+    Ok(())
+}
+
+pub async fn delete(state: Arc<State>, table: ObjectTable, args: DeleteArgs) -> Result<()> {
+    let name = args.name.clone();
+    let deleted_subdomains =
+        crate::db::delete_object(&state, table, args.name, args.cascade).await?;
+
+    for subdomain in deleted_subdomains {
+        println!("Deleted subdomain {subdomain}");
+    }
+    println!("Deleted {} {name}", table.label());
+
+    Ok(())
+}
@@ -1,18 +1,30 @@
+mod delete;
+mod disable;
+mod dry_run;
 mod edit;
+mod enable;
+mod export;
+mod history;
+mod import;
+mod list;
 mod new;
 mod reinstall_cert;
 mod renew;
+mod revoke;
+mod rollback;
+mod show;
 
 use std::{str::FromStr, sync::Arc};
 
 use clap::{Args, Subcommand};
-use eyre::Result;
-use rusqlite::params;
+use eyre::{eyre, Result, WrapErr};
 
 use crate::{
-    db::{DbObject, PoolExtInteract},
-    deploy::EndpointProviderType,
+    db::{DbObject, EndpointDeploy},
+    deploy::{DeployEndpoint, DeployOptions, EndpointProviderType},
     dns::DnsProviderType,
+    notify::Event,
+    Certificate,
 };
 
 use super::State;
@@ -35,6 +47,33 @@ enum Commands {
     ///
     /// This requires that the certificate is cached in the local database.
     ReinstallCert(reinstall_cert::ReinstallCertArgs),
+    /// List subdomains and when their certificates expire
+    List(list::ListArgs),
+    /// Show the settings and certificate status for a subdomain
+    Show(show::ShowArgs),
+    /// Delete a subdomain
+    ///
+    /// This does not remove the certificate from the host.
+    Delete(delete::DeleteArgs),
+    /// Stop renewing a subdomain's certificate, without deleting it
+    Disable(disable::DisableArgs),
+    /// Resume renewing a disabled subdomain's certificate
+    Enable(enable::EnableArgs),
+    /// List the certificates that have been issued for a subdomain
+    History(history::HistoryArgs),
+    /// Deploy an earlier certificate again
+    ///
+    /// Without --serial, this uses the newest certificate before the current one that hasn't
+    /// expired.
+    Rollback(rollback::RollbackArgs),
+    /// Revoke a certificate with the ACME provider
+    Revoke(revoke::RevokeArgs),
+    /// Write a subdomain's certificate and key to files
+    Export(export::ExportArgs),
+    /// Import a certificate that was issued elsewhere
+    ///
+    /// The certificate is renewed as usual when it nears expiration.
+    Import(import::ImportArgs),
 }
 
 pub async fn run(state: Arc<State>, args: SubdomainArgs) -> Result<()> {
@@ -43,64 +82,267 @@ pub async fn run(state: Arc<State>, args: SubdomainArgs) -> Result<()> {
         Commands::Renew(args) => renew::run(state, args).await?,
         Commands::Edit(args) => edit::run(state, args).await?,
         Commands::ReinstallCert(args) => reinstall_cert::run(state, args).await?,
+        Commands::List(args) => list::run(state, args).await?,
+        Commands::Show(args) => show::run(state, args).await?,
+        Commands::Delete(args) => delete::run(state, args).await?,
+        Commands::Disable(args) => disable::run(state, args).await?,
+        Commands::Enable(args) => enable::run(state, args).await?,
+        Commands::History(args) => history::run(state, args).await?,
+        Commands::Rollback(args) => rollback::run(state, args).await?,
+        Commands::Revoke(args) => revoke::run(state, args).await?,
+        Commands::Export(args) => export::run(state, args).await?,
+        Commands::Import(args) => import::run(state, args).await?,
     };
 
     Ok(())
 }
 
+/// The deployer for one of a subdomain's hosts.
+struct EndpointDeployer {
+    id: i64,
+    name: String,
+    deployer: Box<dyn DeployEndpoint>,
+}
+
+/// Create the deployers for a subdomain's hosts.
+async fn create_deployers(
+    state: &Arc<State>,
+    subdomain: &str,
+    endpoints: Vec<DbObject>,
+    options: DeployOptions,
+) -> Result<Vec<EndpointDeployer>> {
+    if endpoints.is_empty() {
+        return Err(eyre!("{subdomain} has no hosts to deploy to"));
+    }
+
+    let mut deployers = Vec::with_capacity(endpoints.len());
+    for endpoint in endpoints {
+        let deployer = async {
+            let creds = state.secrets.open(endpoint.creds)?;
+            let deployer_type = EndpointProviderType::from_str(&endpoint.provider)?;
+            crate::deploy::create_deployer(
+                state.clone(),
+                deployer_type,
+                subdomain.to_string(),
+                creds,
+                options.clone(),
+            )
+            .await
+        }
+        .await
+        .wrap_err_with(|| format!("Failed to set up host {}", endpoint.name))?;
+
+        deployers.push(EndpointDeployer {
+            id: endpoint.id,
+            name: endpoint.name,
+            deployer,
+        });
+    }
+
+    Ok(deployers)
+}
+
+/// Create the deployers for the hosts of a subdomain in the database. If `only` is given, just
+/// that host is used.
+async fn subdomain_deployers(
+    state: &Arc<State>,
+    subdomain: String,
+    only: Option<&str>,
+    options: DeployOptions,
+) -> Result<Vec<EndpointDeployer>> {
+    let mut endpoints = crate::db::subdomain_endpoints(state, subdomain.clone()).await?;
+    if let Some(only) = only {
+        endpoints.retain(|e| e.name == only);
+        if endpoints.is_empty() {
+            return Err(eyre!("{subdomain} does not use a host named {only}"));
+        }
+    }
+
+    create_deployers(state, &subdomain, endpoints, options).await
+}
+
+/// Deploy a certificate to each host and record the results. A host that fails doesn't stop the
+/// others, but the error describes every failure.
+async fn deploy_to_endpoints(
+    state: &Arc<State>,
+    subdomain: &str,
+    cert: Certificate,
+    deployers: &[EndpointDeployer],
+    endpoint_must_exist: bool,
+) -> Result<()> {
+    let serial = cert.info()?.serial;
+
+    let mut results = Vec::with_capacity(deployers.len());
+    let mut failures = Vec::new();
+    for endpoint in deployers {
+        let result = endpoint
+            .deployer
+            .deploy_certificate(cert.clone(), endpoint_must_exist)
+            .await;
+
+        results.push(EndpointDeploy {
+            endpoint_id: endpoint.id,
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+        });
+
+        if let Err(e) = result {
+            failures.push((endpoint.name.as_str(), e));
+        }
+    }
+
+    // A host can accept a certificate and keep serving the old one, so check what's actually
+    // served, when one of the hosts that took the certificate is what serves the subdomain. A
+    // wrong certificate is only blamed on a host when it's the only one that could be serving it.
+    let serving = deployers
+        .iter()
+        .zip(&results)
+        .filter(|(endpoint, result)| result.error.is_none() && endpoint.deployer.serves_subdomain())
+        .map(|(endpoint, _)| endpoint.id)
+        .collect::<Vec<_>>();
+    if !serving.is_empty() {
+        if let Err(e) = crate::verify::verify_served_certificate(state, subdomain, &cert).await {
+            let message = format!("{e:#}");
+            if let [endpoint_id] = serving[..] {
+                for result in results.iter_mut().filter(|r| r.endpoint_id == endpoint_id) {
+                    result.error = Some(message.clone());
+                }
+            }
+            crate::db::record_deploys(state, subdomain.to_string(), serial, results).await?;
+
+            if failures.is_empty() {
+                return Err(e);
+            }
+
+            return Err(eyre!(
+                "Failed to deploy to {} of {} hosts, and {subdomain} does not serve the new certificate. {}",
+                failures.len(),
+                deployers.len(),
+                failures
+                    .iter()
+                    .map(|(name, e)| format!("{name}: {e:#}"))
+                    .chain(std::iter::once(message))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ));
+        }
+    }
+
+    crate::db::record_deploys(state, subdomain.to_string(), serial, results).await?;
+
+    if deployers.len() == 1 {
+        if let Some((_, e)) = failures.pop() {
+            return Err(e);
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        let errors = failures
+            .iter()
+            .map(|(name, e)| format!("{name}: {e:#}"))
+            .collect::<Vec<_>>();
+        Err(eyre!(
+            "Failed to deploy to {} of {} hosts. {}",
+            failures.len(),
+            deployers.len(),
+            errors.join("; ")
+        ))
+    }
+}
+
+/// The context of the error when a newly-issued certificate couldn't be deployed, by which time
+/// the failure has already been sent as a notification.
+#[derive(Debug)]
+pub struct DeployFailure {
+    subdomain: String,
+}
+
+impl std::fmt::Display for DeployFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Deploying the new certificate for {} failed",
+            self.subdomain
+        )
+    }
+}
+
 pub struct Renewal {
     subdomain: String,
-    acme_provider: String,
+    acme_account: i64,
     acme_creds: String,
     dns_provider: String,
     dns_creds: String,
-    endpoint_provider: String,
-    endpoint_creds: String,
+    endpoints: Vec<DbObject>,
 }
 
-async fn start_cert_process(state: Arc<State>, renewal: Renewal) -> Result<()> {
+async fn start_cert_process(
+    state: Arc<State>,
+    renewal: Renewal,
+    deploy_options: DeployOptions,
+) -> Result<()> {
     let Renewal {
         subdomain,
+        acme_account,
         acme_creds,
         dns_provider,
         dns_creds,
-        endpoint_provider,
-        endpoint_creds,
+        endpoints,
         ..
     } = renewal;
 
+    let acme_creds = state.secrets.open(acme_creds)?;
+    let dns_creds = state.secrets.open(dns_creds)?;
+
     let dns_provider_type = DnsProviderType::from_str(&dns_provider)?;
-    let dns_provider = crate::dns::get_dns_provider(dns_provider_type, &subdomain, dns_creds)?;
+    let dns_provider =
+        crate::dns::get_dns_provider(dns_provider_type, &subdomain, dns_creds).await?;
 
     let acme_creds = serde_json::from_str::<instant_acme::AccountCredentials>(&acme_creds)?;
     let account = instant_acme::Account::from_credentials(acme_creds)?;
 
-    let deployer_type = EndpointProviderType::from_str(&endpoint_provider)?;
-    let deployer = crate::deploy::create_deployer(
-        state.clone(),
-        deployer_type,
-        subdomain.clone(),
-        endpoint_creds,
-    )?;
+    let deployers = create_deployers(&state, &subdomain, endpoints, deploy_options).await?;
+    let first = crate::db::get_last_cert(&state, subdomain.clone())
+        .await?
+        .is_none();
 
-    let (cert, expires) =
+    let cert =
         crate::acme::get_certificate(state.clone(), dns_provider, account, subdomain.clone())
             .await?;
 
-    let saved_cert = serde_json::to_string(&cert)?;
-
-    state
-        .pool
-        .interact(move |conn| {
-            let mut stmt = conn
-                .prepare_cached(r##"UPDATE subdomains SET last_cert=?, expires=? WHERE name=?"##)?;
-
-            stmt.execute(params![saved_cert, expires, subdomain])?;
+    let info = cert.info()?;
+    let serial = info.serial.clone();
+    let expires = info.not_after;
+    let saved_cert = state.secrets.seal(serde_json::to_string(&cert)?)?;
+    crate::db::save_certificate(
+        &state,
+        subdomain.clone(),
+        Some(acme_account),
+        info,
+        saved_cert,
+    )
+    .await?;
 
-            Ok::<_, eyre::Report>(())
-        })
-        .await?;
+    let result = deploy_to_endpoints(&state, &subdomain, cert, &deployers, false).await;
+    let event = match &result {
+        Ok(()) if first => Event::Issued {
+            subdomain: &subdomain,
+            serial: &serial,
+            expires,
+        },
+        Ok(()) => Event::Renewed {
+            subdomain: &subdomain,
+            serial: &serial,
+            expires,
+        },
+        Err(error) => Event::DeployFailed {
+            subdomain: &subdomain,
+            serial: &serial,
+            error,
+        },
+    };
+    crate::notify::send(&state, event).await;
 
-    deployer.deploy_certificate(cert, false).await?;
-    Ok(())
+    result.wrap_err(DeployFailure { subdomain })
 }
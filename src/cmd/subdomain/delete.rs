@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::{eyre, Result};
+
+use crate::{cmd::State, db::PoolExtInteract};
+
+#[derive(Debug, Args)]
+pub struct DeleteArgs {
+    /// The subdomain to delete
+    subdomain: String,
+}
+
+pub async fn run(state: Arc<State>, args: DeleteArgs) -> Result<()> {
+    let s = args.subdomain.clone();
+    let deleted = state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached("DELETE FROM subdomains WHERE name=?")?;
+            let deleted = stmt.execute([s])?;
+            Ok::<_, eyre::Report>(deleted)
+        })
+        .await?;
+
+    if deleted == 0 {
+        return Err(eyre!("No subdomain named {} was found", args.subdomain));
+    }
+
+    println!("Deleted subdomain {}", args.subdomain);
+
+    Ok(())
+}
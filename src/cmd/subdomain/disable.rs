@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::{eyre, Result};
+use rusqlite::params;
+use time::OffsetDateTime;
+
+use crate::{cli::parse_date, cmd::State, db::PoolExtInteract};
+
+#[derive(Debug, Args)]
+pub struct DisableArgs {
+    /// The subdomain to disable
+    subdomain: String,
+
+    /// Why the subdomain is disabled, to show in listings
+    #[clap(long)]
+    reason: Option<String>,
+
+    /// Automatically enable the subdomain again at this date (YYYY-MM-DD or RFC 3339)
+    #[clap(long, value_parser = parse_date)]
+    until: Option<OffsetDateTime>,
+}
+
+pub async fn run(state: Arc<State>, args: DisableArgs) -> Result<()> {
+    let s = args.subdomain.clone();
+    let reenable_at = args.until.map(|t| t.unix_timestamp());
+    let updated = state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "UPDATE subdomains SET enabled=false, disabled_reason=?, reenable_at=? WHERE name=?",
+            )?;
+            let updated = stmt.execute(params![args.reason, reenable_at, s])?;
+            Ok::<_, eyre::Report>(updated)
+        })
+        .await?;
+
+    if updated == 0 {
+        return Err(eyre!("No subdomain named {} was found", args.subdomain));
+    }
+
+    println!("Disabled renewals for {}", args.subdomain);
+
+    Ok(())
+}
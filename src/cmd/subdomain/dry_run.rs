@@ -0,0 +1,195 @@
+//! Checking what issuing and deploying a certificate would do, without changing anything.
+
+use std::{str::FromStr, sync::Arc};
+
+use clap::Args;
+use eyre::{eyre, Result, WrapErr};
+
+use crate::{
+    acme::AcmeProvider,
+    db::DbObject,
+    deploy::{DeployOptions, EndpointProviderType},
+    dns::DnsProviderType,
+    Certificate,
+};
+
+use super::{Renewal, State};
+
+#[derive(Args, Debug)]
+pub struct DryRunArgs {
+    /// Check the credentials and show what would change on each host, without issuing or
+    /// deploying anything
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// With --dry-run, also issue a certificate from the Let's Encrypt staging environment to
+    /// check that the DNS challenge works. The certificate is thrown away.
+    #[clap(long, requires = "dry_run")]
+    pub staging: bool,
+}
+
+/// Check everything a renewal needs and print what it would do. `current` is the certificate
+/// that the hosts would be compared against when there's no staging certificate.
+pub(super) async fn plan_renewal(
+    state: &Arc<State>,
+    renewal: Renewal,
+    options: DeployOptions,
+    current: Option<Certificate>,
+    staging: bool,
+) -> Result<()> {
+    let Renewal {
+        subdomain,
+        acme_creds,
+        dns_provider,
+        dns_creds,
+        endpoints,
+        ..
+    } = renewal;
+
+    let mut ok = true;
+
+    let account = state
+        .secrets
+        .open(acme_creds)
+        .and_then(|creds| {
+            Ok(serde_json::from_str::<instant_acme::AccountCredentials>(
+                &creds,
+            )?)
+        })
+        .and_then(|creds| Ok(instant_acme::Account::from_credentials(creds)?));
+    match account {
+        Ok(_) => println!("  ACME account: credentials can be read"),
+        Err(e) => {
+            println!("  ACME account: {e:#}");
+            ok = false;
+        }
+    }
+
+    let dns_creds = state.secrets.open(dns_creds);
+    let dns_result = match (DnsProviderType::from_str(&dns_provider), &dns_creds) {
+        (Ok(provider_type), Ok(creds)) => {
+            crate::dns::validate_creds(provider_type, creds.clone()).await
+        }
+        (Err(e), _) => Err(e.into()),
+        (_, Err(e)) => Err(eyre!("{e:#}")),
+    };
+    match &dns_result {
+        Ok(()) => println!("  DNS provider: credentials work"),
+        Err(e) => {
+            println!("  DNS provider: {e:#}");
+            ok = false;
+        }
+    }
+
+    let mut staging_cert = None;
+    if staging {
+        if let (Ok(()), Ok(creds)) = (&dns_result, dns_creds) {
+            match issue_staging_certificate(state, &subdomain, &dns_provider, creds).await {
+                Ok(cert) => {
+                    let serial = cert.info()?.serial;
+                    println!("  Staging certificate: issued {serial}");
+                    staging_cert = Some(cert);
+                }
+                Err(e) => {
+                    println!("  Staging certificate: {e:#}");
+                    ok = false;
+                }
+            }
+        } else {
+            println!("  Staging certificate: skipped, since the DNS provider doesn't work");
+        }
+    }
+
+    let cert = staging_cert.as_ref().or(current.as_ref());
+    ok &= plan_endpoints(state, &subdomain, endpoints, options, cert).await;
+
+    if ok {
+        Ok(())
+    } else {
+        Err(eyre!("The dry run found problems with {subdomain}"))
+    }
+}
+
+/// Issue a certificate from the staging environment with a throwaway account, so that the real
+/// account's rate limits aren't touched.
+async fn issue_staging_certificate(
+    state: &Arc<State>,
+    subdomain: &str,
+    dns_provider: &str,
+    dns_creds: String,
+) -> Result<Certificate> {
+    let dns_provider_type = DnsProviderType::from_str(dns_provider)?;
+    let dns_provider =
+        crate::dns::get_dns_provider(dns_provider_type, subdomain, dns_creds).await?;
+
+    let account = instant_acme::Account::create(
+        &instant_acme::NewAccount {
+            contact: &[],
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        AcmeProvider::LetsEncryptStaging.url(),
+    )
+    .await
+    .wrap_err("Failed to create a staging account")?;
+
+    crate::acme::get_certificate(state.clone(), dns_provider, account, subdomain.to_string()).await
+}
+
+/// Check each host's credentials and print what deploying would change there. Returns whether
+/// every host passed.
+pub(super) async fn plan_endpoints(
+    state: &Arc<State>,
+    subdomain: &str,
+    endpoints: Vec<DbObject>,
+    options: DeployOptions,
+    cert: Option<&Certificate>,
+) -> bool {
+    if endpoints.is_empty() {
+        println!("  {subdomain} has no hosts to deploy to");
+        return false;
+    }
+
+    let mut ok = true;
+    for endpoint in endpoints {
+        let result = plan_endpoint(state, subdomain, &endpoint, options.clone(), cert).await;
+        match result {
+            Ok(plan) => {
+                for line in plan {
+                    println!("  {}: {line}", endpoint.name);
+                }
+            }
+            Err(e) => {
+                println!("  {}: {e:#}", endpoint.name);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
+
+async fn plan_endpoint(
+    state: &Arc<State>,
+    subdomain: &str,
+    endpoint: &DbObject,
+    options: DeployOptions,
+    cert: Option<&Certificate>,
+) -> Result<Vec<String>> {
+    let creds = state.secrets.open(endpoint.creds.clone())?;
+    let deployer_type = EndpointProviderType::from_str(&endpoint.provider)?;
+
+    crate::deploy::validate_creds(deployer_type.clone(), creds.clone())
+        .await
+        .wrap_err("The credentials don't work")?;
+
+    let deployer = crate::deploy::create_deployer(
+        state.clone(),
+        deployer_type,
+        subdomain.to_string(),
+        creds,
+        options,
+    )
+    .await?;
+    deployer.plan_deploy(cert, false).await
+}
@@ -1,32 +1,78 @@
 use std::sync::Arc;
 
 use clap::Args;
-use eyre::Result;
-use rusqlite::params;
+use eyre::{eyre, Result};
+use rusqlite::{params, OptionalExtension};
 
-use crate::{cmd::State, db::PoolExtInteract};
+use crate::{
+    cli::{is_interactive, select_object, select_objects},
+    cmd::State,
+    db::{DbObject, PoolExtInteract, PoolExtTransaction},
+};
 
 #[derive(Debug, Args)]
 pub struct EditArgs {
     /// The subdomain to edit
     subdomain: String,
+
+    /// The ACME account to issue certificates with
+    #[clap(long)]
+    account: Option<String>,
+
+    /// The DNS provider that manages this domain
+    #[clap(long)]
+    dns: Option<String>,
+
+    /// The hosts that contain the content for this subdomain, replacing the current ones. Repeat
+    /// this to deploy to several hosts.
+    #[clap(long, conflicts_with_all = ["add_endpoint", "remove_endpoint"])]
+    endpoint: Vec<String>,
+
+    /// Add a host to deploy the certificate to
+    #[clap(long)]
+    add_endpoint: Vec<String>,
+
+    /// Stop deploying the certificate to a host
+    #[clap(long)]
+    remove_endpoint: Vec<String>,
+}
+
+/// Like [select_object], but when not running interactively a missing option keeps the current
+/// value instead of being an error.
+fn select_or_keep(
+    objects: &[DbObject],
+    name: Option<&str>,
+    flag: &str,
+    prompt: &str,
+    current: usize,
+) -> Result<usize> {
+    if name.is_none() && !is_interactive() {
+        Ok(current)
+    } else {
+        select_object(objects, name, flag, prompt, current)
+    }
 }
 
 pub async fn run(state: Arc<State>, args: EditArgs) -> Result<()> {
     let objects = crate::db::get_all_objects(&state).await?;
 
     let s = args.subdomain.clone();
-    let (acme_account, dns_provider, endpoint) = state
+    let (acme_account, dns_provider, endpoints) = state
         .pool
         .interact(move |conn| {
-            let mut stmt = conn.prepare_cached(
-                "SELECT acme_account, dns_provider, endpoint FROM subdomains WHERE name=?",
-            )?;
-
-            let result: (i64, i64, i64) =
-                stmt.query_row([s], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
-
-            Ok::<_, eyre::Report>(result)
+            let mut stmt = conn
+                .prepare_cached("SELECT acme_account, dns_provider FROM subdomains WHERE name=?")?;
+
+            let (acme_account, dns_provider): (i64, i64) = stmt
+                .query_row([&s], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?
+                .ok_or_else(|| eyre!("No subdomain named {s} was found"))?;
+            let endpoints = crate::db::query_subdomain_endpoints(conn, &s)?
+                .into_iter()
+                .map(|e| e.id)
+                .collect::<Vec<_>>();
+
+            Ok::<_, eyre::Report>((acme_account, dns_provider, endpoints))
         })
         .await?;
 
@@ -42,66 +88,91 @@ pub async fn run(state: Arc<State>, args: EditArgs) -> Result<()> {
         .position(|a| a.id == dns_provider)
         .unwrap();
 
-    let active_endpoint_idx = objects
-        .endpoints
-        .iter()
-        .position(|a| a.id == endpoint)
-        .unwrap();
+    let new_acme_account_idx = select_or_keep(
+        &objects.acme_accounts,
+        args.account.as_deref(),
+        "--account",
+        "Select an ACME account",
+        active_account_idx,
+    )?;
+
+    let new_dns_provider_idx = select_or_keep(
+        &objects.dns_providers,
+        args.dns.as_deref(),
+        "--dns",
+        "Select a DNS provider",
+        active_dns_provider_idx,
+    )?;
+
+    let new_endpoint_ids = if !args.add_endpoint.is_empty() || !args.remove_endpoint.is_empty() {
+        let find = |name: &String| {
+            select_object(&objects.endpoints, Some(name), "--endpoint", "", 0)
+                .map(|idx| objects.endpoints[idx].id)
+        };
 
-    let new_acme_account_idx = dialoguer::Select::new()
-        .items(
-            &objects
-                .acme_accounts
-                .iter()
-                .map(|i| i.name.as_str())
-                .collect::<Vec<_>>(),
-        )
-        .with_prompt("Select an ACME account")
-        .default(active_account_idx)
-        .interact()?;
-
-    let new_dns_provider_idx = dialoguer::Select::new()
-        .items(
-            &objects
-                .dns_providers
-                .iter()
-                .map(|i| i.name.as_str())
-                .collect::<Vec<_>>(),
-        )
-        .with_prompt("Select a DNS provider")
-        .default(active_dns_provider_idx)
-        .interact()?;
-
-    let new_endpoint_idx = dialoguer::Select::new()
-        .items(
-            &objects
-                .endpoints
-                .iter()
-                .map(|i| i.name.as_str())
-                .collect::<Vec<_>>(),
-        )
-        .with_prompt("Select a host")
-        .default(active_endpoint_idx)
-        .interact()?;
+        let mut ids = endpoints.clone();
+        for name in &args.add_endpoint {
+            let id = find(name)?;
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        for name in &args.remove_endpoint {
+            let id = find(name)?;
+            ids.retain(|e| *e != id);
+        }
+        ids
+    } else if args.endpoint.is_empty() && !is_interactive() {
+        endpoints.clone()
+    } else {
+        let current = objects
+            .endpoints
+            .iter()
+            .map(|e| endpoints.contains(&e.id))
+            .collect::<Vec<_>>();
+        select_objects(
+            &objects.endpoints,
+            &args.endpoint,
+            "--endpoint",
+            "Select the hosts",
+            &current,
+        )?
+        .into_iter()
+        .map(|idx| objects.endpoints[idx].id)
+        .collect()
+    };
+
+    if new_endpoint_ids.is_empty() {
+        return Err(eyre!(
+            "A subdomain needs at least one host. Add another before removing the last one."
+        ));
+    }
 
     let new_acme_account_id = objects.acme_accounts[new_acme_account_idx].id;
     let new_dns_provider_id = objects.dns_providers[new_dns_provider_idx].id;
-    let new_endpoint_id = objects.endpoints[new_endpoint_idx].id;
 
     let clear_cert = new_acme_account_idx != active_account_idx;
 
-    state.pool.interact(move |conn| {
-        let query = if clear_cert {
-            "UPDATE subdomains SET acme_account=?, dns_provider=?, endpoint=?, expires=0 WHERE name=?"
-        } else {
-            "UPDATE subdomains SET acme_account=?, dns_provider=?, endpoint=? WHERE name=?"
-        };
-
-        let mut stmt = conn.prepare_cached(query)?;
-        stmt.execute(params![new_acme_account_id, new_dns_provider_id, new_endpoint_id, args.subdomain])?;
-
-        Ok::<_, eyre::Report>(())
-    }).await?;
+    state
+        .pool
+        .transaction(move |tx| {
+            let query = if clear_cert {
+                "UPDATE subdomains SET acme_account=?, dns_provider=?, expires=0 WHERE name=?"
+            } else {
+                "UPDATE subdomains SET acme_account=?, dns_provider=? WHERE name=?"
+            };
+
+            let mut stmt = tx.prepare_cached(query)?;
+            stmt.execute(params![
+                new_acme_account_id,
+                new_dns_provider_id,
+                args.subdomain
+            ])?;
+            crate::db::link_endpoints(tx, &args.subdomain, &new_endpoint_ids)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await?;
 
     println!("Done!");
 
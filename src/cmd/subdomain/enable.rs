@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::{eyre, Result};
+
+use crate::{cmd::State, db::PoolExtInteract};
+
+#[derive(Debug, Args)]
+pub struct EnableArgs {
+    /// The subdomain to enable
+    subdomain: String,
+}
+
+pub async fn run(state: Arc<State>, args: EnableArgs) -> Result<()> {
+    let s = args.subdomain.clone();
+    let updated = state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "UPDATE subdomains SET enabled=true, disabled_reason=NULL, reenable_at=NULL WHERE name=?",
+            )?;
+            let updated = stmt.execute([s])?;
+            Ok::<_, eyre::Report>(updated)
+        })
+        .await?;
+
+    if updated == 0 {
+        return Err(eyre!("No subdomain named {} was found", args.subdomain));
+    }
+
+    println!("Enabled renewals for {}", args.subdomain);
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Args;
+use eyre::{eyre, Result};
+use p12_keystore::{EncryptionAlgorithm, KeyStore, KeyStoreEntry, MacAlgorithm, PrivateKeyChain};
+
+use crate::{
+    certificate::normalize_serial,
+    cli::value_or_prompt,
+    cmd::State,
+    files::{write_atomic, Owner, PRIVATE_MODE, PUBLIC_MODE},
+    Certificate,
+};
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// The subdomain to export the certificate for
+    subdomain: String,
+
+    /// The directory to write the files to. Defaults to a directory named after the subdomain.
+    #[clap(long)]
+    out: Option<PathBuf>,
+
+    /// The serial number of the certificate to export, as shown by `subdomain history`. This
+    /// defaults to the current certificate.
+    #[clap(long)]
+    serial: Option<String>,
+
+    /// Also write combined.pem, which holds the private key followed by the full chain
+    #[clap(long)]
+    combined: bool,
+
+    /// Also write a password-protected PKCS#12 file, cert.p12
+    #[clap(long)]
+    pkcs12: bool,
+
+    /// The password for the PKCS#12 file. This is prompted for if not given.
+    #[clap(long, env = "RSR_PKCS12_PASSWORD")]
+    pkcs12_password: Option<String>,
+
+    /// Encrypt the PKCS#12 file with 3DES and SHA-1, for older systems that can't read the
+    /// default AES-256 encryption
+    #[clap(long, requires = "pkcs12")]
+    legacy_pkcs12: bool,
+}
+
+/// Load the certificate to export, from the history if a serial number was given.
+async fn load_certificate(state: &Arc<State>, args: &ExportArgs) -> Result<Certificate> {
+    let stored = match args.serial.as_deref() {
+        Some(serial) => {
+            let serial = normalize_serial(serial);
+            crate::db::list_certificates(state, args.subdomain.clone())
+                .await?
+                .into_iter()
+                .find(|c| c.serial == serial)
+                .ok_or_else(|| {
+                    eyre!(
+                        "No certificate with serial {serial} was found for {}",
+                        args.subdomain
+                    )
+                })?
+                .cert
+        }
+        None => crate::db::get_last_cert(state, args.subdomain.clone())
+            .await?
+            .ok_or_else(|| eyre!("This subdomain does not yet have a certificate"))?,
+    };
+
+    Ok(serde_json::from_str(&state.secrets.open(stored)?)?)
+}
+
+fn build_pkcs12(cert: &Certificate, alias: &str, password: &str, legacy: bool) -> Result<Vec<u8>> {
+    let chain = x509_parser::pem::Pem::iter_from_buffer(cert.cert.as_bytes())
+        .map(|pem| Ok(p12_keystore::Certificate::from_der(&pem?.contents)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (_, key) = x509_parser::pem::parse_x509_pem(cert.key.as_bytes())
+        .map_err(|e| eyre!("Failed to read private key: {e}"))?;
+
+    // The local key ID links the key to its certificate. By convention it's the SHA-1 hash of
+    // the certificate.
+    let local_key_id =
+        ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &cert.leaf_der()?);
+
+    let mut keystore = KeyStore::new();
+    keystore.add_entry(
+        alias,
+        KeyStoreEntry::PrivateKeyChain(PrivateKeyChain::new(
+            key.contents,
+            local_key_id.as_ref(),
+            chain,
+        )),
+    );
+
+    let mut writer = keystore.writer(password);
+    if legacy {
+        writer = writer
+            .encryption_algorithm(EncryptionAlgorithm::PbeWithShaAnd3KeyTripleDesCbc)
+            .mac_algorithm(MacAlgorithm::HmacSha1);
+    }
+
+    Ok(writer.write()?)
+}
+
+pub async fn run(state: Arc<State>, args: ExportArgs) -> Result<()> {
+    let cert = load_certificate(&state, &args).await?;
+
+    let pkcs12_password = if args.pkcs12 {
+        Some(value_or_prompt(
+            args.pkcs12_password.clone(),
+            "--pkcs12-password",
+            || {
+                Ok(dialoguer::Password::new()
+                    .with_prompt("PKCS#12 password")
+                    .with_confirmation("Confirm password", "The passwords don't match")
+                    .interact()?)
+            },
+        )?)
+    } else {
+        None
+    };
+
+    let out = args
+        .out
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(&args.subdomain));
+    std::fs::create_dir_all(&out)?;
+
+    let mut files: Vec<(&str, Vec<u8>, u32)> = vec![
+        (
+            "cert.pem",
+            cert.get_leaf_certificate().as_bytes().to_vec(),
+            PUBLIC_MODE,
+        ),
+        (
+            "chain.pem",
+            cert.get_certificate_chain().as_bytes().to_vec(),
+            PUBLIC_MODE,
+        ),
+        ("fullchain.pem", cert.cert.as_bytes().to_vec(), PUBLIC_MODE),
+        ("privkey.pem", cert.key.as_bytes().to_vec(), PRIVATE_MODE),
+    ];
+
+    if args.combined {
+        files.push((
+            "combined.pem",
+            format!("{}{}", cert.key, cert.cert).into_bytes(),
+            PRIVATE_MODE,
+        ));
+    }
+
+    if let Some(password) = pkcs12_password {
+        let pkcs12 = build_pkcs12(&cert, &args.subdomain, &password, args.legacy_pkcs12)?;
+        files.push(("cert.p12", pkcs12, PRIVATE_MODE));
+    }
+
+    for (name, contents, mode) in files {
+        let path = out.join(name);
+        write_atomic(&path, &contents, mode, Owner::default())?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::Result;
+
+use crate::{
+    cli::{describe_expiration, format_timestamp, print_table},
+    cmd::State,
+    db::{CertificateRecord, DeployStatus},
+};
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// The subdomain to show the certificates for
+    subdomain: String,
+}
+
+/// Describe how deploying a certificate went, either overall or to one host.
+pub fn describe_deploy(
+    status: DeployStatus,
+    deployed_at: Option<i64>,
+    error: Option<&str>,
+) -> String {
+    match status {
+        DeployStatus::Pending => "not deployed".to_string(),
+        DeployStatus::Deployed => match deployed_at {
+            Some(deployed_at) => format!("deployed {}", format_timestamp(deployed_at)),
+            None => "deployed".to_string(),
+        },
+        DeployStatus::Failed => match error {
+            Some(error) => format!("failed: {error}"),
+            None => "failed".to_string(),
+        },
+    }
+}
+
+pub fn describe_status(cert: &CertificateRecord) -> String {
+    let mut status = describe_deploy(
+        cert.deploy_status,
+        cert.deployed_at,
+        cert.deploy_error.as_deref(),
+    );
+
+    if let Some(revoked_at) = cert.revoked_at {
+        status = format!("revoked {}", format_timestamp(revoked_at));
+        if let Some(reason) = cert.revocation_reason.as_deref() {
+            status.push_str(&format!(" ({reason})"));
+        }
+    }
+
+    if cert.current {
+        status.push_str(" (current)");
+    }
+
+    status
+}
+
+pub async fn run(state: Arc<State>, args: HistoryArgs) -> Result<()> {
+    // Make sure the subdomain exists, so that a typo doesn't look like an empty history.
+    crate::db::get_subdomain(&state, args.subdomain.clone()).await?;
+
+    let certs = crate::db::list_certificates(&state, args.subdomain).await?;
+    if certs.is_empty() {
+        println!("No certificates have been issued for this subdomain");
+        return Ok(());
+    }
+
+    let rows = certs
+        .iter()
+        .map(|c| {
+            vec![
+                c.serial.clone(),
+                c.issuer.clone(),
+                format_timestamp(c.not_before),
+                describe_expiration(Some(c.not_after)),
+                c.key_type.clone(),
+                match (&c.acme_account, c.imported) {
+                    (Some(account), _) => account.clone(),
+                    (None, true) => "imported".to_string(),
+                    (None, false) => "deleted".to_string(),
+                },
+                describe_status(c),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(
+        &[
+            "Serial", "Issuer", "Issued", "Expires", "Key", "Account", "Status",
+        ],
+        &rows,
+    );
+
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::{ArgGroup, Args};
+use eyre::{eyre, Result, WrapErr};
+use time::OffsetDateTime;
+
+use crate::{
+    certificate::CertificateInfo, cli::describe_expiration, cmd::State, deploy::DeployOptions,
+    Certificate,
+};
+
+use super::{
+    deploy_to_endpoints,
+    new::{insert_subdomain, select_linked_objects, LinkedObjects},
+    subdomain_deployers,
+};
+
+#[derive(Debug, Args)]
+#[clap(group(ArgGroup::new("source").required(true).args(["cert", "certbot_dir"])))]
+pub struct ImportArgs {
+    /// The subdomain to import the certificate for. It is added if it doesn't exist yet.
+    #[clap(
+        required_unless_present = "certbot_dir",
+        conflicts_with = "certbot_dir"
+    )]
+    subdomain: Option<String>,
+
+    /// The certificate file, which may also contain the rest of the chain
+    #[clap(long, requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// A file containing the rest of the chain, if it isn't in the certificate file
+    #[clap(long, requires = "cert")]
+    chain: Option<PathBuf>,
+
+    /// The private key file, in PEM format
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Import every certificate in a certbot `live` directory, such as /etc/letsencrypt/live.
+    /// Each directory is imported as the subdomain it's named after.
+    #[clap(long)]
+    certbot_dir: Option<PathBuf>,
+
+    /// The ACME account to renew new subdomains with
+    #[clap(long)]
+    account: Option<String>,
+
+    /// The DNS provider that manages new subdomains
+    #[clap(long)]
+    dns: Option<String>,
+
+    /// The host that contains the content for new subdomains. Repeat this to deploy to several
+    /// hosts.
+    #[clap(long)]
+    endpoint: Vec<String>,
+
+    /// Deploy the imported certificates to their hosts. Without this, the certificates are
+    /// assumed to be in place already.
+    #[clap(long)]
+    deploy: bool,
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+    let mut contents = contents.replace("\r\n", "\n");
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+fn read_certificate(cert: &Path, chain: Option<&Path>, key: &Path) -> Result<Certificate> {
+    let mut cert = read_file(cert)?;
+    if let Some(chain) = chain {
+        cert.push_str(&read_file(chain)?);
+    }
+
+    Ok(Certificate {
+        cert,
+        key: read_file(key)?,
+    })
+}
+
+/// Check that a certificate can be used for a subdomain, and return its details.
+fn validate(subdomain: &str, cert: &Certificate) -> Result<CertificateInfo> {
+    let info = cert.info()?;
+    cert.check_key()?;
+
+    if !cert.covers(subdomain)? {
+        return Err(eyre!(
+            "The certificate is for {}, not {subdomain}",
+            cert.dns_names()?.join(", ")
+        ));
+    }
+
+    if info.not_after <= OffsetDateTime::now_utc().unix_timestamp() {
+        return Err(eyre!("The certificate has expired"));
+    }
+
+    Ok(info)
+}
+
+/// Find the certificates in a certbot `live` directory. Certbot adds a numeric suffix to the
+/// directory name when it creates a second certificate for the same name, so the suffix is
+/// removed if the certificate doesn't cover the full directory name.
+fn read_certbot_dir(dir: &Path) -> Result<Vec<(String, Result<Certificate>)>> {
+    let mut entries = std::fs::read_dir(dir)
+        .wrap_err_with(|| format!("Failed to read {}", dir.display()))?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut found = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        if !path.join("fullchain.pem").exists() {
+            continue;
+        }
+
+        let dir_name = entry.file_name().to_string_lossy().to_string();
+        let cert = read_certificate(&path.join("fullchain.pem"), None, &path.join("privkey.pem"));
+
+        let name = match (&cert, dir_name.rsplit_once('-')) {
+            (Ok(cert), Some((base, suffix)))
+                if suffix.len() == 4
+                    && suffix.chars().all(|c| c.is_ascii_digit())
+                    && !cert.covers(&dir_name).unwrap_or(false) =>
+            {
+                base.to_string()
+            }
+            _ => dir_name,
+        };
+
+        found.push((name, cert));
+    }
+
+    Ok(found)
+}
+
+async fn import_one(
+    state: &Arc<State>,
+    subdomain: &str,
+    cert: Certificate,
+    info: CertificateInfo,
+    deploy: bool,
+) -> Result<()> {
+    let expires = info.not_after;
+    let stored = state.secrets.seal(serde_json::to_string(&cert)?)?;
+    crate::db::save_certificate(state, subdomain.to_string(), None, info, stored).await?;
+    println!(
+        "Imported {subdomain}, expiring {}",
+        describe_expiration(Some(expires))
+    );
+
+    if deploy {
+        let deployers =
+            subdomain_deployers(state, subdomain.to_string(), None, DeployOptions::default())
+                .await?;
+        deploy_to_endpoints(state, subdomain, cert, &deployers, false).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn run(state: Arc<State>, args: ImportArgs) -> Result<()> {
+    let certs = match (&args.certbot_dir, &args.subdomain, &args.cert, &args.key) {
+        (Some(dir), _, _, _) => read_certbot_dir(dir)?,
+        (None, Some(subdomain), Some(cert), Some(key)) => {
+            let cert = read_certificate(cert, args.chain.as_deref(), key)?;
+            vec![(subdomain.clone(), Ok(cert))]
+        }
+        _ => {
+            return Err(eyre!(
+                "Pass a subdomain with --cert and --key, or --certbot-dir"
+            ))
+        }
+    };
+
+    if certs.is_empty() {
+        return Err(eyre!("No certificates were found"));
+    }
+
+    let existing = crate::db::list_subdomains(&state)
+        .await?
+        .into_iter()
+        .map(|s| s.name)
+        .collect::<HashSet<_>>();
+
+    let mut objects: Option<LinkedObjects> = None;
+    let mut errored = false;
+    for (subdomain, cert) in certs {
+        let result = async {
+            let cert = cert?;
+            let info = validate(&subdomain, &cert)?;
+
+            if !existing.contains(&subdomain) {
+                if objects.is_none() {
+                    let _hider = state.hide_progress();
+                    objects = Some(
+                        select_linked_objects(
+                            &state,
+                            args.account.as_deref(),
+                            args.dns.as_deref(),
+                            &args.endpoint,
+                        )
+                        .await?,
+                    );
+                }
+
+                insert_subdomain(&state, subdomain.clone(), objects.as_ref().unwrap()).await?;
+            }
+
+            import_one(&state, &subdomain, cert, info, args.deploy).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            eprintln!("Failed to import {subdomain}: {e:#}");
+            errored = true;
+        }
+    }
+
+    if errored {
+        Err(eyre!("Encountered errors"))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::Result;
+
+use crate::{
+    cli::{describe_expiration, format_timestamp, print_table},
+    cmd::State,
+    db::SubdomainInfo,
+};
+
+#[derive(Debug, Args)]
+pub struct ListArgs {}
+
+pub fn describe_cert(subdomain: &SubdomainInfo) -> String {
+    if subdomain.has_cert {
+        describe_expiration(subdomain.expires)
+    } else {
+        "no certificate".to_string()
+    }
+}
+
+pub fn describe_status(subdomain: &SubdomainInfo) -> String {
+    if subdomain.enabled {
+        return "enabled".to_string();
+    }
+
+    let mut status = "disabled".to_string();
+    if let Some(reenable_at) = subdomain.reenable_at {
+        status.push_str(" until ");
+        status.push_str(&format_timestamp(reenable_at));
+    }
+
+    if let Some(reason) = subdomain.disabled_reason.as_deref() {
+        status.push_str(": ");
+        status.push_str(reason);
+    }
+
+    status
+}
+
+pub async fn run(state: Arc<State>, _args: ListArgs) -> Result<()> {
+    let subdomains = crate::db::list_subdomains(&state).await?;
+    if subdomains.is_empty() {
+        println!("No subdomains found");
+        return Ok(());
+    }
+
+    let rows = subdomains
+        .iter()
+        .map(|s| {
+            vec![
+                s.name.clone(),
+                s.acme_account.clone(),
+                s.dns_provider.clone(),
+                s.endpoints.join(", "),
+                describe_cert(s),
+                describe_status(s),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(
+        &["Name", "Account", "DNS", "Hosts", "Expires", "Status"],
+        &rows,
+    );
+
+    Ok(())
+}
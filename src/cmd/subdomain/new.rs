@@ -5,26 +5,84 @@ use eyre::{eyre, Result};
 use rusqlite::params;
 
 use crate::{
-    cli::get_unique_name,
-    db::{DbObjects, PoolExtInteract},
+    cli::{get_unique_name, is_interactive, select_object, select_objects},
+    db::{DbObject, DbObjects, PoolExtTransaction},
+    deploy::DeployOptions,
 };
 
-use super::{start_cert_process, State};
+use super::{
+    dry_run::{plan_renewal, DryRunArgs},
+    start_cert_process, State,
+};
 
 #[derive(Args, Debug)]
-pub struct NewSubdomainArgs {}
+pub struct NewSubdomainArgs {
+    /// The subdomain to add
+    #[clap(long)]
+    name: Option<String>,
+
+    /// The ACME account to issue certificates with
+    #[clap(long)]
+    account: Option<String>,
+
+    /// The DNS provider that manages this domain
+    #[clap(long)]
+    dns: Option<String>,
+
+    /// The host that contains the content for this subdomain. Repeat this to deploy the
+    /// certificate to several hosts.
+    #[clap(long)]
+    endpoint: Vec<String>,
+
+    /// The origin to serve from, if the host needs a new CDN endpoint created
+    #[clap(long)]
+    origin: Option<String>,
+
+    #[clap(flatten)]
+    dry_run: DryRunArgs,
+}
 
-pub async fn run(state: Arc<State>, _args: NewSubdomainArgs) -> Result<()> {
-    let hider = state.hide_progress();
+/// Like [select_object], but falls back to the default from the settings. When running
+/// non-interactively the default is used as-is, and otherwise it starts out selected.
+fn select_with_default(
+    objects: &[DbObject],
+    name: Option<&str>,
+    default: Option<&str>,
+    flag: &str,
+    prompt: &str,
+) -> Result<usize> {
+    let name = match name {
+        Some(name) => Some(name),
+        None if !is_interactive() => default,
+        None => None,
+    };
+
+    let default_idx = default
+        .and_then(|default| objects.iter().position(|o| o.name == default))
+        .unwrap_or(0);
+    select_object(objects, name, flag, prompt, default_idx)
+}
 
-    let subdomain =
-        get_unique_name(&state, "Which subdomain are you adding?", "subdomains").await?;
+/// The objects that a new subdomain links to.
+pub(super) struct LinkedObjects {
+    pub account: DbObject,
+    pub dns_provider: DbObject,
+    pub endpoints: Vec<DbObject>,
+}
 
+/// Pick the account, DNS provider, and hosts for a new subdomain, from the command line, the
+/// defaults in the settings, or a prompt.
+pub(super) async fn select_linked_objects(
+    state: &Arc<State>,
+    account: Option<&str>,
+    dns: Option<&str>,
+    endpoints: &[String],
+) -> Result<LinkedObjects> {
     let DbObjects {
         mut acme_accounts,
         mut dns_providers,
-        mut endpoints,
-    } = crate::db::get_all_objects(&state).await?;
+        endpoints: all_endpoints,
+    } = crate::db::get_all_objects(state).await?;
 
     if acme_accounts.is_empty() {
         return Err(eyre!("No ACME accounts found. Please create one first. You may want to use the `init` command."));
@@ -34,58 +92,130 @@ pub async fn run(state: Arc<State>, _args: NewSubdomainArgs) -> Result<()> {
         return Err(eyre!("No DNS providers found. Please create one first. You may want to use the `init` command."));
     }
 
-    if endpoints.is_empty() {
+    if all_endpoints.is_empty() {
         return Err(eyre!(
             "No hosts found. Please create one first. You may want to use the `init` command."
         ));
     }
 
-    let account_idx = dialoguer::Select::new()
-        .with_prompt("Which ACME provider do you want to use?")
-        .items(&acme_accounts.iter().map(|o| &o.name).collect::<Vec<_>>())
-        .default(0)
-        .interact()?;
+    let defaults = &state.settings.defaults;
+    let account_idx = select_with_default(
+        &acme_accounts,
+        account,
+        defaults.account.as_deref(),
+        "--account",
+        "Which ACME provider do you want to use?",
+    )?;
     let account = acme_accounts.drain(account_idx..).next().unwrap();
 
-    let dns_idx = dialoguer::Select::new()
-        .with_prompt("Which DNS provider manages this domain?")
-        .items(&dns_providers.iter().map(|o| &o.name).collect::<Vec<_>>())
-        .default(0)
-        .interact()?;
+    let dns_idx = select_with_default(
+        &dns_providers,
+        dns,
+        defaults.dns.as_deref(),
+        "--dns",
+        "Which DNS provider manages this domain?",
+    )?;
     let dns_provider = dns_providers.drain(dns_idx..).next().unwrap();
 
-    let endpoint_idx = dialoguer::Select::new()
-        .with_prompt("Which host contains the content for this subdomain?")
-        .items(&endpoints.iter().map(|o| &o.name).collect::<Vec<_>>())
-        .default(0)
-        .interact()?;
-    let endpoint = endpoints.drain(endpoint_idx..).next().unwrap();
-
-    drop(hider);
+    let endpoint_names = match (endpoints, defaults.endpoint.as_deref()) {
+        ([], Some(default)) if !is_interactive() => vec![default.to_string()],
+        _ => endpoints.to_vec(),
+    };
+    let default_endpoints = all_endpoints
+        .iter()
+        .map(|e| defaults.endpoint.as_deref() == Some(e.name.as_str()))
+        .collect::<Vec<_>>();
+    let endpoint_idxs = select_objects(
+        &all_endpoints,
+        &endpoint_names,
+        "--endpoint",
+        "Which hosts contain the content for this subdomain?",
+        &default_endpoints,
+    )?;
+    let endpoints = endpoint_idxs
+        .into_iter()
+        .map(|idx| all_endpoints[idx].clone())
+        .collect();
+
+    Ok(LinkedObjects {
+        account,
+        dns_provider,
+        endpoints,
+    })
+}
 
-    let s = subdomain.clone();
-    let account_id = account.id;
-    let dns_id = dns_provider.id;
-    let endpoint_id = endpoint.id;
-    state.pool.interact(move |conn| {
-            let mut stmt = conn.prepare_cached("INSERT INTO subdomains (name, acme_account, dns_provider, endpoint) VALUES (?, ?, ?, ?)")?;
-            stmt.execute(params![s, account_id, dns_id, endpoint_id])?;
+pub(super) async fn insert_subdomain(
+    state: &Arc<State>,
+    subdomain: String,
+    objects: &LinkedObjects,
+) -> Result<()> {
+    let account_id = objects.account.id;
+    let dns_id = objects.dns_provider.id;
+    let endpoint_ids = objects.endpoints.iter().map(|e| e.id).collect::<Vec<_>>();
+    state
+        .pool
+        .transaction(move |tx| {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO subdomains (name, acme_account, dns_provider) VALUES (?, ?, ?)",
+            )?;
+            stmt.execute(params![subdomain, account_id, dns_id])?;
+            crate::db::link_endpoints(tx, &subdomain, &endpoint_ids)?;
             Ok::<_, eyre::Report>(())
-        }).await?;
-
-    start_cert_process(
-        state,
-        super::Renewal {
-            subdomain,
-            acme_provider: account.provider,
-            acme_creds: account.creds,
-            dns_provider: dns_provider.provider,
-            dns_creds: dns_provider.creds,
-            endpoint_provider: endpoint.provider,
-            endpoint_creds: endpoint.creds,
-        },
+        })
+        .await
+}
+
+pub async fn run(state: Arc<State>, args: NewSubdomainArgs) -> Result<()> {
+    let hider = state.hide_progress();
+
+    let subdomain = get_unique_name(
+        &state,
+        args.name,
+        "Which subdomain are you adding?",
+        "subdomains",
+    )
+    .await?;
+
+    let objects = select_linked_objects(
+        &state,
+        args.account.as_deref(),
+        args.dns.as_deref(),
+        &args.endpoint,
     )
     .await?;
 
+    drop(hider);
+
+    if !args.dry_run.dry_run {
+        insert_subdomain(&state, subdomain.clone(), &objects).await?;
+    }
+    let LinkedObjects {
+        account,
+        dns_provider,
+        endpoints,
+    } = objects;
+
+    let renewal = super::Renewal {
+        subdomain,
+        acme_account: account.id,
+        acme_creds: account.creds,
+        dns_provider: dns_provider.provider,
+        dns_creds: dns_provider.creds,
+        endpoints,
+    };
+    let options = DeployOptions {
+        origin: args.origin,
+    };
+
+    if args.dry_run.dry_run {
+        println!(
+            "Would add {} and issue its first certificate",
+            renewal.subdomain
+        );
+        plan_renewal(&state, renewal, options, None, args.dry_run.staging).await?;
+    } else {
+        start_cert_process(state, renewal, options).await?;
+    }
+
     Ok(())
 }
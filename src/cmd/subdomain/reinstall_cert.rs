@@ -1,47 +1,77 @@
-use std::{str::FromStr, sync::Arc};
+use std::sync::Arc;
 
 use clap::Args;
 use eyre::{eyre, Result};
-use indicatif::ProgressBar;
 
-use crate::{db::PoolExtInteract, deploy::EndpointProviderType, Certificate};
+use crate::{deploy::DeployOptions, Certificate};
 
-use super::State;
+use super::{deploy_to_endpoints, dry_run::plan_endpoints, subdomain_deployers, State};
 
 #[derive(Args, Debug)]
 pub struct ReinstallCertArgs {
     /// The subdomain to reinstall the certificate for
     subdomain: String,
+
+    /// The origin to serve from, if the host needs a new CDN endpoint created
+    #[clap(long)]
+    origin: Option<String>,
+
+    /// Only reinstall on this host, instead of every host the subdomain uses
+    #[clap(long)]
+    endpoint: Option<String>,
+
+    /// Check each host's credentials and show what would change there, without deploying
+    /// anything
+    #[clap(long)]
+    dry_run: bool,
 }
 
 pub async fn run(state: Arc<State>, args: ReinstallCertArgs) -> Result<()> {
-    let s = args.subdomain.clone();
-    let (last_cert, provider, creds): (Option<String>, String, String) = state
-        .pool
-        .interact(move |conn| {
-            let mut stmt = conn.prepare_cached(
-                r##"SELECT last_cert, provider, creds
-            FROM subdomains sd
-            JOIN endpoints ep ON ep.id=sd.endpoint
-            WHERE sd.name=?"##,
-            )?;
-
-            let (last_cert, provider, creds) =
-                stmt.query_row([s], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
-
-            Ok::<_, eyre::Report>((last_cert, provider, creds))
-        })
-        .await?;
-
-    let last_cert =
-        last_cert.ok_or_else(|| eyre!("This subdomain does not yet have a certificate"))?;
-    let last_cert: Certificate = serde_json::from_str(&last_cert)?;
-
-    let deployer_type = EndpointProviderType::from_str(&provider)?;
-    let deployer =
-        crate::deploy::create_deployer(state.clone(), deployer_type, args.subdomain, creds)?;
-
-    deployer.deploy_certificate(last_cert, false).await?;
-
-    Ok(())
+    let last_cert = crate::db::get_last_cert(&state, args.subdomain.clone())
+        .await?
+        .ok_or_else(|| eyre!("This subdomain does not yet have a certificate"))?;
+    let last_cert: Certificate = serde_json::from_str(&state.secrets.open(last_cert)?)?;
+
+    if args.dry_run {
+        let mut endpoints = crate::db::subdomain_endpoints(&state, args.subdomain.clone()).await?;
+        if let Some(only) = &args.endpoint {
+            endpoints.retain(|e| &e.name == only);
+            if endpoints.is_empty() {
+                return Err(eyre!("{} does not use a host named {only}", args.subdomain));
+            }
+        }
+
+        println!(
+            "Would reinstall certificate {} for {}",
+            last_cert.info()?.serial,
+            args.subdomain
+        );
+        let options = DeployOptions {
+            origin: args.origin,
+        };
+        if !plan_endpoints(
+            &state,
+            &args.subdomain,
+            endpoints,
+            options,
+            Some(&last_cert),
+        )
+        .await
+        {
+            return Err(eyre!("The dry run found problems with {}", args.subdomain));
+        }
+        return Ok(());
+    }
+
+    let deployers = subdomain_deployers(
+        &state,
+        args.subdomain.clone(),
+        args.endpoint.as_deref(),
+        DeployOptions {
+            origin: args.origin,
+        },
+    )
+    .await?;
+
+    deploy_to_endpoints(&state, &args.subdomain, last_cert, &deployers, false).await
 }
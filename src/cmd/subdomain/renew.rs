@@ -2,66 +2,175 @@ use std::sync::Arc;
 
 use clap::Args;
 use eyre::{eyre, Result};
+use futures::StreamExt;
 use time::OffsetDateTime;
 
-use crate::{cmd::State, db::PoolExtInteract};
+use crate::{
+    cli::describe_expiration,
+    cmd::State,
+    db::{PoolExtInteract, PoolExtTransaction},
+    deploy::DeployOptions,
+    notify::{Event, RenewalResult},
+    Certificate,
+};
 
-use super::{start_cert_process, Renewal};
+use super::{dry_run::DryRunArgs, start_cert_process, DeployFailure, Renewal};
 
 #[derive(Debug, Args)]
 pub struct RenewArgs {
     /// A specific subdomain to renew
     subdomain: Option<String>,
 
-    /// Renew the subdomain's certificate even if it's far from expiration. This is only honored
-    /// when the `subdomain` option is provided.
+    /// Renew the subdomain's certificate even if it's far from expiration or disabled. This is
+    /// only honored when the `subdomain` option is provided.
     ///
     /// Use this with care, as providers like LetsEncrypt have very strict rate limits on how
     /// often you can renew your certificate.
     #[clap(long, default_value_t = false)]
     force: bool,
+
+    #[clap(flatten)]
+    dry_run: DryRunArgs,
+}
+
+fn renewal_threshold(state: &State) -> i64 {
+    (OffsetDateTime::now_utc() + time::Duration::days(state.settings.renewal_threshold_days))
+        .unix_timestamp()
+}
+
+/// The disabled subdomains whose re-enable date has passed.
+fn query_reenable_due(conn: &rusqlite::Connection, now: i64) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare_cached("SELECT name FROM subdomains WHERE NOT enabled AND reenable_at <= ?")?;
+    let names = stmt
+        .query_map([now], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+    Ok(names)
+}
+
+/// Enable any disabled subdomains whose re-enable date has passed. With `dry_run`, only say
+/// which would be.
+async fn reenable_due(state: &Arc<State>, dry_run: bool) -> Result<()> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if dry_run {
+        let names = state
+            .pool
+            .interact(move |conn| query_reenable_due(conn, now))
+            .await?;
+        for name in names {
+            println!("Would re-enable {name}");
+        }
+        return Ok(());
+    }
+
+    let reenabled = state
+        .pool
+        .transaction(move |tx| {
+            let names = query_reenable_due(tx, now)?;
+
+            tx.execute(
+                r##"UPDATE subdomains SET enabled=true, disabled_reason=NULL, reenable_at=NULL
+                WHERE NOT enabled AND reenable_at <= ?"##,
+                [now],
+            )?;
+
+            Ok::<_, eyre::Report>(names)
+        })
+        .await?;
+
+    for name in reenabled {
+        println!("Re-enabled {name}");
+    }
+
+    Ok(())
 }
 
-fn renewal_threshold() -> i64 {
-    (OffsetDateTime::now_utc() + time::Duration::days(30)).unix_timestamp()
+/// Renew a subdomain, record whether it worked, and send an alert right away if it didn't.
+async fn renew_and_record(
+    state: Arc<State>,
+    renewal: Renewal,
+) -> (Result<()>, Option<RenewalResult>) {
+    let subdomain = renewal.subdomain.clone();
+    let result = tokio::task::spawn(start_cert_process(
+        state.clone(),
+        renewal,
+        DeployOptions::default(),
+    ))
+    .await
+    .map_err(eyre::Report::from)
+    .and_then(|result| result);
+
+    let error = result.as_ref().err().map(|e| format!("{e:#}"));
+    let recorded = match crate::db::record_renewal_attempt(&state, subdomain.clone(), error).await {
+        Ok((failures, expires)) => Some(RenewalResult::new(
+            subdomain,
+            result.as_ref().err(),
+            failures,
+            expires,
+        )),
+        Err(e) => {
+            eprintln!("Failed to record the renewal of {subdomain}: {e:#}");
+            None
+        }
+    };
+
+    // A deploy failure has already been announced, so it doesn't get a second alert. It still
+    // counts as a failure in the summary.
+    let notified = result
+        .as_ref()
+        .is_err_and(|e| e.downcast_ref::<DeployFailure>().is_some());
+    if let Some(recorded) = recorded.as_ref().filter(|r| r.error.is_some() && !notified) {
+        let event = if recorded.expiring_soon(state.settings.notify.expiry_warning_days) {
+            Event::ExpiringSoon(recorded)
+        } else {
+            Event::RenewalFailed(recorded)
+        };
+        crate::notify::send(&state, event).await;
+    }
+
+    (result, recorded)
 }
 
-async fn renew_any_needed(state: Arc<State>) -> Result<()> {
+async fn renew_any_needed(state: Arc<State>, dry_run: &DryRunArgs) -> Result<()> {
+    reenable_due(&state, dry_run.dry_run).await?;
+
+    let threshold = renewal_threshold(&state);
+    let now = OffsetDateTime::now_utc().unix_timestamp();
     let renewals = state
         .pool
         .interact(move |conn| {
             let mut stmt = conn.prepare_cached(
                 r##"
             SELECT sd.name,
-                aa.provider as acme_provider,
+                sd.acme_account,
                 aa.creds as acme_creds,
                 dp.provider as dns_provider,
-                dp.creds as dns_creds,
-                ep.provider as endpoint_provider,
-                ep.creds as endpoint_creds
+                dp.creds as dns_creds
             FROM subdomains sd
             JOIN acme_accounts aa ON aa.id=sd.acme_account
             JOIN dns_providers dp ON dp.id=sd.dns_provider
-            JOIN endpoints ep ON ep.id=sd.endpoint
-            WHERE sd.enabled AND sd.last_cert IS NOT NULL AND sd.expires < ?
+            WHERE (sd.enabled OR sd.reenable_at <= ?)
+                AND sd.last_cert IS NOT NULL AND sd.expires < ?
         "##,
             )?;
 
-            let threshold = renewal_threshold();
-            let results = stmt
-                .query_map([threshold], |row| {
+            let mut results = stmt
+                .query_map([now, threshold], |row| {
                     Ok(Renewal {
                         subdomain: row.get(0)?,
-                        acme_provider: row.get(1)?,
+                        acme_account: row.get(1)?,
                         acme_creds: row.get(2)?,
                         dns_provider: row.get(3)?,
                         dns_creds: row.get(4)?,
-                        endpoint_provider: row.get(5)?,
-                        endpoint_creds: row.get(6)?,
+                        endpoints: Vec::new(),
                     })
                 })?
                 .collect::<Result<Vec<_>, rusqlite::Error>>()?;
 
+            for renewal in &mut results {
+                renewal.endpoints = crate::db::query_subdomain_endpoints(conn, &renewal.subdomain)?;
+            }
+
             Ok::<_, eyre::Report>(results)
         })
         .await?;
@@ -69,22 +178,35 @@ async fn renew_any_needed(state: Arc<State>) -> Result<()> {
     let mut errored = false;
     if renewals.is_empty() {
         println!("All certificates up to date");
+    } else if dry_run.dry_run {
+        for renewal in renewals {
+            if let Err(e) = plan_renewal(&state, renewal, dry_run.staging).await {
+                eprintln!("{e:#}");
+                errored = true;
+            }
+        }
     } else {
-        let tasks = renewals
-            .into_iter()
+        let concurrency = state.settings.concurrency.max(1);
+        let results = futures::stream::iter(renewals)
             .map(|r| {
                 let state = state.clone();
-                tokio::task::spawn(start_cert_process(state, r))
+                let subdomain = r.subdomain.clone();
+                async move { (subdomain, renew_and_record(state, r).await) }
             })
-            .collect::<Vec<_>>();
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
 
-        let results = futures::future::join_all(tasks).await;
-        for r in results {
-            if let Err(e) = r {
-                eprintln!("Error renewing certificate: {}", e);
+        let mut summary = Vec::with_capacity(results.len());
+        for (subdomain, (result, recorded)) in results {
+            summary.extend(recorded);
+            if let Err(error) = result {
+                eprintln!("Error renewing certificate for {subdomain}: {error:#}");
                 errored = true;
             }
         }
+
+        crate::notify::send(&state, Event::Summary(summary.iter().collect())).await;
     }
 
     if errored {
@@ -94,48 +216,97 @@ async fn renew_any_needed(state: Arc<State>) -> Result<()> {
     }
 }
 
-async fn renew_one_cmd(state: Arc<State>, subdomain: String, force: bool) -> Result<()> {
-    let (renewal, expires) = state
+/// Look up what's needed to renew a subdomain, along with its expiration and whether it's enabled.
+pub(super) async fn load_renewal(
+    state: &Arc<State>,
+    subdomain: String,
+) -> Result<(Renewal, Option<i64>, bool)> {
+    state
         .pool
         .interact(move |conn| {
             let mut stmt = conn.prepare_cached(
                 r##"
-            SELECT aa.provider as acme_provider,
+            SELECT sd.acme_account,
                 aa.creds as acme_creds,
                 dp.provider as dns_provider,
                 dp.creds as dns_creds,
-                ep.provider as endpoint_provider,
-                ep.creds as endpoint_creds,
-                sd.expires
+                sd.expires,
+                sd.enabled
             FROM subdomains sd
             JOIN acme_accounts aa ON aa.id=sd.acme_account
             JOIN dns_providers dp ON dp.id=sd.dns_provider
-            JOIN endpoints ep ON ep.id=sd.endpoint
             WHERE sd.name = ?
         "##,
             )?;
 
-            let renewal: (Renewal, Option<i64>) = stmt.query_row([subdomain.clone()], |row| {
-                Ok((
-                    Renewal {
-                        subdomain,
-                        acme_provider: row.get(0)?,
-                        acme_creds: row.get(1)?,
-                        dns_provider: row.get(2)?,
-                        dns_creds: row.get(3)?,
-                        endpoint_provider: row.get(4)?,
-                        endpoint_creds: row.get(5)?,
-                    },
-                    row.get(6)?,
-                ))
-            })?;
+            let endpoints = crate::db::query_subdomain_endpoints(conn, &subdomain)?;
+            let renewal: (Renewal, Option<i64>, bool) =
+                stmt.query_row([subdomain.clone()], |row| {
+                    Ok((
+                        Renewal {
+                            subdomain,
+                            acme_account: row.get(0)?,
+                            acme_creds: row.get(1)?,
+                            dns_provider: row.get(2)?,
+                            dns_creds: row.get(3)?,
+                            endpoints,
+                        },
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                })?;
 
             Ok::<_, eyre::Report>(renewal)
         })
-        .await?;
+        .await
+}
+
+/// Print why a subdomain is due, and check what renewing it would do.
+async fn plan_renewal(state: &Arc<State>, renewal: Renewal, staging: bool) -> Result<()> {
+    let (expires, current) =
+        match crate::db::get_last_cert(state, renewal.subdomain.clone()).await? {
+            Some(sealed) => {
+                let cert: Certificate = serde_json::from_str(&state.secrets.open(sealed)?)?;
+                (Some(cert.info()?.not_after), Some(cert))
+            }
+            None => (None, None),
+        };
+    println!(
+        "{} would be renewed. Its certificate expires {}.",
+        renewal.subdomain,
+        describe_expiration(expires)
+    );
+
+    super::dry_run::plan_renewal(state, renewal, DeployOptions::default(), current, staging).await
+}
 
-    if expires.unwrap_or(0) < renewal_threshold() || force {
-        start_cert_process(state, renewal).await?;
+async fn renew_one_cmd(
+    state: Arc<State>,
+    subdomain: String,
+    force: bool,
+    dry_run: &DryRunArgs,
+) -> Result<()> {
+    reenable_due(&state, dry_run.dry_run).await?;
+
+    let (renewal, expires, mut enabled) = load_renewal(&state, subdomain.clone()).await?;
+    if dry_run.dry_run && !enabled {
+        // reenable_due didn't change anything, so check whether it would have.
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        enabled = state
+            .pool
+            .interact(move |conn| query_reenable_due(conn, now))
+            .await?
+            .contains(&subdomain);
+    }
+
+    if !enabled && !force {
+        println!("Renewals are disabled for this subdomain. Use --force to renew it anyway.");
+    } else if expires.unwrap_or(0) < renewal_threshold(&state) || force {
+        if dry_run.dry_run {
+            plan_renewal(&state, renewal, dry_run.staging).await?;
+        } else {
+            renew_and_record(state, renewal).await.0?;
+        }
     } else {
         println!("Certificate is not due for renewal yet");
     }
@@ -145,8 +316,8 @@ async fn renew_one_cmd(state: Arc<State>, subdomain: String, force: bool) -> Res
 
 pub async fn run(state: Arc<State>, args: RenewArgs) -> Result<()> {
     if let Some(subdomain) = args.subdomain {
-        renew_one_cmd(state, subdomain, args.force).await
+        renew_one_cmd(state, subdomain, args.force, &args.dry_run).await
     } else {
-        renew_any_needed(state).await
+        renew_any_needed(state, &args.dry_run).await
     }
 }
@@ -0,0 +1,162 @@
+use std::{str::FromStr, sync::Arc};
+
+use clap::Args;
+use eyre::{eyre, Result};
+use indicatif::ProgressBar;
+
+use crate::{
+    acme::{
+        client::{AcmeClient, RevocationReason, Signer},
+        AcmeProvider,
+    },
+    certificate::normalize_serial,
+    cli::{is_interactive, variant_parser},
+    cmd::State,
+    db::{CertificateRecord, ObjectTable},
+    deploy::DeployOptions,
+    Certificate,
+};
+
+use super::{renew::load_renewal, start_cert_process};
+
+#[derive(Debug, Args)]
+pub struct RevokeArgs {
+    /// The subdomain whose certificate should be revoked
+    subdomain: String,
+
+    /// Why the certificate is being revoked
+    #[clap(long, value_parser = variant_parser::<RevocationReason>())]
+    reason: Option<RevocationReason>,
+
+    /// The serial number of the certificate to revoke, as shown by `subdomain history`. This
+    /// defaults to the current certificate.
+    #[clap(long)]
+    serial: Option<String>,
+
+    /// Sign the revocation with the certificate's own key instead of the account that issued it.
+    /// This works even if the account has been deleted.
+    #[clap(long)]
+    use_cert_key: bool,
+
+    /// Issue and deploy a new certificate after revoking the current one
+    #[clap(long)]
+    reissue: bool,
+}
+
+/// Find the certificate to revoke in the subdomain's history. If the current certificate was
+/// issued before the history was kept, it is added to the history first, assuming that it came
+/// from the subdomain's current account.
+async fn find_certificate(
+    state: &Arc<State>,
+    subdomain: &str,
+    serial: Option<&str>,
+) -> Result<CertificateRecord> {
+    crate::db::backfill_last_cert(state, subdomain.to_string()).await?;
+    let mut certs = crate::db::list_certificates(state, subdomain.to_string())
+        .await?
+        .into_iter();
+
+    match serial {
+        Some(serial) => {
+            let serial = normalize_serial(serial);
+            certs.find(|c| c.serial == serial).ok_or_else(|| {
+                eyre!("No certificate with serial {serial} was found for {subdomain}")
+            })
+        }
+        None => certs
+            .find(|c| c.current)
+            .ok_or_else(|| eyre!("This subdomain does not yet have a certificate")),
+    }
+}
+
+pub async fn run(state: Arc<State>, args: RevokeArgs) -> Result<()> {
+    let subdomain = crate::db::get_subdomain(&state, args.subdomain.clone()).await?;
+    let current_account = crate::db::get_object(
+        &state,
+        ObjectTable::AcmeAccounts,
+        subdomain.acme_account.clone(),
+    )
+    .await?;
+    let record = find_certificate(&state, &subdomain.name, args.serial.as_deref()).await?;
+
+    if record.revoked_at.is_some() {
+        return Err(eyre!(
+            "Certificate {} has already been revoked",
+            record.serial
+        ));
+    }
+
+    if is_interactive() {
+        let confirmed = dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Revoke certificate {} for {}? This can't be undone.",
+                record.serial, subdomain.name
+            ))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            return Ok(());
+        }
+    }
+
+    let cert: Certificate = serde_json::from_str(&state.secrets.open(record.cert)?)?;
+
+    // The revocation has to go to the ACME server that issued the certificate. If the issuing
+    // account has been deleted or the certificate was imported, assume it's the same server as
+    // the subdomain's current account.
+    let account = match record.acme_account.clone() {
+        Some(name) if name != current_account.name => {
+            crate::db::get_object(&state, ObjectTable::AcmeAccounts, name).await?
+        }
+        _ => current_account,
+    };
+
+    let signer = if args.use_cert_key {
+        Signer::from_private_key_pem(&cert.key)?
+    } else if record.acme_account.is_some() {
+        Signer::from_account_creds(&state.secrets.open(account.creds)?)?
+    } else if record.imported {
+        return Err(eyre!(
+            "This certificate was imported, so it wasn't issued by any of the accounts here. Pass --use-cert-key to revoke it with the certificate's own key."
+        ));
+    } else {
+        return Err(eyre!(
+            "The account that issued this certificate has been deleted. Pass --use-cert-key to revoke it with the certificate's own key."
+        ));
+    };
+
+    let progress = state
+        .progress
+        .add(ProgressBar::new_spinner().with_message("Revoking certificate..."));
+
+    let provider = AcmeProvider::from_str(&account.provider)?;
+    let client = AcmeClient::new(&provider).await?;
+    client
+        .revoke_certificate(&signer, &cert.leaf_der()?, args.reason)
+        .await?;
+
+    crate::db::record_revocation(
+        &state,
+        subdomain.name.clone(),
+        record.serial.clone(),
+        args.reason.map(|r| r.as_ref().to_string()),
+    )
+    .await?;
+    progress.finish_with_message(format!("Revoked certificate {}", record.serial));
+
+    if !record.current {
+        return Ok(());
+    }
+
+    if args.reissue {
+        let (renewal, _, _) = load_renewal(&state, subdomain.name).await?;
+        start_cert_process(state, renewal, DeployOptions::default()).await?;
+    } else {
+        println!(
+            "{name} is still using the revoked certificate. Run `subdomain renew {name} --force` to replace it, or `subdomain disable {name}` if it is no longer needed.",
+            name = subdomain.name
+        );
+    }
+
+    Ok(())
+}
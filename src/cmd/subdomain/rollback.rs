@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::{eyre, Result};
+use time::OffsetDateTime;
+
+use crate::{
+    certificate::normalize_serial, cli::format_timestamp, cmd::State, deploy::DeployOptions,
+    Certificate,
+};
+
+use super::{deploy_to_endpoints, subdomain_deployers};
+
+#[derive(Debug, Args)]
+pub struct RollbackArgs {
+    /// The subdomain to roll back
+    subdomain: String,
+
+    /// The serial number of the certificate to deploy, as shown by `subdomain history`
+    #[clap(long)]
+    serial: Option<String>,
+}
+
+pub async fn run(state: Arc<State>, args: RollbackArgs) -> Result<()> {
+    crate::db::get_subdomain(&state, args.subdomain.clone()).await?;
+    crate::db::backfill_last_cert(&state, args.subdomain.clone()).await?;
+    let certs = crate::db::list_certificates(&state, args.subdomain.clone()).await?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let target = match args.serial.as_deref() {
+        Some(serial) => {
+            let serial = normalize_serial(serial);
+            let cert = certs
+                .into_iter()
+                .find(|c| c.serial == serial)
+                .ok_or_else(|| {
+                    eyre!(
+                        "No certificate with serial {serial} was found for {}",
+                        args.subdomain
+                    )
+                })?;
+
+            if cert.revoked_at.is_some() {
+                return Err(eyre!("Certificate {serial} has been revoked"));
+            }
+
+            if cert.not_after <= now {
+                return Err(eyre!(
+                    "Certificate {serial} expired at {}",
+                    format_timestamp(cert.not_after)
+                ));
+            }
+
+            cert
+        }
+        None => {
+            let current = certs
+                .iter()
+                .position(|c| c.current)
+                .ok_or_else(|| eyre!("{} does not yet have a certificate", args.subdomain))?;
+            certs
+                .into_iter()
+                .skip(current + 1)
+                .find(|c| c.not_after > now && c.revoked_at.is_none())
+                .ok_or_else(|| {
+                    eyre!(
+                        "{} has no earlier certificate that is still valid and not revoked",
+                        args.subdomain
+                    )
+                })?
+        }
+    };
+
+    let cert: Certificate = serde_json::from_str(&state.secrets.open(target.cert)?)?;
+    let deployers = subdomain_deployers(
+        &state,
+        args.subdomain.clone(),
+        None,
+        DeployOptions::default(),
+    )
+    .await?;
+
+    deploy_to_endpoints(&state, &args.subdomain, cert, &deployers, true).await?;
+
+    crate::db::restore_certificate(&state, args.subdomain.clone(), target.id).await?;
+    println!(
+        "Rolled back {} to certificate {}",
+        args.subdomain, target.serial
+    );
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use clap::Args;
+use eyre::Result;
+
+use crate::{cmd::State, db::DeployStatus};
+
+use super::{
+    history::describe_deploy,
+    list::{describe_cert, describe_status},
+};
+
+#[derive(Debug, Args)]
+pub struct ShowArgs {
+    /// The subdomain to show
+    subdomain: String,
+}
+
+pub async fn run(state: Arc<State>, args: ShowArgs) -> Result<()> {
+    let subdomain = crate::db::get_subdomain(&state, args.subdomain.clone()).await?;
+    let deploys = crate::db::endpoint_deploy_statuses(&state, args.subdomain).await?;
+
+    println!("Name:         {}", subdomain.name);
+    println!("ACME account: {}", subdomain.acme_account);
+    println!("DNS provider: {}", subdomain.dns_provider);
+    println!("Expires:      {}", describe_cert(&subdomain));
+    println!("Status:       {}", describe_status(&subdomain));
+
+    println!("Hosts:");
+    for deploy in deploys {
+        let status = if subdomain.has_cert {
+            describe_deploy(
+                deploy.status.unwrap_or(DeployStatus::Pending),
+                deploy.deployed_at,
+                deploy.error.as_deref(),
+            )
+        } else {
+            "no certificate".to_string()
+        };
+        println!("  {}: {status}", deploy.endpoint);
+    }
+
+    Ok(())
+}
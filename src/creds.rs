@@ -0,0 +1,125 @@
+//! Provider credentials can refer to secrets kept somewhere else, which are looked up each time
+//! the credentials are used:
+//!
+//! - `env:NAME` reads the environment variable `NAME`.
+//! - `file:/path` reads the contents of a file.
+//! - `cmd:<command>` runs a shell command and reads its output, such as `cmd:pass show vercel`.
+//!
+//! A reference can stand in for a provider's whole credentials, in which case it should resolve
+//! to either the provider's JSON credentials or a bare token, or for just the token inside them.
+
+use eyre::{eyre, Result, WrapErr};
+use serde::de::DeserializeOwned;
+use tokio::process::Command;
+
+/// If the value is a reference, return its kind and target.
+fn parse_reference(value: &str) -> Option<(&str, &str)> {
+    let (kind, target) = value.split_once(':')?;
+    match kind {
+        "env" | "file" | "cmd" => Some((kind, target)),
+        _ => None,
+    }
+}
+
+pub fn is_reference(value: &str) -> bool {
+    parse_reference(value).is_some()
+}
+
+/// Look up the secret that a value refers to. Values that aren't references are returned as-is.
+/// Secret commands run without blocking, since they can take a while and other renewals may be
+/// running at the same time.
+pub async fn resolve(value: &str) -> Result<String> {
+    let Some((kind, target)) = parse_reference(value) else {
+        return Ok(value.to_string());
+    };
+
+    let resolved = match kind {
+        "env" => std::env::var(target)
+            .wrap_err_with(|| format!("Failed to read environment variable {target}"))?,
+        "file" => std::fs::read_to_string(target)
+            .wrap_err_with(|| format!("Failed to read secret file {target}"))?,
+        "cmd" => {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(target)
+                .output()
+                .await
+                .wrap_err_with(|| format!("Failed to run secret command {target}"))?;
+
+            if !output.status.success() {
+                return Err(eyre!(
+                    "Secret command {target} failed with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+
+            String::from_utf8(output.stdout)
+                .wrap_err_with(|| format!("Secret command {target} did not output valid UTF-8"))?
+        }
+        _ => unreachable!(),
+    };
+
+    Ok(resolved.trim().to_string())
+}
+
+/// Look up an optional value that may be a reference.
+pub async fn resolve_option(value: Option<&str>) -> Result<Option<String>> {
+    match value {
+        Some(value) => Ok(Some(resolve(value).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Parse stored provider credentials, resolving the whole value first if it is a reference.
+/// Anything that isn't a JSON object is treated as a bare token.
+pub async fn parse<T: DeserializeOwned>(
+    creds: &str,
+    from_token: impl FnOnce(String) -> T,
+) -> Result<T> {
+    let creds = resolve(creds).await?;
+    if creds.trim_start().starts_with('{') {
+        Ok(serde_json::from_str(&creds)?)
+    } else {
+        Ok(from_token(creds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn resolves_command_output() {
+        assert_eq!(resolve("cmd:echo ' token '").await.unwrap(), "token");
+        assert_eq!(resolve("plain value").await.unwrap(), "plain value");
+
+        let err = resolve("cmd:echo nope >&2; exit 2").await.unwrap_err();
+        assert!(err.to_string().ends_with(": nope"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn slow_command_lets_other_tasks_run() {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        let ticker = tokio::spawn(async move {
+            loop {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        assert_eq!(resolve("cmd:sleep 0.3; echo done").await.unwrap(), "done");
+        ticker.abort();
+
+        assert!(ticks.load(Ordering::SeqCst) >= 5);
+    }
+}
@@ -0,0 +1,286 @@
+//! Encryption for the credentials and private keys stored in the database.
+//!
+//! Encrypted values are stored as `enc:v1:` followed by the base64-encoded nonce and ciphertext.
+//! Anything without that prefix is plain text, which lets databases created before encryption
+//! existed keep working until they are encrypted with `db rekey`.
+
+use std::{io::Write, path::Path, sync::OnceLock};
+
+use argon2::Argon2;
+use deadpool_sqlite::Pool;
+use eyre::{eyre, Result, WrapErr};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use rusqlite::OptionalExtension;
+use strum::{AsRefStr, EnumString};
+
+use crate::db::PoolExtInteract;
+
+const PREFIX: &str = "enc:v1:";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+/// A known value, encrypted and stored so that a wrong key can be detected before it is used.
+const KEY_CHECK: &str = "remote-ssl-renewal";
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+#[derive(Clone)]
+pub struct Cipher {
+    key: [u8; KEY_LEN],
+}
+
+impl Cipher {
+    pub fn generate() -> Result<Cipher> {
+        let mut key = [0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| eyre!("Failed to generate key"))?;
+        Ok(Cipher { key })
+    }
+
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Result<Cipher> {
+        let mut key = [0u8; KEY_LEN];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| eyre!("Failed to derive key from passphrase: {e}"))?;
+        Ok(Cipher { key })
+    }
+
+    /// Read a key encoded with [Cipher::encoded_key].
+    pub fn from_encoded_key(encoded: &str) -> Result<Cipher> {
+        let decoded = base64::decode(encoded.trim()).wrap_err("Key is not valid base64")?;
+        let key = decoded
+            .try_into()
+            .map_err(|_| eyre!("Key must be {KEY_LEN} bytes"))?;
+        Ok(Cipher { key })
+    }
+
+    pub fn encoded_key(&self) -> String {
+        base64::encode(self.key)
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &self.key).unwrap())
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| eyre!("Failed to generate nonce"))?;
+
+        let mut data = plaintext.as_bytes().to_vec();
+        self.aead_key()
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| eyre!("Failed to encrypt value"))?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&data);
+        Ok(format!("{PREFIX}{}", base64::encode(output)))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> Result<String> {
+        let encoded = stored
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| eyre!("Value is not encrypted"))?;
+        let data = base64::decode(encoded)?;
+        if data.len() < NONCE_LEN {
+            return Err(eyre!("Encrypted value is too short"));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).unwrap();
+        let mut ciphertext = ciphertext.to_vec();
+        let plaintext = self
+            .aead_key()
+            .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+            .map_err(|_| eyre!("Failed to decrypt value. The key may be wrong."))?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+}
+
+/// Where the database's encryption key comes from.
+#[derive(AsRefStr, Clone, Copy, Debug, EnumString, PartialEq, Eq)]
+pub enum KeySource {
+    /// A key derived from a passphrase with Argon2.
+    Passphrase,
+    /// A random key, read from `$RSR_KEY` or the file at `$RSR_KEY_FILE`.
+    Key,
+}
+
+/// The contents of the `encryption` table.
+#[derive(Clone)]
+pub struct EncryptionConfig {
+    pub key_source: KeySource,
+    pub salt: Option<String>,
+    pub key_check: String,
+}
+
+impl EncryptionConfig {
+    pub fn new(key_source: KeySource, salt: Option<String>, cipher: &Cipher) -> Result<Self> {
+        Ok(EncryptionConfig {
+            key_source,
+            salt,
+            key_check: cipher.encrypt(KEY_CHECK)?,
+        })
+    }
+
+    pub async fn load(pool: &Pool) -> Result<Option<EncryptionConfig>> {
+        pool.interact(|conn| {
+            let config = conn
+                .query_row(
+                    "SELECT key_source, salt, key_check FROM encryption",
+                    [],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            config
+                .map(
+                    |(key_source, salt, key_check)| -> Result<EncryptionConfig> {
+                        Ok(EncryptionConfig {
+                            key_source: key_source.parse()?,
+                            salt,
+                            key_check,
+                        })
+                    },
+                )
+                .transpose()
+        })
+        .await
+    }
+
+    /// Get the key from the environment, or by prompting for the passphrase.
+    fn load_cipher(&self) -> Result<Cipher> {
+        let cipher = match self.key_source {
+            KeySource::Passphrase => {
+                let passphrase = match std::env::var("RSR_PASSPHRASE") {
+                    Ok(passphrase) => passphrase,
+                    Err(_) if crate::cli::is_interactive() => dialoguer::Password::new()
+                        .with_prompt("Database passphrase")
+                        .interact()?,
+                    Err(_) => {
+                        return Err(eyre!(
+                        "The database is encrypted with a passphrase. Set $RSR_PASSPHRASE to unlock it."
+                    ))
+                    }
+                };
+
+                let salt = base64::decode(self.salt.as_deref().unwrap_or_default())?;
+                Cipher::from_passphrase(&passphrase, &salt)?
+            }
+            KeySource::Key => {
+                if let Ok(key) = std::env::var("RSR_KEY") {
+                    Cipher::from_encoded_key(&key)?
+                } else if let Ok(path) = std::env::var("RSR_KEY_FILE") {
+                    read_key_file(Path::new(&path))?
+                } else {
+                    return Err(eyre!(
+                        "The database is encrypted with a key. Set $RSR_KEY or $RSR_KEY_FILE to unlock it."
+                    ));
+                }
+            }
+        };
+
+        match cipher.decrypt(&self.key_check) {
+            Ok(check) if check == KEY_CHECK => Ok(cipher),
+            _ => Err(eyre!("The database encryption key is incorrect")),
+        }
+    }
+}
+
+pub fn generate_salt() -> Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| eyre!("Failed to generate salt"))?;
+    Ok(base64::encode(salt))
+}
+
+pub fn read_key_file(path: &Path) -> Result<Cipher> {
+    let key = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read key file {}", path.display()))?;
+    Cipher::from_encoded_key(&key)
+}
+
+/// Write a key to a file that only the current user can read.
+pub fn write_key_file(path: &Path, cipher: &Cipher) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(path)
+        .wrap_err_with(|| format!("Failed to create key file {}", path.display()))?;
+    writeln!(file, "{}", cipher.encoded_key())?;
+    Ok(())
+}
+
+/// Encrypts and decrypts values stored in the database. The key is only loaded the first time
+/// it's needed, so that commands which don't touch credentials can run without it.
+pub struct Secrets {
+    config: Option<EncryptionConfig>,
+    cipher: OnceLock<Cipher>,
+}
+
+impl Secrets {
+    pub async fn load(pool: &Pool) -> Result<Secrets> {
+        Ok(Secrets {
+            config: EncryptionConfig::load(pool).await?,
+            cipher: OnceLock::new(),
+        })
+    }
+
+    /// Return the cipher for the database, or `None` if it isn't encrypted.
+    pub fn cipher(&self) -> Result<Option<&Cipher>> {
+        let Some(config) = self.config.as_ref() else {
+            return Ok(None);
+        };
+
+        if let Some(cipher) = self.cipher.get() {
+            return Ok(Some(cipher));
+        }
+
+        let cipher = config.load_cipher()?;
+        Ok(Some(self.cipher.get_or_init(|| cipher)))
+    }
+
+    /// Prepare a value to be stored in the database, encrypting it if the database is encrypted.
+    pub fn seal(&self, plaintext: String) -> Result<String> {
+        if plaintext.is_empty() {
+            return Ok(plaintext);
+        }
+
+        match self.cipher()? {
+            Some(cipher) => cipher.encrypt(&plaintext),
+            None => Ok(plaintext),
+        }
+    }
+
+    /// Read a value from the database, decrypting it if it's encrypted.
+    pub fn open(&self, stored: String) -> Result<String> {
+        if !is_encrypted(&stored) {
+            return Ok(stored);
+        }
+
+        match self.cipher()? {
+            Some(cipher) => cipher.decrypt(&stored),
+            None => Err(eyre!(
+                "Found an encrypted value, but the database has no encryption key configured"
+            )),
+        }
+    }
+}
@@ -1,14 +1,29 @@
-use std::{path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use deadpool_sqlite::{Hook, HookError, HookErrorCause};
-use eyre::Result;
-use rusqlite::Connection;
+use eyre::{eyre, Result};
+use rusqlite::{params, Connection, OptionalExtension};
 use rusqlite_migration::{Migrations, M};
+use strum::{AsRefStr, EnumString};
+use time::OffsetDateTime;
 
-use crate::cmd::State;
+use crate::{certificate::CertificateInfo, cmd::State, Certificate};
 
-const MIGRATIONS: [&str; 1] = [include_str!("../migrations/0001-init.sql")];
+const MIGRATIONS: [&str; 8] = [
+    include_str!("../migrations/0001-init.sql"),
+    include_str!("../migrations/0002-subdomain-disable.sql"),
+    include_str!("../migrations/0003-encryption.sql"),
+    include_str!("../migrations/0004-certificates.sql"),
+    include_str!("../migrations/0005-certificate-revocation.sql"),
+    include_str!("../migrations/0006-subdomain-endpoints.sql"),
+    include_str!("../migrations/0007-renewal-failures.sql"),
+    include_str!("../migrations/0008-certificate-imported.sql"),
+];
 
 fn create_migrations() -> Migrations<'static> {
     let items = MIGRATIONS.iter().map(|m| M::up(m)).collect::<Vec<_>>();
@@ -21,13 +36,45 @@ pub fn migrate(conn: &mut Connection) -> Result<()> {
     Ok(())
 }
 
-pub async fn create_db() -> Result<deadpool_sqlite::Pool> {
-    let config_dir = dirs::config_dir()
+/// The directory that holds the default database and profiles.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("."))
-        .join("remote-ssl-renewal");
-    std::fs::create_dir_all(&config_dir)?;
+        .join("remote-ssl-renewal")
+}
+
+/// Find the database to use. An explicit path takes precedence, and otherwise each profile has its
+/// own database inside the config directory.
+pub fn db_path(db: Option<PathBuf>, profile: Option<&str>) -> Result<PathBuf> {
+    if let Some(db) = db {
+        return Ok(db);
+    }
+
+    match profile {
+        Some(profile) => {
+            let valid = !profile.is_empty()
+                && profile
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(eyre!(
+                    "Profile names may only contain letters, numbers, dashes, and underscores"
+                ));
+            }
+
+            Ok(config_dir()
+                .join("profiles")
+                .join(profile)
+                .join("data.sqlite3"))
+        }
+        None => Ok(config_dir().join("data.sqlite3")),
+    }
+}
 
-    let db_path = config_dir.join("data.sqlite3");
+pub async fn create_db(db_path: &Path) -> Result<deadpool_sqlite::Pool> {
+    if let Some(dir) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
 
     let pool = deadpool_sqlite::Config::new(db_path)
         .builder(deadpool_sqlite::Runtime::Tokio1)?
@@ -98,7 +145,9 @@ where
         let result = conn
             .interact(move |conn| {
                 let mut tx = conn.transaction()?;
-                f(&mut tx)
+                let result = f(&mut tx)?;
+                tx.commit()?;
+                Ok::<_, ERR>(result)
             })
             .await
             .unwrap()?;
@@ -106,6 +155,7 @@ where
     }
 }
 
+#[derive(Clone)]
 pub struct DbObject {
     pub id: i64,
     pub name: String,
@@ -124,6 +174,207 @@ impl DbObject {
     }
 }
 
+/// The tables holding the objects that subdomains link to.
+#[derive(Clone, Copy, Debug)]
+pub enum ObjectTable {
+    AcmeAccounts,
+    DnsProviders,
+    Endpoints,
+}
+
+impl ObjectTable {
+    pub fn table(&self) -> &'static str {
+        match self {
+            ObjectTable::AcmeAccounts => "acme_accounts",
+            ObjectTable::DnsProviders => "dns_providers",
+            ObjectTable::Endpoints => "endpoints",
+        }
+    }
+
+    /// A condition on `subdomains` that matches the subdomains using the object whose ID is bound
+    /// to the parameter.
+    fn subdomain_filter(&self) -> &'static str {
+        match self {
+            ObjectTable::AcmeAccounts => "acme_account=?",
+            ObjectTable::DnsProviders => "dns_provider=?",
+            ObjectTable::Endpoints => {
+                "name IN (SELECT subdomain FROM subdomain_endpoints WHERE endpoint=?)"
+            }
+        }
+    }
+
+    /// Like [ObjectTable::subdomain_filter], but only matches the subdomains that can't do without
+    /// the object. A subdomain with several hosts only depends on a host if it's the last one.
+    fn dependent_subdomain_filter(&self) -> &'static str {
+        match self {
+            ObjectTable::Endpoints => {
+                r##"name IN (SELECT subdomain FROM subdomain_endpoints
+                    GROUP BY subdomain HAVING COUNT(*)=1 AND MAX(endpoint)=?)"##
+            }
+            _ => self.subdomain_filter(),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ObjectTable::AcmeAccounts => "ACME account",
+            ObjectTable::DnsProviders => "DNS provider",
+            ObjectTable::Endpoints => "host",
+        }
+    }
+}
+
+pub async fn list_objects(state: &Arc<State>, table: ObjectTable) -> Result<Vec<DbObject>> {
+    let statement = format!(
+        "SELECT id, name, provider, creds FROM {} ORDER BY name",
+        table.table()
+    );
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(&statement)?;
+            let objects = stmt
+                .query_map([], DbObject::from_row)?
+                .collect::<Result<Vec<DbObject>, _>>()?;
+            Ok(objects)
+        })
+        .await
+}
+
+pub async fn get_object(state: &Arc<State>, table: ObjectTable, name: String) -> Result<DbObject> {
+    let statement = format!(
+        "SELECT id, name, provider, creds FROM {} WHERE name=?",
+        table.table()
+    );
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(&statement)?;
+            let object = stmt
+                .query_row([&name], DbObject::from_row)
+                .optional()?
+                .ok_or_else(|| eyre!("No {} named {name} was found", table.label()))?;
+            Ok(object)
+        })
+        .await
+}
+
+/// Change an object's name and credentials. Values that are `None` are left alone.
+pub async fn update_object(
+    state: &Arc<State>,
+    table: ObjectTable,
+    id: i64,
+    name: Option<String>,
+    creds: Option<String>,
+) -> Result<()> {
+    let statement = format!(
+        "UPDATE {} SET name=COALESCE(?, name), creds=COALESCE(?, creds) WHERE id=?",
+        table.table()
+    );
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(&statement)?;
+            stmt.execute(params![name, creds, id])?;
+            Ok(())
+        })
+        .await
+}
+
+/// Return the names of the subdomains that use the given object.
+pub async fn subdomains_using(
+    state: &Arc<State>,
+    table: ObjectTable,
+    id: i64,
+) -> Result<Vec<String>> {
+    let statement = format!(
+        "SELECT name FROM subdomains WHERE {} ORDER BY name",
+        table.subdomain_filter()
+    );
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached(&statement)?;
+            let names = stmt
+                .query_map([id], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(names)
+        })
+        .await
+}
+
+/// Delete an object. If any subdomains depend on it, they are deleted too when `cascade` is set,
+/// and otherwise this returns an error. Subdomains with other hosts left are just unlinked from a
+/// deleted host. Returns the names of the deleted subdomains.
+pub async fn delete_object(
+    state: &Arc<State>,
+    table: ObjectTable,
+    name: String,
+    cascade: bool,
+) -> Result<Vec<String>> {
+    state
+        .pool
+        .transaction(move |tx| {
+            let id: i64 = tx
+                .query_row(
+                    &format!("SELECT id FROM {} WHERE name=?", table.table()),
+                    [&name],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| eyre!("No {} named {name} was found", table.label()))?;
+
+            let subdomains = {
+                let mut stmt = tx.prepare_cached(&format!(
+                    "SELECT name FROM subdomains WHERE {} ORDER BY name",
+                    table.dependent_subdomain_filter()
+                ))?;
+                let names = stmt
+                    .query_map([id], |row| row.get(0))?
+                    .collect::<Result<Vec<String>, _>>()?;
+                names
+            };
+
+            if !subdomains.is_empty() {
+                if !cascade {
+                    return Err(eyre!(
+                        "{} {name} is still used by subdomains {}. Delete them first, or pass --cascade to delete them along with it.",
+                        table.label(),
+                        subdomains.join(", ")
+                    ));
+                }
+
+                tx.execute(
+                    &format!(
+                        "DELETE FROM subdomains WHERE {}",
+                        table.dependent_subdomain_filter()
+                    ),
+                    [id],
+                )?;
+            }
+
+            match table {
+                ObjectTable::Endpoints => {
+                    tx.execute("DELETE FROM subdomain_endpoints WHERE endpoint=?", [id])?;
+                }
+                // The history keeps the certificates the account issued, but not its id, which
+                // a later account can reuse.
+                ObjectTable::AcmeAccounts => {
+                    tx.execute(
+                        "UPDATE certificates SET acme_account=NULL WHERE acme_account=?",
+                        [id],
+                    )?;
+                }
+                ObjectTable::DnsProviders => {}
+            }
+
+            tx.execute(&format!("DELETE FROM {} WHERE id=?", table.table()), [id])?;
+
+            Ok(subdomains)
+        })
+        .await
+}
+
 pub struct DbObjects {
     pub acme_accounts: Vec<DbObject>,
     pub dns_providers: Vec<DbObject>,
@@ -131,34 +382,585 @@ pub struct DbObjects {
 }
 
 pub async fn get_all_objects(state: &Arc<State>) -> Result<DbObjects> {
+    Ok(DbObjects {
+        acme_accounts: list_objects(state, ObjectTable::AcmeAccounts).await?,
+        dns_providers: list_objects(state, ObjectTable::DnsProviders).await?,
+        endpoints: list_objects(state, ObjectTable::Endpoints).await?,
+    })
+}
+
+pub struct SubdomainInfo {
+    pub name: String,
+    pub acme_account: String,
+    pub dns_provider: String,
+    /// The names of the hosts that the certificate is deployed to
+    pub endpoints: Vec<String>,
+    pub has_cert: bool,
+    pub expires: Option<i64>,
+    pub enabled: bool,
+    pub disabled_reason: Option<String>,
+    pub reenable_at: Option<i64>,
+}
+
+impl SubdomainInfo {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let endpoints = serde_json::from_str(&row.get::<_, String>(3)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?;
+
+        Ok(Self {
+            name: row.get(0)?,
+            acme_account: row.get(1)?,
+            dns_provider: row.get(2)?,
+            endpoints,
+            has_cert: row.get(4)?,
+            expires: row.get(5)?,
+            enabled: row.get(6)?,
+            disabled_reason: row.get(7)?,
+            reenable_at: row.get(8)?,
+        })
+    }
+}
+
+const SUBDOMAIN_INFO_QUERY: &str = r##"
+    SELECT sd.name,
+        aa.name as acme_account,
+        dp.name as dns_provider,
+        (SELECT json_group_array(name) FROM (
+            SELECT ep.name FROM subdomain_endpoints se
+            JOIN endpoints ep ON ep.id=se.endpoint
+            WHERE se.subdomain=sd.name
+            ORDER BY ep.name
+        )) as endpoints,
+        sd.last_cert IS NOT NULL as has_cert,
+        sd.expires,
+        sd.enabled,
+        sd.disabled_reason,
+        sd.reenable_at
+    FROM subdomains sd
+    JOIN acme_accounts aa ON aa.id=sd.acme_account
+    JOIN dns_providers dp ON dp.id=sd.dns_provider
+"##;
+
+pub async fn list_subdomains(state: &Arc<State>) -> Result<Vec<SubdomainInfo>> {
     state
         .pool
         .interact(|conn| {
+            let mut stmt =
+                conn.prepare_cached(&format!("{SUBDOMAIN_INFO_QUERY} ORDER BY sd.name"))?;
+            let subdomains = stmt
+                .query_map([], SubdomainInfo::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(subdomains)
+        })
+        .await
+}
+
+pub async fn get_subdomain(state: &Arc<State>, name: String) -> Result<SubdomainInfo> {
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt =
+                conn.prepare_cached(&format!("{SUBDOMAIN_INFO_QUERY} WHERE sd.name=?"))?;
+            let subdomain = stmt
+                .query_row([&name], SubdomainInfo::from_row)
+                .optional()?
+                .ok_or_else(|| eyre!("No subdomain named {name} was found"))?;
+            Ok(subdomain)
+        })
+        .await
+}
+
+/// Return the hosts that a subdomain's certificate is deployed to, ordered by name.
+pub fn query_subdomain_endpoints(
+    conn: &Connection,
+    subdomain: &str,
+) -> Result<Vec<DbObject>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r##"SELECT ep.id, ep.name, ep.provider, ep.creds
+        FROM subdomain_endpoints se
+        JOIN endpoints ep ON ep.id=se.endpoint
+        WHERE se.subdomain=?
+        ORDER BY ep.name"##,
+    )?;
+    let endpoints = stmt
+        .query_map([subdomain], DbObject::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(endpoints)
+}
+
+pub async fn subdomain_endpoints(state: &Arc<State>, subdomain: String) -> Result<Vec<DbObject>> {
+    state
+        .pool
+        .interact(move |conn| Ok(query_subdomain_endpoints(conn, &subdomain)?))
+        .await
+}
+
+/// Replace the set of hosts that a subdomain's certificate is deployed to.
+pub fn link_endpoints(
+    conn: &Connection,
+    subdomain: &str,
+    endpoints: &[i64],
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM subdomain_endpoints WHERE subdomain=?",
+        [subdomain],
+    )?;
+
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO subdomain_endpoints (subdomain, endpoint) VALUES (?, ?)",
+    )?;
+    for endpoint in endpoints {
+        stmt.execute(params![subdomain, endpoint])?;
+    }
+
+    Ok(())
+}
+
+/// Return a subdomain's current certificate, as stored in the database.
+pub async fn get_last_cert(state: &Arc<State>, subdomain: String) -> Result<Option<String>> {
+    state
+        .pool
+        .interact(move |conn| {
+            let mut stmt = conn.prepare_cached("SELECT last_cert FROM subdomains WHERE name=?")?;
+            let last_cert = stmt
+                .query_row([&subdomain], |row| row.get(0))
+                .optional()?
+                .ok_or_else(|| eyre!("No subdomain named {subdomain} was found"))?;
+            Ok(last_cert)
+        })
+        .await
+}
+
+/// Record whether an attempt to renew a subdomain worked. Returns how many times in a row
+/// renewing it has now failed, and when its current certificate expires.
+pub async fn record_renewal_attempt(
+    state: &Arc<State>,
+    subdomain: String,
+    error: Option<String>,
+) -> Result<(i64, Option<i64>)> {
+    state
+        .pool
+        .transaction(move |tx| {
+            tx.execute(
+                r##"UPDATE subdomains SET
+                    renewal_failures = CASE WHEN ?2 IS NULL THEN 0 ELSE renewal_failures + 1 END,
+                    last_renewal_error = ?2
+                WHERE name = ?1"##,
+                params![subdomain, error],
+            )?;
+
+            let result = tx.query_row(
+                "SELECT renewal_failures, expires FROM subdomains WHERE name = ?",
+                [&subdomain],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            Ok::<_, eyre::Report>(result)
+        })
+        .await
+}
+
+/// Whether a certificate in a subdomain's history has been deployed to its host.
+#[derive(AsRefStr, Clone, Copy, Debug, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum DeployStatus {
+    Pending,
+    Deployed,
+    Failed,
+}
+
+pub struct CertificateRecord {
+    pub id: i64,
+    pub serial: String,
+    pub issuer: String,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub key_type: String,
+    /// The name of the issuing account, or `None` if it has since been deleted or the
+    /// certificate was imported.
+    pub acme_account: Option<String>,
+    /// Whether the certificate was issued somewhere else and imported.
+    pub imported: bool,
+    pub deploy_status: DeployStatus,
+    pub deploy_error: Option<String>,
+    pub deployed_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub revocation_reason: Option<String>,
+    /// Whether this is the subdomain's current certificate.
+    pub current: bool,
+    /// The certificate and key, as stored in the database.
+    pub cert: String,
+}
+
+impl CertificateRecord {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let deploy_status = row.get::<_, String>(7)?.parse().map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Text, Box::new(e))
+        })?;
+
+        Ok(Self {
+            id: row.get(0)?,
+            serial: row.get(1)?,
+            issuer: row.get(2)?,
+            not_before: row.get(3)?,
+            not_after: row.get(4)?,
+            key_type: row.get(5)?,
+            acme_account: row.get(6)?,
+            deploy_status,
+            deploy_error: row.get(8)?,
+            deployed_at: row.get(9)?,
+            revoked_at: row.get(10)?,
+            revocation_reason: row.get(11)?,
+            current: row.get(12)?,
+            imported: row.get(13)?,
+            cert: row.get(14)?,
+        })
+    }
+}
+
+/// Return every certificate issued for a subdomain, newest first.
+pub async fn list_certificates(
+    state: &Arc<State>,
+    subdomain: String,
+) -> Result<Vec<CertificateRecord>> {
+    state
+        .pool
+        .interact(move |conn| {
             let mut stmt = conn.prepare_cached(
-                "SELECT id, name, provider, creds FROM acme_accounts ORDER BY name",
+                r##"SELECT c.id, c.serial, c.issuer, c.not_before, c.not_after, c.key_type,
+                    aa.name as acme_account,
+                    c.deploy_status, c.deploy_error, c.deployed_at,
+                    c.revoked_at, c.revocation_reason,
+                    c.serial IS sd.serial as current,
+                    c.imported,
+                    c.cert
+                FROM certificates c
+                JOIN subdomains sd ON sd.name=c.subdomain
+                LEFT JOIN acme_accounts aa ON aa.id=c.acme_account
+                WHERE c.subdomain=?
+                ORDER BY c.id DESC"##,
             )?;
-            let acme_accounts = stmt
-                .query_map([], DbObject::from_row)?
-                .collect::<Result<Vec<DbObject>, _>>()?;
+            let certs = stmt
+                .query_map([subdomain], CertificateRecord::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(certs)
+        })
+        .await
+}
+
+/// Make a certificate the subdomain's current one, adding it to the history if it isn't there
+/// yet.
+fn insert_certificate(
+    tx: &rusqlite::Transaction,
+    subdomain: &str,
+    acme_account: Option<i64>,
+    info: &CertificateInfo,
+    cert: &str,
+) -> Result<()> {
+    tx.execute(
+        "UPDATE subdomains SET last_cert=?, expires=?, serial=? WHERE name=?",
+        params![cert, info.not_after, info.serial, subdomain],
+    )?;
+
+    // Importing or reinstalling the same certificate again shouldn't add it to the
+    // history twice.
+    let existing = tx
+        .query_row(
+            "SELECT id FROM certificates WHERE subdomain=? AND serial=?",
+            params![subdomain, info.serial],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    if existing.is_some() {
+        return Ok(());
+    }
+
+    tx.execute(
+        r##"INSERT INTO certificates
+        (subdomain, serial, issuer, not_before, not_after, key_type, acme_account, imported, cert,
+            created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"##,
+        params![
+            subdomain,
+            info.serial,
+            info.issuer,
+            info.not_before,
+            info.not_after,
+            info.key_type,
+            acme_account,
+            acme_account.is_none(),
+            cert,
+            OffsetDateTime::now_utc().unix_timestamp()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Databases from before the certificate history only have each subdomain's latest certificate,
+/// in `subdomains.last_cert`. Add it to the history if it isn't there yet, so that it can still
+/// be rolled back to or revoked once it's replaced. This needs the encryption key, so it's done
+/// when a certificate is needed rather than when the database is migrated.
+pub async fn backfill_last_cert(state: &Arc<State>, subdomain: String) -> Result<()> {
+    let name = subdomain.clone();
+    let missing = state
+        .pool
+        .interact(move |conn| {
+            let missing = conn
+                .query_row(
+                    r##"SELECT sd.last_cert, sd.acme_account FROM subdomains sd
+                    WHERE sd.name=? AND sd.last_cert IS NOT NULL
+                        AND NOT EXISTS (
+                            SELECT 1 FROM certificates c
+                            WHERE c.subdomain=sd.name AND c.serial IS sd.serial
+                        )"##,
+                    [name],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+                )
+                .optional()?;
+            Ok::<_, eyre::Report>(missing)
+        })
+        .await?;
+
+    let Some((stored, acme_account)) = missing else {
+        return Ok(());
+    };
+
+    let cert: Certificate = serde_json::from_str(&state.secrets.open(stored.clone())?)?;
+    let info = cert.info()?;
+    state
+        .pool
+        .transaction(move |tx| {
+            insert_certificate(tx, &subdomain, Some(acme_account), &info, &stored)
+        })
+        .await
+}
+
+/// Add a newly-issued certificate to a subdomain's history, and make it the current certificate.
+/// `cert` is the certificate and key, already sealed for storage.
+pub async fn save_certificate(
+    state: &Arc<State>,
+    subdomain: String,
+    acme_account: Option<i64>,
+    info: CertificateInfo,
+    cert: String,
+) -> Result<()> {
+    backfill_last_cert(state, subdomain.clone()).await?;
+
+    state
+        .pool
+        .transaction(move |tx| insert_certificate(tx, &subdomain, acme_account, &info, &cert))
+        .await
+}
+
+/// Make a certificate from the history the subdomain's current certificate again.
+pub async fn restore_certificate(state: &Arc<State>, subdomain: String, id: i64) -> Result<()> {
+    state
+        .pool
+        .interact(move |conn| {
+            conn.execute(
+                r##"UPDATE subdomains
+                SET (last_cert, expires, serial) =
+                    (SELECT cert, not_after, serial FROM certificates WHERE id=?)
+                WHERE name=?"##,
+                params![id, subdomain],
+            )?;
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// The outcome of deploying a certificate to one host.
+pub struct EndpointDeploy {
+    pub endpoint_id: i64,
+    /// The error, or `None` if the deploy succeeded
+    pub error: Option<String>,
+}
+
+/// Record the results of deploying a certificate to some of a subdomain's hosts. The
+/// certificate's overall status covers every host that the subdomain currently uses, so
+/// redeploying to one host keeps the results for the others.
+pub async fn record_deploys(
+    state: &Arc<State>,
+    subdomain: String,
+    serial: String,
+    results: Vec<EndpointDeploy>,
+) -> Result<()> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    state
+        .pool
+        .transaction(move |tx| {
+            let Some(certificate) = tx
+                .query_row(
+                    "SELECT id FROM certificates WHERE subdomain=? AND serial=?",
+                    params![subdomain, serial],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?
+            else {
+                return Ok(());
+            };
+
+            {
+                let mut stmt = tx.prepare_cached(
+                    r##"INSERT INTO certificate_deploys
+                    (certificate, endpoint, status, error, deployed_at, attempted_at)
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    ON CONFLICT (certificate, endpoint) DO UPDATE SET
+                        status=excluded.status,
+                        error=excluded.error,
+                        deployed_at=COALESCE(excluded.deployed_at, deployed_at),
+                        attempted_at=excluded.attempted_at"##,
+                )?;
+
+                for result in &results {
+                    let status = match result.error {
+                        None => DeployStatus::Deployed,
+                        Some(_) => DeployStatus::Failed,
+                    };
+                    stmt.execute(params![
+                        certificate,
+                        result.endpoint_id,
+                        status.as_ref(),
+                        result.error,
+                        result.error.is_none().then_some(now),
+                        now
+                    ])?;
+                }
+            }
 
+            let statuses = {
+                let mut stmt = tx.prepare_cached(
+                    r##"SELECT ep.name, cd.status, cd.error
+                    FROM subdomain_endpoints se
+                    JOIN endpoints ep ON ep.id=se.endpoint
+                    LEFT JOIN certificate_deploys cd ON cd.endpoint=se.endpoint AND cd.certificate=?
+                    WHERE se.subdomain=?
+                    ORDER BY ep.name"##,
+                )?;
+                let statuses = stmt
+                    .query_map(params![certificate, subdomain], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                statuses
+            };
+
+            let errors = statuses
+                .iter()
+                .filter_map(|(name, _, error)| Some(format!("{name}: {}", error.as_ref()?)))
+                .collect::<Vec<_>>();
+            let status = if !errors.is_empty() {
+                DeployStatus::Failed
+            } else if statuses
+                .iter()
+                .all(|(_, status, _)| status.as_deref() == Some(DeployStatus::Deployed.as_ref()))
+            {
+                DeployStatus::Deployed
+            } else {
+                DeployStatus::Pending
+            };
+            let error = (!errors.is_empty()).then(|| errors.join("; "));
+
+            tx.execute(
+                r##"UPDATE certificates
+                SET deploy_status=?,
+                    deploy_error=?,
+                    deployed_at=CASE WHEN ? THEN ? ELSE deployed_at END
+                WHERE id=?"##,
+                params![
+                    status.as_ref(),
+                    error,
+                    status == DeployStatus::Deployed,
+                    now,
+                    certificate
+                ],
+            )?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// How the current certificate's deploy went on one of a subdomain's hosts.
+pub struct EndpointDeployStatus {
+    pub endpoint: String,
+    /// The result of the last attempt, or `None` if the certificate hasn't been deployed there
+    pub status: Option<DeployStatus>,
+    pub error: Option<String>,
+    pub deployed_at: Option<i64>,
+}
+
+/// Return the deploy status of a subdomain's current certificate on each of its hosts.
+pub async fn endpoint_deploy_statuses(
+    state: &Arc<State>,
+    subdomain: String,
+) -> Result<Vec<EndpointDeployStatus>> {
+    state
+        .pool
+        .interact(move |conn| {
             let mut stmt = conn.prepare_cached(
-                "SELECT id, name, provider, creds FROM dns_providers ORDER BY name",
+                r##"SELECT ep.name, cd.status, cd.error, cd.deployed_at
+                FROM subdomain_endpoints se
+                JOIN endpoints ep ON ep.id=se.endpoint
+                JOIN subdomains sd ON sd.name=se.subdomain
+                LEFT JOIN certificates c ON c.subdomain=sd.name AND c.serial=sd.serial
+                LEFT JOIN certificate_deploys cd ON cd.certificate=c.id AND cd.endpoint=ep.id
+                WHERE se.subdomain=?
+                ORDER BY ep.name"##,
             )?;
-            let dns_providers = stmt
-                .query_map([], DbObject::from_row)?
-                .collect::<Result<Vec<DbObject>, _>>()?;
+            let statuses = stmt
+                .query_map([subdomain], |row| {
+                    let status = row
+                        .get::<_, Option<String>>(1)?
+                        .map(|s| s.parse::<DeployStatus>())
+                        .transpose()
+                        .map_err(|e| {
+                            rusqlite::Error::FromSqlConversionFailure(
+                                1,
+                                rusqlite::types::Type::Text,
+                                Box::new(e),
+                            )
+                        })?;
 
-            let mut stmt = conn
-                .prepare_cached("SELECT id, name, provider, creds FROM endpoints ORDER BY name")?;
-            let endpoints = stmt
-                .query_map([], DbObject::from_row)?
-                .collect::<Result<Vec<DbObject>, _>>()?;
+                    Ok(EndpointDeployStatus {
+                        endpoint: row.get(0)?,
+                        status,
+                        error: row.get(2)?,
+                        deployed_at: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(statuses)
+        })
+        .await
+}
 
-            Ok(DbObjects {
-                acme_accounts,
-                dns_providers,
-                endpoints,
-            })
+/// Mark a certificate as revoked.
+pub async fn record_revocation(
+    state: &Arc<State>,
+    subdomain: String,
+    serial: String,
+    reason: Option<String>,
+) -> Result<()> {
+    state
+        .pool
+        .interact(move |conn| {
+            conn.execute(
+                "UPDATE certificates SET revoked_at=?, revocation_reason=? WHERE subdomain=? AND serial=?",
+                params![
+                    OffsetDateTime::now_utc().unix_timestamp(),
+                    reason,
+                    subdomain,
+                    serial
+                ],
+            )?;
+            Ok::<_, eyre::Report>(())
         })
         .await
 }
@@ -1,4 +1,12 @@
+pub mod aws;
 pub mod digitalocean;
+pub mod fly;
+pub mod heroku;
+pub mod kubernetes;
+pub mod local_file;
+pub mod ssh;
+pub mod vercel;
+pub mod webhook;
 
 use async_trait::async_trait;
 use eyre::Result;
@@ -7,30 +15,125 @@ use strum::{Display, EnumIter, EnumString, EnumVariantNames};
 
 use crate::{cmd::State, Certificate};
 
-use self::digitalocean::DigitalOceanCreds;
+use self::{
+    aws::AwsConfig, digitalocean::DigitalOceanCreds, fly::FlyCreds, heroku::HerokuCreds,
+    kubernetes::KubernetesConfig, local_file::LocalFileConfig, ssh::SshConfig, vercel::VercelCreds,
+    webhook::WebhookConfig,
+};
 
-#[derive(Debug, Display, EnumIter, EnumString, EnumVariantNames)]
+#[derive(Clone, Debug, Display, EnumIter, EnumString, EnumVariantNames)]
 pub enum EndpointProviderType {
+    Aws,
     DigitalOcean,
+    Fly,
+    Heroku,
+    Kubernetes,
+    LocalFile,
+    Ssh,
+    Vercel,
+    Webhook,
+}
+
+/// Values used when a deployer has to create the endpoint, instead of prompting for them.
+#[derive(Clone, Debug, Default)]
+pub struct DeployOptions {
+    /// The origin that a newly-created CDN endpoint should serve from
+    pub origin: Option<String>,
 }
 
 #[async_trait]
 pub trait DeployEndpoint: Send + Sync {
     async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()>;
+
+    /// Describe what deploying would change, one line per change, using only requests that
+    /// don't change anything. `cert` is the certificate that would be deployed, if it's known.
+    async fn plan_deploy(
+        &self,
+        cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>>;
+
+    /// Whether the provider itself serves the subdomain over TLS once the certificate is
+    /// deployed, so that connecting to the subdomain shows whether the deploy took effect.
+    fn serves_subdomain(&self) -> bool {
+        false
+    }
 }
 
-pub fn create_deployer(
+pub async fn create_deployer(
     state: Arc<State>,
     deployer_type: EndpointProviderType,
     subdomain: String,
     creds: String,
+    options: DeployOptions,
 ) -> Result<Box<dyn DeployEndpoint>> {
-    let deployer = match deployer_type {
+    let deployer: Box<dyn DeployEndpoint> = match deployer_type {
+        EndpointProviderType::Aws => {
+            let config = AwsConfig::from_string(creds).await?;
+            Box::new(aws::Aws::new(state, config, subdomain)?)
+        }
         EndpointProviderType::DigitalOcean => {
-            let creds = DigitalOceanCreds::from_string_or_env(creds)?;
-            Box::new(digitalocean::DigitalOcean::new(state, creds, subdomain)?)
+            let creds = DigitalOceanCreds::from_string_or_env(creds).await?;
+            Box::new(digitalocean::DigitalOcean::new(
+                state, creds, subdomain, options,
+            )?)
+        }
+        EndpointProviderType::Fly => {
+            let creds = FlyCreds::from_string(creds).await?;
+            Box::new(fly::Fly::new(state, creds, subdomain)?)
+        }
+        EndpointProviderType::Heroku => {
+            let creds = HerokuCreds::from_string(creds).await?;
+            Box::new(heroku::Heroku::new(state, creds, subdomain)?)
+        }
+        EndpointProviderType::Kubernetes => {
+            let config = KubernetesConfig::from_string(creds).await?;
+            Box::new(kubernetes::Kubernetes::new(state, config, subdomain)?)
+        }
+        EndpointProviderType::LocalFile => {
+            let config = LocalFileConfig::from_string(creds).await?;
+            Box::new(local_file::LocalFile::new(state, config, subdomain)?)
+        }
+        EndpointProviderType::Ssh => {
+            let config = SshConfig::from_string(creds).await?;
+            Box::new(ssh::Ssh::new(state, config, subdomain)?)
+        }
+        EndpointProviderType::Vercel => {
+            let creds = VercelCreds::from_string_or_env(creds).await?;
+            Box::new(vercel::Vercel::new(state, creds, subdomain)?)
+        }
+        EndpointProviderType::Webhook => {
+            let config = WebhookConfig::from_string(creds).await?;
+            Box::new(webhook::Webhook::new(state, config, subdomain)?)
         }
     };
 
     Ok(deployer)
 }
+
+/// Check that the credentials for an endpoint provider work, without changing anything.
+pub async fn validate_creds(deployer_type: EndpointProviderType, creds: String) -> Result<()> {
+    match deployer_type {
+        EndpointProviderType::Aws => AwsConfig::from_string(creds).await?.validate().await,
+        EndpointProviderType::DigitalOcean => {
+            DigitalOceanCreds::from_string_or_env(creds)
+                .await?
+                .validate()
+                .await
+        }
+        EndpointProviderType::Fly => FlyCreds::from_string(creds).await?.validate().await,
+        EndpointProviderType::Heroku => HerokuCreds::from_string(creds).await?.validate().await,
+        EndpointProviderType::Kubernetes => {
+            KubernetesConfig::from_string(creds).await?.validate().await
+        }
+        EndpointProviderType::LocalFile => LocalFileConfig::from_string(creds).await?.validate(),
+        EndpointProviderType::Ssh => SshConfig::from_string(creds).await?.validate().await,
+        EndpointProviderType::Vercel => {
+            VercelCreds::from_string_or_env(creds)
+                .await?
+                .validate()
+                .await
+        }
+        EndpointProviderType::Webhook => WebhookConfig::from_string(creds).await?.validate().await,
+    }
+}
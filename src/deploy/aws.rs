@@ -0,0 +1,432 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_sdk_acm::{
+    error::DisplayErrorContext,
+    primitives::Blob,
+    types::{CertificateType, Filters, KeyAlgorithm},
+};
+use aws_sdk_cloudfront::types::{MinimumProtocolVersion, SslSupportMethod, ViewerCertificate};
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+
+use crate::{cmd::State, Certificate};
+
+use super::DeployEndpoint;
+
+/// CloudFront only uses certificates from this region.
+const CLOUDFRONT_REGION: &str = "us-east-1";
+
+/// Where to import certificates in AWS Certificate Manager, and what to attach new ones to.
+/// Credentials that aren't given here come from the usual AWS sources, such as the
+/// `AWS_ACCESS_KEY_ID` environment variable, `~/.aws/credentials`, or an instance role.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AwsConfig {
+    /// The region to import certificates into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// A profile from the AWS config files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_key_id: Option<String>,
+    /// The secret access key, or a reference to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_access_key: Option<String>,
+    /// The ARN of the certificate to reimport over. If this isn't set, the imported certificate
+    /// for the subdomain is found by its domain name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate_arn: Option<String>,
+    /// An ALB listener to use a newly-imported certificate as the default for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listener_arn: Option<String>,
+    /// A CloudFront distribution to use a newly-imported certificate for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distribution_id: Option<String>,
+}
+
+impl AwsConfig {
+    pub async fn from_string(creds: String) -> Result<Self> {
+        if creds.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let config: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the AWS endpoint configuration")?;
+        Ok(config)
+    }
+
+    /// Check that the settings fit together, without contacting AWS.
+    pub fn check(&self) -> Result<()> {
+        if self.access_key_id.is_some() != self.secret_access_key.is_some() {
+            return Err(eyre!(
+                "The access key ID and secret access key must be given together"
+            ));
+        }
+
+        if self.listener_arn.is_some() && self.distribution_id.is_some() {
+            return Err(eyre!(
+                "Choose either a load balancer listener or a CloudFront distribution, not both"
+            ));
+        }
+
+        if self.distribution_id.is_some()
+            && self.region.as_deref().unwrap_or(CLOUDFRONT_REGION) != CLOUDFRONT_REGION
+        {
+            return Err(eyre!(
+                "CloudFront only uses certificates imported into {CLOUDFRONT_REGION}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn sdk_config(&self) -> Result<SdkConfig> {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+
+        // CloudFront needs the certificate in us-east-1, wherever the other defaults point.
+        let region = match (&self.region, &self.distribution_id) {
+            (Some(region), _) => Some(region.clone()),
+            (None, Some(_)) => Some(CLOUDFRONT_REGION.to_string()),
+            (None, None) => None,
+        };
+        if let Some(region) = region {
+            loader = loader.region(Region::new(region));
+        }
+
+        if let Some(profile) = &self.profile {
+            loader = loader.profile_name(profile);
+        }
+
+        if let (Some(key_id), Some(secret)) = (&self.access_key_id, &self.secret_access_key) {
+            let secret = crate::creds::resolve(secret).await?;
+            loader = loader.credentials_provider(aws_sdk_acm::config::Credentials::new(
+                key_id,
+                secret,
+                None,
+                None,
+                "remote-ssl-renewal",
+            ));
+        }
+
+        let config = loader.load().await;
+        if config.region().is_none() {
+            return Err(eyre!(
+                "No AWS region is configured. Pass --aws-region, or set AWS_REGION."
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// Make a read-only request to check that the credentials work.
+    pub async fn validate(&self) -> Result<()> {
+        self.check()?;
+        let config = self.sdk_config().await?;
+        aws_sdk_acm::Client::new(&config)
+            .list_certificates()
+            .max_items(1)
+            .send()
+            .await
+            .map_err(|e| eyre!("AWS rejected the credentials: {}", DisplayErrorContext(e)))?;
+        Ok(())
+    }
+
+    pub fn from_console() -> Result<Self> {
+        let optional = |prompt: &str| -> Result<Option<String>> {
+            let value: String = dialoguer::Input::new()
+                .with_prompt(prompt)
+                .allow_empty(true)
+                .interact_text()?;
+            Ok(Some(value).filter(|v| !v.is_empty()))
+        };
+
+        let config = AwsConfig {
+            region: optional("Region (or blank to use the AWS default)")?,
+            profile: optional("AWS profile (or blank to use the default credentials)")?,
+            listener_arn: optional("ALB listener ARN to attach new certificates to (or blank)")?,
+            distribution_id: optional(
+                "CloudFront distribution ID to attach new certificates to (or blank)",
+            )?,
+            ..Default::default()
+        };
+        config.check()?;
+
+        Ok(config)
+    }
+}
+
+pub struct Aws {
+    state: Arc<State>,
+    config: AwsConfig,
+    subdomain: String,
+}
+
+impl Aws {
+    pub fn new(state: Arc<State>, config: AwsConfig, subdomain: String) -> Result<Self> {
+        config.check()?;
+        Ok(Aws {
+            state,
+            config,
+            subdomain,
+        })
+    }
+
+    /// Find the certificate that was imported for the subdomain before, if any.
+    async fn find_existing_certificate(&self, acm: &aws_sdk_acm::Client) -> Result<Option<String>> {
+        if let Some(arn) = &self.config.certificate_arn {
+            return Ok(Some(arn.clone()));
+        }
+
+        // Without a filter, only RSA 2048 certificates are listed.
+        let key_types = KeyAlgorithm::values()
+            .iter()
+            .map(|value| KeyAlgorithm::from(*value))
+            .collect();
+        let mut pages = acm
+            .list_certificates()
+            .includes(Filters::builder().set_key_types(Some(key_types)).build())
+            .into_paginator()
+            .items()
+            .send();
+
+        while let Some(summary) = pages.next().await {
+            let summary = summary.map_err(|e| {
+                eyre!(
+                    "Failed to list ACM certificates: {}",
+                    DisplayErrorContext(e)
+                )
+            })?;
+
+            let same_domain = summary
+                .domain_name()
+                .map(|name| name.eq_ignore_ascii_case(&self.subdomain))
+                .unwrap_or(false);
+            if same_domain && summary.r#type() == Some(&CertificateType::Imported) {
+                return Ok(summary.certificate_arn().map(|arn| arn.to_string()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Import the certificate, over the existing one if there is an ARN. Returns the ARN.
+    async fn import_certificate(
+        &self,
+        acm: &aws_sdk_acm::Client,
+        cert: &Certificate,
+        arn: Option<String>,
+    ) -> Result<String> {
+        let output = acm
+            .import_certificate()
+            .set_certificate_arn(arn)
+            .certificate(Blob::new(cert.get_leaf_certificate()))
+            .certificate_chain(Blob::new(cert.get_certificate_chain()))
+            .private_key(Blob::new(cert.key.as_bytes()))
+            .send()
+            .await
+            .map_err(|e| {
+                eyre!(
+                    "Failed to import the certificate into ACM: {}",
+                    DisplayErrorContext(e)
+                )
+            })?;
+
+        output
+            .certificate_arn()
+            .map(|arn| arn.to_string())
+            .ok_or_else(|| eyre!("ACM did not return the ARN of the imported certificate"))
+    }
+
+    /// Make the certificate the listener's default, unless it already is.
+    async fn attach_to_listener(
+        &self,
+        config: &SdkConfig,
+        listener: &str,
+        arn: &str,
+    ) -> Result<()> {
+        let elb = aws_sdk_elasticloadbalancingv2::Client::new(config);
+        let listeners = elb
+            .describe_listeners()
+            .listener_arns(listener)
+            .send()
+            .await
+            .map_err(|e| {
+                eyre!(
+                    "Failed to look up listener {listener}: {}",
+                    aws_sdk_elasticloadbalancingv2::error::DisplayErrorContext(e)
+                )
+            })?;
+
+        let attached = listeners.listeners().iter().any(|l| {
+            l.certificates()
+                .iter()
+                .any(|c| c.is_default() == Some(true) && c.certificate_arn() == Some(arn))
+        });
+        if attached {
+            return Ok(());
+        }
+
+        elb.modify_listener()
+            .listener_arn(listener)
+            .certificates(
+                aws_sdk_elasticloadbalancingv2::types::Certificate::builder()
+                    .certificate_arn(arn)
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|e| {
+                eyre!(
+                    "Failed to update listener {listener}: {}",
+                    aws_sdk_elasticloadbalancingv2::error::DisplayErrorContext(e)
+                )
+            })?;
+
+        Ok(())
+    }
+
+    /// Serve the distribution with the certificate, unless it already is.
+    async fn attach_to_distribution(
+        &self,
+        config: &SdkConfig,
+        distribution: &str,
+        arn: &str,
+    ) -> Result<()> {
+        let cloudfront = aws_sdk_cloudfront::Client::new(config);
+        let current = cloudfront
+            .get_distribution_config()
+            .id(distribution)
+            .send()
+            .await
+            .map_err(|e| {
+                eyre!(
+                    "Failed to look up distribution {distribution}: {}",
+                    aws_sdk_cloudfront::error::DisplayErrorContext(e)
+                )
+            })?;
+
+        let etag = current.e_tag().map(|etag| etag.to_string());
+        let mut distribution_config = current.distribution_config.ok_or_else(|| {
+            eyre!("CloudFront did not return the configuration of distribution {distribution}")
+        })?;
+
+        let viewer_certificate = distribution_config.viewer_certificate.take();
+        if let Some(viewer_certificate) = &viewer_certificate {
+            if viewer_certificate.acm_certificate_arn.as_deref() == Some(arn) {
+                return Ok(());
+            }
+        }
+
+        // Keep the existing minimum TLS version if there is one. The default certificate's
+        // minimum can't be used with a custom certificate, so pick the recommended one instead.
+        let minimum_protocol_version = viewer_certificate
+            .filter(|v| v.cloud_front_default_certificate != Some(true))
+            .and_then(|v| v.minimum_protocol_version)
+            .unwrap_or(MinimumProtocolVersion::TlSv122021);
+        distribution_config.viewer_certificate = Some(
+            ViewerCertificate::builder()
+                .acm_certificate_arn(arn)
+                .ssl_support_method(SslSupportMethod::SniOnly)
+                .minimum_protocol_version(minimum_protocol_version)
+                .cloud_front_default_certificate(false)
+                .build(),
+        );
+
+        cloudfront
+            .update_distribution()
+            .id(distribution)
+            .set_if_match(etag)
+            .distribution_config(distribution_config)
+            .send()
+            .await
+            .map_err(|e| {
+                eyre!(
+                    "Failed to update distribution {distribution}: {}",
+                    aws_sdk_cloudfront::error::DisplayErrorContext(e)
+                )
+            })?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for Aws {
+    /// Import the certificate into ACM. Reimporting over the existing certificate keeps its ARN,
+    /// so anything using it picks up the new certificate by itself. A newly-imported certificate
+    /// is attached to the configured listener or distribution.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Importing certificate into ACM"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        let config = self.config.sdk_config().await?;
+        let acm = aws_sdk_acm::Client::new(&config);
+
+        let existing = self.find_existing_certificate(&acm).await?;
+        if existing.is_none() && endpoint_must_exist {
+            progress.finish_and_clear();
+            return Err(eyre!(
+                "No certificate for {} has been imported into ACM",
+                self.subdomain
+            ));
+        }
+
+        let arn = self.import_certificate(&acm, &cert, existing).await?;
+
+        if let Some(listener) = &self.config.listener_arn {
+            progress.set_message("Updating load balancer listener");
+            self.attach_to_listener(&config, listener, &arn).await?;
+        } else if let Some(distribution) = &self.config.distribution_id {
+            progress.set_message("Updating CloudFront distribution");
+            self.attach_to_distribution(&config, distribution, &arn)
+                .await?;
+        }
+
+        progress.finish_with_message(format!("Imported as {arn}"));
+
+        Ok(())
+    }
+
+    async fn plan_deploy(
+        &self,
+        _cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        let config = self.config.sdk_config().await?;
+        let acm = aws_sdk_acm::Client::new(&config);
+
+        let mut plan = match self.find_existing_certificate(&acm).await? {
+            Some(arn) => vec![format!("Would reimport the certificate over {arn}")],
+            None if endpoint_must_exist => {
+                return Err(eyre!(
+                    "No certificate for {} has been imported into ACM",
+                    self.subdomain
+                ))
+            }
+            None => vec!["Would import the certificate into ACM".to_string()],
+        };
+
+        if let Some(listener) = &self.config.listener_arn {
+            plan.push(format!(
+                "Would make it the default certificate of listener {listener}, unless it already is"
+            ));
+        } else if let Some(distribution) = &self.config.distribution_id {
+            plan.push(format!(
+                "Would serve CloudFront distribution {distribution} with it, unless it already does"
+            ));
+        }
+
+        Ok(plan)
+    }
+
+    /// An imported certificate only serves the subdomain through a listener or distribution.
+    fn serves_subdomain(&self) -> bool {
+        self.config.listener_arn.is_some() || self.config.distribution_id.is_some()
+    }
+}
@@ -1,7 +1,7 @@
 use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use eyre::{eyre, Result};
+use eyre::{eyre, Result, WrapErr};
 use indicatif::ProgressBar;
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
@@ -10,26 +10,68 @@ use time::macros::format_description;
 
 use crate::{cmd::State, Certificate};
 
-use super::DeployEndpoint;
+use super::{DeployEndpoint, DeployOptions};
+
+/// Where on DigitalOcean the certificate is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigitalOceanMode {
+    /// The Spaces CDN endpoint for the subdomain, which is created if needed
+    #[default]
+    Cdn,
+    /// The load balancers whose forwarding rules use the subdomain's previous certificate
+    LoadBalancer,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct DigitalOceanCreds {
     token: String,
+    #[serde(default)]
+    pub mode: DigitalOceanMode,
 }
 
 impl DigitalOceanCreds {
-    pub fn from_string_or_env(creds: String) -> Result<Self> {
+    pub fn new(token: String) -> DigitalOceanCreds {
+        DigitalOceanCreds {
+            token,
+            mode: DigitalOceanMode::default(),
+        }
+    }
+
+    pub async fn from_string_or_env(creds: String) -> Result<Self> {
         if creds.is_empty() {
             Self::from_env()
         } else {
-            let creds: Self = serde_json::from_str(&creds)?;
+            let mut creds: Self = crate::creds::parse(&creds, Self::new).await?;
+            creds.token = crate::creds::resolve(&creds.token).await?;
             Ok(creds)
         }
     }
 
     pub fn from_env() -> Result<DigitalOceanCreds> {
         let token = std::env::var("DIGITAL_OCEAN_TOKEN")?;
-        Ok(DigitalOceanCreds { token })
+        Ok(DigitalOceanCreds::new(token))
+    }
+
+    /// Make a cheap authenticated request to check that the token works.
+    pub async fn validate(&self) -> Result<()> {
+        let response = Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .build()?
+            .get("https://api.digitalocean.com/v2/account")
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!(
+                "DigitalOcean rejected the API token: {status} {body}"
+            ));
+        }
+
+        Ok(())
     }
 
     pub fn from_console() -> Result<Option<DigitalOceanCreds>> {
@@ -41,15 +83,31 @@ impl DigitalOceanCreds {
         if token.is_empty() {
             Ok(None)
         } else {
-            Ok(Some(DigitalOceanCreds { token }))
+            Ok(Some(DigitalOceanCreds::new(token)))
         }
     }
+
+    /// Ask whether the certificate goes on a CDN endpoint or load balancers.
+    pub fn mode_from_console() -> Result<DigitalOceanMode> {
+        let selection = dialoguer::Select::new()
+            .with_prompt("Where is the certificate used?")
+            .items(&["Spaces CDN endpoint", "Load balancers"])
+            .default(0)
+            .interact()?;
+
+        Ok(match selection {
+            0 => DigitalOceanMode::Cdn,
+            _ => DigitalOceanMode::LoadBalancer,
+        })
+    }
 }
 
 #[derive(Deserialize)]
 struct DOCertificate {
     id: String,
     name: String,
+    #[serde(default)]
+    dns_names: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -73,28 +131,99 @@ struct DOEndpoint {
 }
 
 #[derive(Deserialize)]
-struct DOEndpointResponse {
-    endpoint: DOEndpoint,
+struct DOEndpointsResponse {
+    endpoints: Vec<DOEndpoint>,
 }
 
+/// Load balancers are kept as JSON, since updating one means sending back all of its settings.
 #[derive(Deserialize)]
-struct DOEndpointsResponse {
-    endpoints: Vec<DOEndpoint>,
+struct DOLoadBalancersResponse {
+    load_balancers: Vec<serde_json::Value>,
+}
+
+/// The fields of a load balancer that DigitalOcean sets itself, which can't be sent back in an
+/// update. Everything else is sent as it was fetched, since anything left out is reset.
+const LOAD_BALANCER_READ_ONLY: &[&str] = &["id", "ip", "ipv6", "status", "created_at"];
+
+/// Build the update for a load balancer that points the forwarding rules using one of the
+/// previous certificates at the new one. Returns the update and the IDs of the certificates that
+/// were replaced.
+fn load_balancer_update(
+    load_balancer: &serde_json::Value,
+    previous: &[String],
+    cert_id: &str,
+) -> Result<(serde_json::Map<String, serde_json::Value>, Vec<String>)> {
+    let mut payload = load_balancer
+        .as_object()
+        .cloned()
+        .ok_or_else(|| eyre!("DigitalOcean returned a load balancer that isn't an object"))?;
+    payload.retain(|setting, value| {
+        !value.is_null() && !LOAD_BALANCER_READ_ONLY.contains(&setting.as_str())
+    });
+
+    // Newer load balancers are sized by node count, and older ones by a size slug. Only one
+    // of them can be sent.
+    if payload.contains_key("size_unit") {
+        payload.remove("size");
+    }
+
+    // The region comes back as an object, but is set by its slug.
+    if let Some(region) = load_balancer["region"]["slug"].as_str() {
+        payload.insert("region".to_string(), json!(region));
+    }
+
+    // A load balancer targets either a tag or a list of droplets, but not both.
+    match load_balancer["tag"].as_str() {
+        Some(tag) if !tag.is_empty() => {
+            payload.remove("droplet_ids");
+        }
+        _ => {
+            payload.remove("tag");
+        }
+    }
+
+    let mut replaced = Vec::new();
+    if let Some(rules) = payload
+        .get_mut("forwarding_rules")
+        .and_then(|rules| rules.as_array_mut())
+    {
+        for rule in rules {
+            let Some(old) = rule["certificate_id"].as_str().map(|id| id.to_string()) else {
+                continue;
+            };
+
+            if previous.contains(&old) {
+                rule["certificate_id"] = json!(cert_id);
+                if !replaced.contains(&old) {
+                    replaced.push(old);
+                }
+            }
+        }
+    }
+
+    Ok((payload, replaced))
 }
 
 pub struct DigitalOcean {
     state: Arc<State>,
     creds: DigitalOceanCreds,
     subdomain: String,
+    options: DeployOptions,
     client: Client,
 }
 
 impl DigitalOcean {
-    pub fn new(state: Arc<State>, creds: DigitalOceanCreds, subdomain: String) -> Result<Self> {
+    pub fn new(
+        state: Arc<State>,
+        creds: DigitalOceanCreds,
+        subdomain: String,
+        options: DeployOptions,
+    ) -> Result<Self> {
         Ok(DigitalOcean {
             state,
             creds,
             subdomain,
+            options,
             client: Client::builder().user_agent(crate::USER_AGENT).build()?,
         })
     }
@@ -216,12 +345,13 @@ impl DigitalOcean {
     }
 
     async fn create_endpoint(&self, cert_id: &str) -> Result<()> {
-        let origin: String = {
+        let origin = crate::cli::value_or_prompt(self.options.origin.clone(), "--origin", || {
             let _hider = self.state.hide_progress();
-            dialoguer::Input::new()
+            Ok(dialoguer::Input::new()
                 .with_prompt("Enter the Spaces origin FQDN for this endpoint. This can be found in the Digital Ocean Spaces configuration")
-                .interact_text()?
-        };
+                .interact_text()?)
+        })
+        .wrap_err_with(|| format!("CDN Endpoint for {} does not exist", self.subdomain))?;
 
         let payload = json!({
             "origin": origin,
@@ -279,13 +409,184 @@ impl DigitalOcean {
             page += 1;
         }
     }
-}
 
-#[async_trait]
-impl DeployEndpoint for DigitalOcean {
-    /// Find the deployment by the subdomain, and deploy the new certificate. If the deployment
+    /// Return the IDs of the uploaded certificates that the new certificate replaces: those for
+    /// the subdomain whose names the new certificate all covers, other than the new one itself.
+    /// Without the new certificate, every one for the subdomain counts.
+    async fn find_previous_certificates(
+        &self,
+        cert: Option<&Certificate>,
+        new_id: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let mut previous = Vec::new();
+        let mut page = 1;
+        loop {
+            let certs = self
+                .client
+                .get("https://api.digitalocean.com/v2/certificates")
+                .query(&[("page", page), ("per_page", 200)])
+                .bearer_auth(&self.creds.token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<DOCertificatesResponse>()
+                .await?;
+
+            if certs.certificates.is_empty() {
+                return Ok(previous);
+            }
+
+            for old in certs.certificates {
+                if Some(old.id.as_str()) == new_id
+                    || !old
+                        .dns_names
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(&self.subdomain))
+                {
+                    continue;
+                }
+
+                let mut covered = true;
+                if let Some(cert) = cert {
+                    for name in &old.dns_names {
+                        covered &= cert.covers(name)?;
+                    }
+                }
+
+                if covered {
+                    previous.push(old.id);
+                }
+            }
+
+            page += 1;
+        }
+    }
+
+    async fn list_load_balancers(&self) -> Result<Vec<serde_json::Value>> {
+        let mut load_balancers = Vec::new();
+        let mut page = 1;
+        loop {
+            let result = self
+                .client
+                .get("https://api.digitalocean.com/v2/load_balancers")
+                .query(&[("page", page), ("per_page", 200)])
+                .bearer_auth(&self.creds.token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<DOLoadBalancersResponse>()
+                .await?;
+
+            if result.load_balancers.is_empty() {
+                return Ok(load_balancers);
+            }
+
+            load_balancers.extend(result.load_balancers);
+            page += 1;
+        }
+    }
+
+    /// Point the forwarding rules that use one of the previous certificates at the new one.
+    /// Returns the IDs of the certificates that were replaced.
+    async fn set_load_balancer_cert(
+        &self,
+        load_balancer: &serde_json::Value,
+        previous: &[String],
+        cert_id: &str,
+    ) -> Result<Vec<String>> {
+        let (payload, replaced) = load_balancer_update(load_balancer, previous, cert_id)?;
+        if replaced.is_empty() {
+            return Ok(replaced);
+        }
+
+        let id = load_balancer["id"]
+            .as_str()
+            .ok_or_else(|| eyre!("DigitalOcean returned a load balancer without an ID"))?;
+        let name = load_balancer["name"].as_str().unwrap_or(id);
+
+        let response = self
+            .client
+            .put(format!(
+                "https://api.digitalocean.com/v2/load_balancers/{id}"
+            ))
+            .bearer_auth(&self.creds.token)
+            .json(&payload)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!(
+                "Failed to update load balancer {name}: {status} {body}"
+            ));
+        }
+
+        Ok(replaced)
+    }
+
+    /// Swap the new certificate into every load balancer that uses one of the subdomain's
+    /// previous certificates, and only then delete the certificates that were replaced.
+    async fn deploy_to_load_balancers(&self, cert: Certificate) -> Result<()> {
+        let cert_id = self.upload_certificate(cert.clone()).await?;
+
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Finding load balancers"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        let previous = self
+            .find_previous_certificates(Some(&cert), Some(&cert_id))
+            .await?;
+        let mut replaced: Vec<String> = Vec::new();
+        let mut already_used = false;
+        for load_balancer in self.list_load_balancers().await? {
+            let uses_new = load_balancer["forwarding_rules"]
+                .as_array()
+                .map(|rules| {
+                    rules
+                        .iter()
+                        .any(|rule| rule["certificate_id"].as_str() == Some(cert_id.as_str()))
+                })
+                .unwrap_or(false);
+            already_used |= uses_new;
+
+            if let Some(name) = load_balancer["name"].as_str() {
+                progress.set_message(format!("Updating load balancer {name}"));
+            }
+
+            for old in self
+                .set_load_balancer_cert(&load_balancer, &previous, &cert_id)
+                .await?
+            {
+                if !replaced.contains(&old) {
+                    replaced.push(old);
+                }
+            }
+        }
+
+        if replaced.is_empty() && !already_used {
+            progress.finish_and_clear();
+            return Err(eyre!(
+                "No load balancer uses an earlier certificate for {}. The new certificate was uploaded, so choose it in the load balancer's forwarding rules to finish setting it up.",
+                self.subdomain
+            ));
+        }
+
+        progress.set_message("Removing old certificates");
+        for old in &replaced {
+            self.remove_cert(old).await?;
+        }
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    /// Find the CDN endpoint by the subdomain, and deploy the new certificate. If the endpoint
     /// does not yet exist, prompt for the relevant information.
-    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+    async fn deploy_to_cdn(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
         let cert_id = self.upload_certificate(cert).await?;
 
         if let Some(endpoint) = self.find_existing_endpoint().await? {
@@ -311,4 +612,224 @@ impl DeployEndpoint for DigitalOcean {
 
         Ok(())
     }
+
+    async fn plan_cdn(&self, endpoint_must_exist: bool) -> Result<Vec<String>> {
+        match self.find_existing_endpoint().await? {
+            Some(endpoint) => Ok(vec![
+                format!(
+                    "Would upload the certificate and use it for CDN endpoint {} ({})",
+                    endpoint.id, endpoint.custom_domain
+                ),
+                format!(
+                    "Would delete certificate {}, which the endpoint uses now",
+                    endpoint.certificate_id
+                ),
+            ]),
+            None if endpoint_must_exist => {
+                Err(eyre!("CDN Endpoint for {} does not exist", self.subdomain))
+            }
+            None => {
+                let origin = match &self.options.origin {
+                    Some(origin) => format!("serving from {origin}"),
+                    None => "after asking for its origin".to_string(),
+                };
+                Ok(vec![format!(
+                    "Would upload the certificate and create a CDN endpoint for {}, {origin}",
+                    self.subdomain
+                )])
+            }
+        }
+    }
+
+    async fn plan_load_balancers(&self, cert: Option<&Certificate>) -> Result<Vec<String>> {
+        let previous = self.find_previous_certificates(cert, None).await?;
+
+        let mut plan = vec!["Would upload the certificate".to_string()];
+        let mut replaced: Vec<String> = Vec::new();
+        for load_balancer in self.list_load_balancers().await? {
+            let mut uses = load_balancer["forwarding_rules"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|rule| rule["certificate_id"].as_str())
+                .filter(|id| previous.iter().any(|old| old == id))
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>();
+            uses.sort();
+            uses.dedup();
+            if uses.is_empty() {
+                continue;
+            }
+
+            let name = load_balancer["name"]
+                .as_str()
+                .or_else(|| load_balancer["id"].as_str())
+                .unwrap_or_default();
+            plan.push(format!(
+                "Would switch load balancer {name} from certificate {} to the new one",
+                uses.join(", ")
+            ));
+            for old in uses {
+                if !replaced.contains(&old) {
+                    replaced.push(old);
+                }
+            }
+        }
+
+        if replaced.is_empty() {
+            return Err(eyre!(
+                "No load balancer uses an earlier certificate for {}, so the new certificate would have to be chosen in the load balancer's forwarding rules by hand",
+                self.subdomain
+            ));
+        }
+
+        plan.push(format!(
+            "Would delete certificate {} afterward",
+            replaced.join(", ")
+        ));
+        Ok(plan)
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for DigitalOcean {
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        match self.creds.mode {
+            DigitalOceanMode::Cdn => self.deploy_to_cdn(cert, endpoint_must_exist).await,
+            DigitalOceanMode::LoadBalancer => self.deploy_to_load_balancers(cert).await,
+        }
+    }
+
+    async fn plan_deploy(
+        &self,
+        cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        match self.creds.mode {
+            DigitalOceanMode::Cdn => self.plan_cdn(endpoint_must_exist).await,
+            DigitalOceanMode::LoadBalancer => self.plan_load_balancers(cert).await,
+        }
+    }
+
+    fn serves_subdomain(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A load balancer as the API returns it, trimmed to the interesting fields.
+    fn load_balancer() -> serde_json::Value {
+        json!({
+            "id": "4de7ac8b-495b-4884-9a69-1050c6793cd6",
+            "name": "web",
+            "ip": "104.131.186.241",
+            "ipv6": null,
+            "status": "active",
+            "created_at": "2024-02-13T17:36:04Z",
+            "type": "REGIONAL_NETWORK",
+            "network": "EXTERNAL",
+            "network_stack": "DUALSTACK",
+            "tls_cipher_policy": "STRONG",
+            "size": "lb-small",
+            "size_unit": 2,
+            "region": { "name": "New York 3", "slug": "nyc3" },
+            "tag": "web",
+            "droplet_ids": [3164444, 3164445],
+            "domains": [],
+            "forwarding_rules": [
+                {
+                    "entry_protocol": "https",
+                    "entry_port": 443,
+                    "target_protocol": "http",
+                    "target_port": 80,
+                    "certificate_id": "old-cert",
+                    "tls_passthrough": false
+                },
+                {
+                    "entry_protocol": "http",
+                    "entry_port": 80,
+                    "target_protocol": "http",
+                    "target_port": 80,
+                    "certificate_id": "",
+                    "tls_passthrough": false
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn update_swaps_previous_certificate() {
+        let (payload, replaced) =
+            load_balancer_update(&load_balancer(), &["old-cert".to_string()], "new-cert").unwrap();
+
+        assert_eq!(replaced, vec!["old-cert".to_string()]);
+        assert_eq!(
+            payload["forwarding_rules"][0]["certificate_id"],
+            json!("new-cert")
+        );
+        assert_eq!(payload["forwarding_rules"][1]["certificate_id"], json!(""));
+    }
+
+    #[test]
+    fn update_keeps_other_settings() {
+        let (payload, _) =
+            load_balancer_update(&load_balancer(), &["old-cert".to_string()], "new-cert").unwrap();
+
+        for (setting, value) in [
+            ("name", json!("web")),
+            ("type", json!("REGIONAL_NETWORK")),
+            ("network", json!("EXTERNAL")),
+            ("network_stack", json!("DUALSTACK")),
+            ("tls_cipher_policy", json!("STRONG")),
+            ("domains", json!([])),
+            ("size_unit", json!(2)),
+            ("region", json!("nyc3")),
+            ("tag", json!("web")),
+        ] {
+            assert_eq!(payload.get(setting), Some(&value), "{setting}");
+        }
+    }
+
+    #[test]
+    fn update_leaves_out_read_only_and_conflicting_fields() {
+        let (payload, _) =
+            load_balancer_update(&load_balancer(), &["old-cert".to_string()], "new-cert").unwrap();
+
+        for setting in [
+            "id",
+            "ip",
+            "ipv6",
+            "status",
+            "created_at",
+            "size",
+            "droplet_ids",
+        ] {
+            assert!(!payload.contains_key(setting), "{setting}");
+        }
+    }
+
+    #[test]
+    fn update_targets_droplets_without_tag() {
+        let mut load_balancer = load_balancer();
+        load_balancer["tag"] = json!("");
+        load_balancer["size_unit"] = serde_json::Value::Null;
+
+        let (payload, _) =
+            load_balancer_update(&load_balancer, &["old-cert".to_string()], "new-cert").unwrap();
+
+        assert_eq!(payload["droplet_ids"], json!([3164444, 3164445]));
+        assert!(!payload.contains_key("tag"));
+        assert_eq!(payload["size"], json!("lb-small"));
+        assert!(!payload.contains_key("size_unit"));
+    }
+
+    #[test]
+    fn update_without_previous_certificate() {
+        let (_, replaced) =
+            load_balancer_update(&load_balancer(), &["other".to_string()], "new-cert").unwrap();
+        assert!(replaced.is_empty());
+    }
 }
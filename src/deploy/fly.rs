@@ -0,0 +1,251 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{cmd::State, Certificate};
+
+use super::DeployEndpoint;
+
+#[derive(Serialize, Deserialize)]
+pub struct FlyCreds {
+    token: String,
+    /// The app that serves the subdomain
+    pub app: String,
+}
+
+impl FlyCreds {
+    pub fn new(token: String, app: String) -> FlyCreds {
+        FlyCreds { token, app }
+    }
+
+    pub async fn from_string(creds: String) -> Result<Self> {
+        let mut creds: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the Fly.io endpoint configuration")?;
+        creds.token = crate::creds::resolve(&creds.token).await?;
+        Ok(creds)
+    }
+
+    fn request(&self, client: &Client, method: Method, path: &str) -> RequestBuilder {
+        let request = client.request(method, format!("https://api.machines.dev/v1{path}"));
+
+        // Tokens from `fly tokens create` carry their own scheme, while older ones are bearer
+        // tokens.
+        if self.token.starts_with("FlyV1 ") {
+            request.header("Authorization", &self.token)
+        } else {
+            request.bearer_auth(&self.token)
+        }
+    }
+
+    /// Make a cheap authenticated request to check that the token works and can see the app.
+    pub async fn validate(&self) -> Result<()> {
+        let client = Client::builder().user_agent(crate::USER_AGENT).build()?;
+        let response = self
+            .request(&client, Method::GET, &format!("/apps/{}", self.app))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(eyre!("Fly.io app {} does not exist", self.app));
+        } else if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!("Fly.io rejected the API token: {status} {body}"));
+        }
+
+        Ok(())
+    }
+
+    /// Prompt for whichever of the token and app weren't given on the command line.
+    pub fn from_console(token: Option<String>, app: Option<String>) -> Result<FlyCreds> {
+        let app = match app {
+            Some(app) => app,
+            None => dialoguer::Input::new()
+                .with_prompt("Fly.io app name")
+                .interact_text()?,
+        };
+        let token = match token {
+            Some(token) => token,
+            None => dialoguer::Input::new()
+                .with_prompt("Fly.io API token")
+                .interact_text()?,
+        };
+
+        Ok(FlyCreds { token, app })
+    }
+}
+
+pub struct Fly {
+    state: Arc<State>,
+    creds: FlyCreds,
+    subdomain: String,
+    client: Client,
+}
+
+impl Fly {
+    pub fn new(state: Arc<State>, creds: FlyCreds, subdomain: String) -> Result<Self> {
+        Ok(Fly {
+            state,
+            creds,
+            subdomain,
+            client: Client::builder().user_agent(crate::USER_AGENT).build()?,
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.creds.request(&self.client, method, path)
+    }
+
+    /// Look up the subdomain as a certificate hostname of the app, if it has been added.
+    async fn hostname(&self) -> Result<Option<FlyHostname>> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/apps/{}/certificates/{}", self.creds.app, self.subdomain),
+            )
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else if status.is_success() {
+            Ok(Some(response.json::<FlyHostname>().await?))
+        } else {
+            let body = response.text().await?;
+            Err(eyre!(
+                "Failed to look up the certificate for {}: {status} {body}",
+                self.subdomain
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FlyHostname {
+    #[serde(default)]
+    certificates: Vec<FlyCertificate>,
+}
+
+#[derive(Deserialize)]
+struct FlyCertificate {
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+impl FlyHostname {
+    /// Check whether the hostname already serves the certificate as its custom certificate.
+    /// Fly.io doesn't report the serial number, so the certificate is recognized by the second
+    /// that it expires.
+    fn serves(&self, cert: &Certificate) -> bool {
+        let Ok(info) = cert.info() else {
+            return false;
+        };
+
+        self.certificates.iter().any(|existing| {
+            existing.source.as_deref() == Some("custom")
+                && existing
+                    .expires_at
+                    .as_deref()
+                    .and_then(|expires| OffsetDateTime::parse(expires, &Rfc3339).ok())
+                    .is_some_and(|expires| expires.unix_timestamp() == info.not_after)
+        })
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for Fly {
+    /// Upload the certificate as the hostname's custom certificate, which replaces any earlier
+    /// one, unless the hostname already serves it. The hostname is added to the app if it isn't
+    /// there yet.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Uploading certificate"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        match self.hostname().await? {
+            // This can happen when reinstalling a certificate that a failed command had already
+            // uploaded, and there's nothing left to do.
+            Some(hostname) if hostname.serves(&cert) => {
+                progress.finish_with_message("Done");
+                return Ok(());
+            }
+            None if endpoint_must_exist => {
+                progress.finish_and_clear();
+                return Err(eyre!(
+                    "{} is not a certificate hostname of Fly.io app {}",
+                    self.subdomain,
+                    self.creds.app
+                ));
+            }
+            _ => {}
+        }
+
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/apps/{}/certificates/custom", self.creds.app),
+            )
+            .json(&json!({
+                "hostname": self.subdomain,
+                "fullchain": cert.cert,
+                "private_key": cert.key,
+            }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!("Failed to upload certificate: {status} {body}"));
+        }
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    async fn plan_deploy(
+        &self,
+        cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        let plan = match self.hostname().await? {
+            Some(hostname) if cert.is_some_and(|cert| hostname.serves(cert)) => format!(
+                "{} already has the certificate in Fly.io app {}, so nothing would change",
+                self.subdomain, self.creds.app
+            ),
+            Some(_) => format!(
+                "Would replace the custom certificate of {} in Fly.io app {}",
+                self.subdomain, self.creds.app
+            ),
+            None if endpoint_must_exist => {
+                return Err(eyre!(
+                    "{} is not a certificate hostname of Fly.io app {}",
+                    self.subdomain,
+                    self.creds.app
+                ));
+            }
+            None => format!(
+                "Would add {} to Fly.io app {} with the certificate",
+                self.subdomain, self.creds.app
+            ),
+        };
+
+        Ok(vec![plan])
+    }
+
+    fn serves_subdomain(&self) -> bool {
+        true
+    }
+}
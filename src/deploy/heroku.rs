@@ -0,0 +1,291 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{cmd::State, Certificate};
+
+use super::DeployEndpoint;
+
+#[derive(Serialize, Deserialize)]
+pub struct HerokuCreds {
+    token: String,
+    /// The app whose SNI endpoint serves the subdomain
+    pub app: String,
+}
+
+impl HerokuCreds {
+    pub fn new(token: String, app: String) -> HerokuCreds {
+        HerokuCreds { token, app }
+    }
+
+    pub async fn from_string(creds: String) -> Result<Self> {
+        let mut creds: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the Heroku endpoint configuration")?;
+        creds.token = crate::creds::resolve(&creds.token).await?;
+        Ok(creds)
+    }
+
+    fn request(&self, client: &Client, method: Method, path: &str) -> RequestBuilder {
+        client
+            .request(method, format!("https://api.heroku.com{path}"))
+            .bearer_auth(&self.token)
+            .header("Accept", "application/vnd.heroku+json; version=3")
+    }
+
+    /// Make a cheap authenticated request to check that the token works and can see the app.
+    pub async fn validate(&self) -> Result<()> {
+        let client = Client::builder().user_agent(crate::USER_AGENT).build()?;
+        let response = self
+            .request(&client, Method::GET, &format!("/apps/{}", self.app))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(eyre!("Heroku app {} does not exist", self.app));
+        } else if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!("Heroku rejected the API key: {status} {body}"));
+        }
+
+        Ok(())
+    }
+
+    /// Prompt for whichever of the token and app weren't given on the command line.
+    pub fn from_console(token: Option<String>, app: Option<String>) -> Result<HerokuCreds> {
+        let app = match app {
+            Some(app) => app,
+            None => dialoguer::Input::new()
+                .with_prompt("Heroku app name")
+                .interact_text()?,
+        };
+        let token = match token {
+            Some(token) => token,
+            None => dialoguer::Input::new()
+                .with_prompt("Heroku API key")
+                .interact_text()?,
+        };
+
+        Ok(HerokuCreds { token, app })
+    }
+}
+
+#[derive(Deserialize)]
+struct SslCert {
+    #[serde(default)]
+    cert_domains: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SniEndpoint {
+    id: String,
+    name: String,
+    certificate_chain: String,
+    ssl_cert: SslCert,
+}
+
+pub struct Heroku {
+    state: Arc<State>,
+    creds: HerokuCreds,
+    subdomain: String,
+    client: Client,
+}
+
+impl Heroku {
+    pub fn new(state: Arc<State>, creds: HerokuCreds, subdomain: String) -> Result<Self> {
+        Ok(Heroku {
+            state,
+            creds,
+            subdomain,
+            client: Client::builder().user_agent(crate::USER_AGENT).build()?,
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.creds.request(&self.client, method, path)
+    }
+
+    /// Find the app's SNI endpoint whose certificate covers the subdomain, including through a
+    /// wildcard.
+    async fn find_existing_endpoint(&self) -> Result<Option<SniEndpoint>> {
+        let endpoints = self
+            .request(
+                Method::GET,
+                &format!("/apps/{}/sni-endpoints", self.creds.app),
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<SniEndpoint>>()
+            .await?;
+
+        Ok(endpoints.into_iter().find(|endpoint| {
+            let existing = Certificate {
+                cert: endpoint.certificate_chain.clone(),
+                key: String::new(),
+            };
+            existing.covers(&self.subdomain).unwrap_or_else(|_| {
+                endpoint
+                    .ssl_cert
+                    .cert_domains
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&self.subdomain))
+            })
+        }))
+    }
+
+    async fn update_endpoint(&self, endpoint: &SniEndpoint, cert: &Certificate) -> Result<()> {
+        let response = self
+            .request(
+                Method::PATCH,
+                &format!("/apps/{}/sni-endpoints/{}", self.creds.app, endpoint.id),
+            )
+            .json(&json!({
+                "certificate_chain": cert.cert,
+                "private_key": cert.key,
+            }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!(
+                "Failed to update SNI endpoint {}: {status} {body}",
+                endpoint.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Create an SNI endpoint for the certificate and point the subdomain's domain at it.
+    async fn create_endpoint(&self, cert: &Certificate) -> Result<()> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/apps/{}/sni-endpoints", self.creds.app),
+            )
+            .json(&json!({
+                "certificate_chain": cert.cert,
+                "private_key": cert.key,
+            }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!("Failed to create SNI endpoint: {status} {body}"));
+        }
+        let endpoint = response.json::<SniEndpoint>().await?;
+
+        let response = self
+            .request(
+                Method::PATCH,
+                &format!("/apps/{}/domains/{}", self.creds.app, self.subdomain),
+            )
+            .json(&json!({ "sni_endpoint": endpoint.id }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(eyre!(
+                "Created SNI endpoint {}, but {} is not a domain of Heroku app {}",
+                endpoint.name,
+                self.subdomain,
+                self.creds.app
+            ));
+        } else if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!(
+                "Created SNI endpoint {}, but failed to use it for {}: {status} {body}",
+                endpoint.name,
+                self.subdomain
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for Heroku {
+    /// Update the SNI endpoint that serves the subdomain with the new certificate, or create one
+    /// if there isn't one yet.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Uploading certificate"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        match self.find_existing_endpoint().await? {
+            // This can happen when reinstalling a certificate that a failed command had already
+            // uploaded, and there's nothing left to do.
+            Some(endpoint) if endpoint.certificate_chain.trim() == cert.cert.trim() => {}
+            Some(endpoint) => self.update_endpoint(&endpoint, &cert).await?,
+            None if endpoint_must_exist => {
+                progress.finish_and_clear();
+                return Err(eyre!(
+                    "Heroku app {} has no SNI endpoint for {}",
+                    self.creds.app,
+                    self.subdomain
+                ));
+            }
+            None => {
+                progress.set_message("Creating SNI endpoint");
+                self.create_endpoint(&cert).await?;
+            }
+        }
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    async fn plan_deploy(
+        &self,
+        cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        let plan = match self.find_existing_endpoint().await? {
+            Some(endpoint)
+                if cert
+                    .is_some_and(|cert| endpoint.certificate_chain.trim() == cert.cert.trim()) =>
+            {
+                format!(
+                    "SNI endpoint {} already has the certificate, so nothing would change",
+                    endpoint.name
+                )
+            }
+            Some(endpoint) => format!(
+                "Would replace the certificate of SNI endpoint {}",
+                endpoint.name
+            ),
+            None if endpoint_must_exist => {
+                return Err(eyre!(
+                    "Heroku app {} has no SNI endpoint for {}",
+                    self.creds.app,
+                    self.subdomain
+                ))
+            }
+            None => format!(
+                "Would create an SNI endpoint in Heroku app {} and use it for {}",
+                self.creds.app, self.subdomain
+            ),
+        };
+
+        Ok(vec![plan])
+    }
+
+    fn serves_subdomain(&self) -> bool {
+        true
+    }
+}
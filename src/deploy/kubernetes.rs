@@ -0,0 +1,524 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use k8s_openapi::{
+    api::{apps::v1::Deployment, core::v1::Secret},
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    ByteString,
+};
+use kube::{
+    api::{ListParams, Patch, PatchParams, PostParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    Api, Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{cmd::State, Certificate};
+
+use super::DeployEndpoint;
+
+const TLS_SECRET_TYPE: &str = "kubernetes.io/tls";
+
+fn default_namespace() -> String {
+    "default".to_string()
+}
+
+fn default_secret_name() -> String {
+    "{subdomain}-tls".to_string()
+}
+
+/// Which cluster to connect to and the Secret to write the certificate to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KubernetesConfig {
+    /// The kubeconfig file to use. Defaults to `$KUBECONFIG` or `~/.kube/config`, falling back to
+    /// the service account when running inside the cluster.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubeconfig: Option<String>,
+    /// The kubeconfig context to use, instead of the current one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Always use the service account of the pod this runs in
+    #[serde(default)]
+    pub in_cluster: bool,
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    /// The name of the Secret, which may contain `{subdomain}`
+    #[serde(default = "default_secret_name")]
+    pub secret_name: String,
+    /// A Deployment to restart after the Secret is updated, for servers that don't reload
+    /// certificates by themselves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<String>,
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> Self {
+        KubernetesConfig {
+            kubeconfig: None,
+            context: None,
+            in_cluster: false,
+            namespace: default_namespace(),
+            secret_name: default_secret_name(),
+            deployment: None,
+        }
+    }
+}
+
+impl KubernetesConfig {
+    pub async fn from_string(creds: String) -> Result<Self> {
+        if creds.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let config: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the Kubernetes endpoint configuration")?;
+        Ok(config)
+    }
+
+    async fn client(&self) -> Result<Client> {
+        let options = KubeConfigOptions {
+            context: self.context.clone(),
+            ..Default::default()
+        };
+
+        let config = if self.in_cluster {
+            kube::Config::incluster().wrap_err("Failed to load the in-cluster configuration")?
+        } else if let Some(path) = &self.kubeconfig {
+            let kubeconfig = Kubeconfig::read_from(path)
+                .wrap_err_with(|| format!("Failed to read kubeconfig {path}"))?;
+            kube::Config::from_custom_kubeconfig(kubeconfig, &options)
+                .await
+                .wrap_err_with(|| format!("Failed to load kubeconfig {path}"))?
+        } else if self.context.is_some() {
+            kube::Config::from_kubeconfig(&options)
+                .await
+                .wrap_err("Failed to load the kubeconfig")?
+        } else {
+            kube::Config::infer()
+                .await
+                .wrap_err("Failed to find a kubeconfig or in-cluster configuration")?
+        };
+
+        Ok(Client::try_from(config)?)
+    }
+
+    /// Check that the cluster can be reached and Secrets in the namespace can be read.
+    pub async fn validate(&self) -> Result<()> {
+        let secrets: Api<Secret> = Api::namespaced(self.client().await?, &self.namespace);
+        secrets
+            .list_metadata(&ListParams::default().limit(1))
+            .await
+            .wrap_err_with(|| format!("Failed to list Secrets in namespace {}", self.namespace))?;
+        Ok(())
+    }
+
+    pub fn from_console() -> Result<Self> {
+        let optional = |prompt: &str| -> Result<Option<String>> {
+            let value: String = dialoguer::Input::new()
+                .with_prompt(prompt)
+                .allow_empty(true)
+                .interact_text()?;
+            Ok(Some(value).filter(|v| !v.is_empty()))
+        };
+
+        let kubeconfig = optional("Kubeconfig file (or blank for the default)")?;
+        let context = optional("Context (or blank for the current one)")?;
+        let namespace = dialoguer::Input::new()
+            .with_prompt("Namespace")
+            .default(default_namespace())
+            .interact_text()?;
+        println!(
+            "The Secret name can contain {{subdomain}}, which is replaced by the subdomain's name."
+        );
+        let secret_name = dialoguer::Input::new()
+            .with_prompt("Secret name")
+            .default(default_secret_name())
+            .interact_text()?;
+        let deployment = optional("Deployment to restart afterward (or blank for none)")?;
+
+        Ok(KubernetesConfig {
+            kubeconfig,
+            context,
+            in_cluster: false,
+            namespace,
+            secret_name,
+            deployment,
+        })
+    }
+}
+
+pub struct Kubernetes {
+    state: Arc<State>,
+    config: KubernetesConfig,
+    subdomain: String,
+}
+
+impl Kubernetes {
+    pub fn new(state: Arc<State>, config: KubernetesConfig, subdomain: String) -> Result<Self> {
+        Ok(Kubernetes {
+            state,
+            config,
+            subdomain,
+        })
+    }
+}
+
+/// Create the Secret, or update an existing one. The update only applies if the Secret
+/// hasn't changed since it was read, so that a concurrent change isn't overwritten.
+async fn write_secret(
+    secrets: &Api<Secret>,
+    namespace: &str,
+    name: &str,
+    cert: &Certificate,
+    endpoint_must_exist: bool,
+) -> Result<()> {
+    let qualified = format!("{namespace}/{name}");
+
+    let Some(existing) = secrets
+        .get_opt(name)
+        .await
+        .wrap_err_with(|| format!("Failed to read Secret {qualified}"))?
+    else {
+        if endpoint_must_exist {
+            return Err(eyre!("Secret {qualified} does not exist"));
+        }
+
+        let data = BTreeMap::from([
+            (
+                "tls.crt".to_string(),
+                ByteString(cert.cert.as_bytes().to_vec()),
+            ),
+            (
+                "tls.key".to_string(),
+                ByteString(cert.key.as_bytes().to_vec()),
+            ),
+        ]);
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                ..Default::default()
+            },
+            type_: Some(TLS_SECRET_TYPE.to_string()),
+            data: Some(data),
+            ..Default::default()
+        };
+
+        secrets
+            .create(&PostParams::default(), &secret)
+            .await
+            .wrap_err_with(|| format!("Failed to create Secret {qualified}"))?;
+        return Ok(());
+    };
+
+    let secret_type = existing.type_.as_deref().unwrap_or("Opaque");
+    if secret_type != TLS_SECRET_TYPE {
+        return Err(eyre!(
+            "Secret {qualified} has type {secret_type}, not {TLS_SECRET_TYPE}"
+        ));
+    }
+
+    // A merge patch leaves any other keys, such as ca.crt, alone.
+    let patch = json!({
+        "metadata": { "resourceVersion": existing.metadata.resource_version },
+        "data": {
+            "tls.crt": ByteString(cert.cert.as_bytes().to_vec()),
+            "tls.key": ByteString(cert.key.as_bytes().to_vec()),
+        },
+    });
+
+    match secrets
+        .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(status)) if status.is_conflict() => Err(eyre!(
+            "Secret {qualified} was changed by something else while it was being updated"
+        )),
+        Err(e) => Err(e).wrap_err_with(|| format!("Failed to update Secret {qualified}")),
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for Kubernetes {
+    /// Write the certificate to the TLS Secret, and then restart the Deployment if there is one.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Updating Kubernetes Secret"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        let client = self.config.client().await?;
+        let secrets: Api<Secret> = Api::namespaced(client.clone(), &self.config.namespace);
+        let name = self
+            .config
+            .secret_name
+            .replace("{subdomain}", &self.subdomain);
+
+        write_secret(
+            &secrets,
+            &self.config.namespace,
+            &name,
+            &cert,
+            endpoint_must_exist,
+        )
+        .await?;
+
+        if let Some(deployment) = &self.config.deployment {
+            progress.set_message(format!("Restarting {deployment}"));
+            let deployments: Api<Deployment> = Api::namespaced(client, &self.config.namespace);
+            deployments.restart(deployment).await.wrap_err_with(|| {
+                format!(
+                    "Updated Secret {name}, but failed to restart Deployment {}/{deployment}",
+                    self.config.namespace
+                )
+            })?;
+        }
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    async fn plan_deploy(
+        &self,
+        _cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        let client = self.config.client().await?;
+        let secrets: Api<Secret> = Api::namespaced(client.clone(), &self.config.namespace);
+        let name = self
+            .config
+            .secret_name
+            .replace("{subdomain}", &self.subdomain);
+        let qualified = format!("{}/{name}", self.config.namespace);
+
+        let existing = secrets
+            .get_opt(&name)
+            .await
+            .wrap_err_with(|| format!("Failed to read Secret {qualified}"))?;
+        let mut plan = match existing {
+            Some(existing) => {
+                let secret_type = existing.type_.as_deref().unwrap_or("Opaque");
+                if secret_type != TLS_SECRET_TYPE {
+                    return Err(eyre!(
+                        "Secret {qualified} has type {secret_type}, not {TLS_SECRET_TYPE}"
+                    ));
+                }
+                vec![format!(
+                    "Would replace tls.crt and tls.key in Secret {qualified}"
+                )]
+            }
+            None if endpoint_must_exist => {
+                return Err(eyre!("Secret {qualified} does not exist"));
+            }
+            None => vec![format!("Would create Secret {qualified}")],
+        };
+
+        if let Some(deployment) = &self.config.deployment {
+            let qualified = format!("{}/{deployment}", self.config.namespace);
+            let deployments: Api<Deployment> = Api::namespaced(client, &self.config.namespace);
+            if deployments
+                .get_opt(deployment)
+                .await
+                .wrap_err_with(|| format!("Failed to read Deployment {qualified}"))?
+                .is_none()
+            {
+                return Err(eyre!("Deployment {qualified} does not exist"));
+            }
+            plan.push(format!("Would restart Deployment {qualified}"));
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use http::{Method, Request, Response, StatusCode};
+    use kube::client::Body;
+    use serde_json::Value;
+
+    use super::*;
+
+    /// A request that the mock API server received.
+    struct Received {
+        method: Method,
+        path: String,
+        content_type: Option<String>,
+        body: Value,
+    }
+
+    /// A client whose API server answers each request with `respond`, and the requests it got.
+    fn mock_client(
+        respond: impl Fn(&Method, &str) -> (StatusCode, Value) + Send + Sync + 'static,
+    ) -> (Client, Arc<Mutex<Vec<Received>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let respond = Arc::new(respond);
+        let log = received.clone();
+        let service = tower::service_fn(move |request: Request<Body>| {
+            let respond = respond.clone();
+            let log = log.clone();
+            async move {
+                let (parts, body) = request.into_parts();
+                let body = body.collect_bytes().await?;
+                let (status, response) = respond(&parts.method, parts.uri.path());
+                log.lock().unwrap().push(Received {
+                    method: parts.method,
+                    path: parts.uri.path().to_string(),
+                    content_type: parts
+                        .headers
+                        .get(http::header::CONTENT_TYPE)
+                        .map(|value| value.to_str().unwrap().to_string()),
+                    body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+                });
+
+                let response = Response::builder()
+                    .status(status)
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::to_vec(&response).unwrap()))
+                    .unwrap();
+                Ok::<_, kube::Error>(response)
+            }
+        });
+
+        (Client::new(service, "default"), received)
+    }
+
+    fn status(code: StatusCode, reason: &str) -> (StatusCode, Value) {
+        (
+            code,
+            json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "metadata": {},
+                "status": "Failure",
+                "message": reason,
+                "reason": reason,
+                "code": code.as_u16(),
+            }),
+        )
+    }
+
+    fn secret(type_: &str) -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "site-tls", "namespace": "web", "resourceVersion": "42" },
+            "type": type_,
+            "data": { "tls.crt": "b2xk", "tls.key": "b2xk", "ca.crt": "Y2E=" },
+        })
+    }
+
+    fn cert() -> Certificate {
+        Certificate {
+            cert: "new cert".to_string(),
+            key: "new key".to_string(),
+        }
+    }
+
+    const SECRET_PATH: &str = "/api/v1/namespaces/web/secrets/site-tls";
+
+    #[tokio::test]
+    async fn creates_missing_secret() {
+        let (client, received) = mock_client(|method, _| match *method {
+            Method::GET => status(StatusCode::NOT_FOUND, "NotFound"),
+            _ => (StatusCode::CREATED, secret(TLS_SECRET_TYPE)),
+        });
+        let secrets: Api<Secret> = Api::namespaced(client, "web");
+
+        write_secret(&secrets, "web", "site-tls", &cert(), false)
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].method, Method::POST);
+        assert_eq!(received[1].path, "/api/v1/namespaces/web/secrets");
+        let created = &received[1].body;
+        assert_eq!(created["type"], TLS_SECRET_TYPE);
+        assert_eq!(created["metadata"]["name"], "site-tls");
+        assert_eq!(created["metadata"]["namespace"], "web");
+        assert_eq!(created["data"]["tls.crt"], base64::encode("new cert"));
+        assert_eq!(created["data"]["tls.key"], base64::encode("new key"));
+    }
+
+    #[tokio::test]
+    async fn missing_secret_that_must_exist() {
+        let (client, received) = mock_client(|_, _| status(StatusCode::NOT_FOUND, "NotFound"));
+        let secrets: Api<Secret> = Api::namespaced(client, "web");
+
+        let err = write_secret(&secrets, "web", "site-tls", &cert(), true)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "Secret web/site-tls does not exist");
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn updates_existing_secret() {
+        let (client, received) = mock_client(|_, _| (StatusCode::OK, secret(TLS_SECRET_TYPE)));
+        let secrets: Api<Secret> = Api::namespaced(client, "web");
+
+        write_secret(&secrets, "web", "site-tls", &cert(), true)
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let patch = &received[1];
+        assert_eq!(patch.method, Method::PATCH);
+        assert_eq!(patch.path, SECRET_PATH);
+        assert_eq!(
+            patch.content_type.as_deref(),
+            Some("application/merge-patch+json")
+        );
+        assert_eq!(patch.body["metadata"]["resourceVersion"], "42");
+        assert_eq!(patch.body["data"]["tls.crt"], base64::encode("new cert"));
+        assert_eq!(patch.body["data"]["tls.key"], base64::encode("new key"));
+        // Other keys are left for the merge to keep.
+        assert!(patch.body["data"].get("ca.crt").is_none());
+    }
+
+    #[tokio::test]
+    async fn conflicting_update() {
+        let (client, _) = mock_client(|method, _| match *method {
+            Method::GET => (StatusCode::OK, secret(TLS_SECRET_TYPE)),
+            _ => status(StatusCode::CONFLICT, "Conflict"),
+        });
+        let secrets: Api<Secret> = Api::namespaced(client, "web");
+
+        let err = write_secret(&secrets, "web", "site-tls", &cert(), false)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Secret web/site-tls was changed by something else while it was being updated"
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_secret_type() {
+        let (client, received) = mock_client(|_, _| (StatusCode::OK, secret("Opaque")));
+        let secrets: Api<Secret> = Api::namespaced(client, "web");
+
+        let err = write_secret(&secrets, "web", "site-tls", &cert(), false)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Secret web/site-tls has type Opaque, not kubernetes.io/tls"
+        );
+        // Nothing is written to a Secret of the wrong type.
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cmd::State,
+    files::{mode_and_owner, write_atomic, Owner, PRIVATE_MODE, PUBLIC_MODE},
+    Certificate,
+};
+
+use super::DeployEndpoint;
+
+/// Where and how to write certificates on the local machine. Each path may contain
+/// `{subdomain}`, which is replaced by the name of the subdomain being deployed, so that one
+/// endpoint can serve many subdomains. Files without a path aren't written.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LocalFileConfig {
+    /// The leaf certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_path: Option<String>,
+    /// The intermediate certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_path: Option<String>,
+    /// The leaf certificate followed by the intermediates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullchain_path: Option<String>,
+    /// The private key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<String>,
+    /// The private key followed by the full chain, as HAProxy expects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combined_path: Option<String>,
+    /// The owner of the written files, as `user`, `user:group`, or `:group`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// The mode of the files that only contain certificates. Defaults to 0644.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_mode: Option<u32>,
+    /// The mode of the files that contain the private key. Defaults to 0600.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_mode: Option<u32>,
+    /// A shell command to run after the files are written, such as `systemctl reload nginx`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reload_command: Option<String>,
+}
+
+impl LocalFileConfig {
+    pub async fn from_string(creds: String) -> Result<Self> {
+        if creds.is_empty() {
+            return Err(eyre!("This endpoint has no file paths configured"));
+        }
+
+        let config: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the file endpoint configuration")?;
+        Ok(config)
+    }
+
+    /// Check that the configuration can be used, without writing anything.
+    pub fn validate(&self) -> Result<()> {
+        self.check_paths()?;
+
+        if let Some(owner) = &self.owner {
+            Owner::parse(owner)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that enough paths are set to be useful.
+    pub fn check_paths(&self) -> Result<()> {
+        if self.cert_path.is_none() && self.fullchain_path.is_none() && self.combined_path.is_none()
+        {
+            return Err(eyre!(
+                "At least one of the certificate, full chain, or combined paths must be set"
+            ));
+        }
+
+        if self.key_path.is_none() && self.combined_path.is_none() {
+            return Err(eyre!(
+                "Either the key path or the combined path must be set"
+            ));
+        }
+
+        if let Some(owner) = &self.owner {
+            Owner::parse(owner)?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the files to write for a certificate, with their paths and modes.
+    pub(super) fn files(
+        &self,
+        subdomain: &str,
+        cert: &Certificate,
+    ) -> Vec<(PathBuf, Vec<u8>, u32)> {
+        let cert_mode = self.cert_mode.unwrap_or(PUBLIC_MODE);
+        let key_mode = self.key_mode.unwrap_or(PRIVATE_MODE);
+
+        [
+            (
+                &self.cert_path,
+                cert.get_leaf_certificate().to_string(),
+                cert_mode,
+            ),
+            (
+                &self.chain_path,
+                cert.get_certificate_chain().to_string(),
+                cert_mode,
+            ),
+            (&self.fullchain_path, cert.cert.clone(), cert_mode),
+            (&self.key_path, cert.key.clone(), key_mode),
+            (
+                &self.combined_path,
+                format!("{}{}", cert.key, cert.cert),
+                key_mode,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(path, contents, mode)| {
+            let path = path.as_ref()?.replace("{subdomain}", subdomain);
+            Some((PathBuf::from(path), contents.into_bytes(), mode))
+        })
+        .collect()
+    }
+
+    /// Describe the files that would be written for the subdomain and the reload command, given
+    /// a way to check whether a path exists where they'd be written. `host` names the remote host
+    /// the files are on, if they aren't local.
+    pub(super) fn plan(
+        &self,
+        subdomain: &str,
+        host: Option<&str>,
+        endpoint_must_exist: bool,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Result<Vec<String>> {
+        let on = host.map(|host| format!(" on {host}")).unwrap_or_default();
+
+        let paths = [
+            &self.cert_path,
+            &self.chain_path,
+            &self.fullchain_path,
+            &self.key_path,
+            &self.combined_path,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|path| PathBuf::from(path.replace("{subdomain}", subdomain)));
+
+        let mut plan = Vec::new();
+        let mut created_dirs = Vec::new();
+        for path in paths {
+            if exists(&path) {
+                plan.push(format!("Would replace {}{on}", path.display()));
+                continue;
+            }
+
+            match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() && !exists(dir) => {
+                    if endpoint_must_exist {
+                        return Err(eyre!("Directory {}{on} does not exist", dir.display()));
+                    }
+                    if !created_dirs.contains(&dir.to_path_buf()) {
+                        plan.push(format!("Would create directory {}{on}", dir.display()));
+                        created_dirs.push(dir.to_path_buf());
+                    }
+                }
+                _ => {}
+            }
+            plan.push(format!("Would create {}{on}", path.display()));
+        }
+
+        if let Some(command) = &self.reload_command {
+            plan.push(format!("Would run{on}: {command}"));
+        }
+
+        Ok(plan)
+    }
+
+    pub fn from_console() -> Result<Self> {
+        let path = |prompt: &str, default: &str| -> Result<Option<String>> {
+            let path: String = dialoguer::Input::new()
+                .with_prompt(format!("{prompt} (or blank to skip)"))
+                .default(default.to_string())
+                .allow_empty(true)
+                .interact_text()?;
+            Ok(Some(path).filter(|p| !p.is_empty()))
+        };
+
+        println!("Paths can contain {{subdomain}}, which is replaced by the subdomain's name.");
+        let mut config = LocalFileConfig {
+            cert_path: path("Certificate path", "")?,
+            chain_path: path("Chain path", "")?,
+            fullchain_path: path("Full chain path", "/etc/ssl/{subdomain}/fullchain.pem")?,
+            key_path: path("Private key path", "/etc/ssl/{subdomain}/privkey.pem")?,
+            combined_path: path("Combined key and full chain path", "")?,
+            ..Default::default()
+        };
+
+        let owner: String = dialoguer::Input::new()
+            .with_prompt("Owner of the files, as user:group (or blank to leave unchanged)")
+            .allow_empty(true)
+            .interact_text()?;
+        config.owner = Some(owner).filter(|o| !o.is_empty());
+
+        let reload_command: String = dialoguer::Input::new()
+            .with_prompt("Command to run after writing the files (or blank for none)")
+            .allow_empty(true)
+            .interact_text()?;
+        config.reload_command = Some(reload_command).filter(|c| !c.is_empty());
+
+        Ok(config)
+    }
+}
+
+/// The state of a file before it was replaced, so that it can be put back.
+struct PreviousFile {
+    path: PathBuf,
+    /// The old contents, mode, and owner, or `None` if the file didn't exist
+    contents: Option<(Vec<u8>, u32, Owner)>,
+}
+
+impl PreviousFile {
+    fn read(path: &Path) -> Result<Self> {
+        let contents = match std::fs::metadata(path) {
+            Ok(metadata) => {
+                let contents = std::fs::read(path)
+                    .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+                let (mode, owner) = mode_and_owner(&metadata);
+                Some((contents, mode, owner))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).wrap_err_with(|| format!("Failed to read {}", path.display())),
+        };
+
+        Ok(PreviousFile {
+            path: path.to_path_buf(),
+            contents,
+        })
+    }
+
+    fn restore(&self) -> Result<()> {
+        match &self.contents {
+            Some((contents, mode, owner)) => write_atomic(&self.path, contents, *mode, *owner),
+            None => std::fs::remove_file(&self.path)
+                .wrap_err_with(|| format!("Failed to remove {}", self.path.display())),
+        }
+    }
+}
+
+/// Put back a set of files, reporting any that couldn't be restored.
+fn restore_all(previous: &[PreviousFile]) -> Result<()> {
+    let failed = previous
+        .iter()
+        .filter_map(|file| file.restore().err())
+        .map(|e| format!("{e:#}"))
+        .collect::<Vec<_>>();
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Failed to restore the previous files: {}",
+            failed.join("; ")
+        ))
+    }
+}
+
+pub struct LocalFile {
+    state: Arc<State>,
+    config: LocalFileConfig,
+    subdomain: String,
+}
+
+impl LocalFile {
+    pub fn new(state: Arc<State>, config: LocalFileConfig, subdomain: String) -> Result<Self> {
+        config.validate()?;
+        Ok(LocalFile {
+            state,
+            config,
+            subdomain,
+        })
+    }
+
+    async fn run_reload_command(&self, command: &str) -> Result<()> {
+        let output = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("RSR_SUBDOMAIN", &self.subdomain)
+            .output()
+            .await
+            .wrap_err_with(|| format!("Failed to run reload command {command}"))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let details = if stderr.trim().is_empty() {
+                stdout.trim()
+            } else {
+                stderr.trim()
+            };
+            let mut message = format!("Reload command {command} failed with {}", output.status);
+            if !details.is_empty() {
+                message = format!("{message}: {details}");
+            }
+            return Err(eyre!(message));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for LocalFile {
+    /// Write the certificate files and run the reload command. If a file can't be written or the
+    /// reload command fails, the previous files are put back.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let owner = self
+            .config
+            .owner
+            .as_deref()
+            .map(Owner::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let files = self.config.files(&self.subdomain, &cert);
+
+        for (path, _, _) in &files {
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+
+            if dir.exists() {
+                continue;
+            } else if endpoint_must_exist {
+                return Err(eyre!("Directory {} does not exist", dir.display()));
+            } else {
+                std::fs::create_dir_all(dir)
+                    .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+            }
+        }
+
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Writing certificate files"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        let mut previous = Vec::with_capacity(files.len());
+        for (path, contents, mode) in &files {
+            let result = PreviousFile::read(path).and_then(|file| {
+                previous.push(file);
+                write_atomic(path, contents, *mode, owner)
+            });
+
+            if let Err(e) = result {
+                progress.finish_and_clear();
+                restore_all(&previous)?;
+                return Err(e);
+            }
+        }
+
+        if let Some(command) = &self.config.reload_command {
+            progress.set_message(format!("Running {command}"));
+            if let Err(e) = self.run_reload_command(command).await {
+                progress.finish_and_clear();
+                restore_all(&previous).wrap_err(format!("{e:#}"))?;
+                return Err(
+                    e.wrap_err("Reload failed, so the previous certificate files were restored")
+                );
+            }
+        }
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    async fn plan_deploy(
+        &self,
+        _cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        self.config
+            .plan(&self.subdomain, None, endpoint_must_exist, |path| {
+                path.exists()
+            })
+    }
+}
@@ -0,0 +1,802 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use ssh2::{
+    CheckResult, ExtendedData, FileStat, HashType, KnownHostFileKind, OpenFlags, OpenType, Session,
+};
+
+use crate::{cmd::State, Certificate};
+
+use super::{local_file::LocalFileConfig, DeployEndpoint};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn default_port() -> u16 {
+    22
+}
+
+/// How to log in to a remote host and where to put the certificate there. The file paths, owner,
+/// modes, and reload command work the same way as for [LocalFileConfig], but apply to the
+/// remote host.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SshConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub user: String,
+    /// The private key to log in with, or a reference to it. If this isn't set, the key is
+    /// taken from ssh-agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    /// The passphrase for the private key, or a reference to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    /// The expected SHA256 fingerprint of the host's key, as shown by `ssh-keygen -l`. If this
+    /// isn't set, the host must be in the known_hosts file instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_key: Option<String>,
+    /// The known_hosts file to check the host's key against. Defaults to ~/.ssh/known_hosts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub known_hosts: Option<String>,
+    #[serde(flatten)]
+    pub files: LocalFileConfig,
+}
+
+/// Whether a host's key is already trusted.
+enum HostKeyStatus {
+    Trusted,
+    /// The host isn't in the known_hosts file. This holds its key's fingerprint.
+    Unknown(String),
+}
+
+impl SshConfig {
+    pub async fn from_string(creds: String) -> Result<Self> {
+        if creds.is_empty() {
+            return Err(eyre!("This endpoint has no host configured"));
+        }
+
+        let mut config: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the SSH endpoint configuration")?;
+        // Logging in happens on a blocking thread, so the key is looked up here ahead of time.
+        config.private_key = crate::creds::resolve_option(config.private_key.as_deref()).await?;
+        config.passphrase = crate::creds::resolve_option(config.passphrase.as_deref()).await?;
+        Ok(config)
+    }
+
+    /// Check that the host can be reached and logged in to, without changing anything.
+    pub async fn validate(self) -> Result<()> {
+        self.files.check_paths()?;
+        tokio::task::spawn_blocking(move || self.connect().map(|_| ())).await?
+    }
+
+    pub fn from_console() -> Result<Self> {
+        let host: String = dialoguer::Input::new()
+            .with_prompt("Host name")
+            .interact_text()?;
+        let port: u16 = dialoguer::Input::new()
+            .with_prompt("Port")
+            .default(default_port())
+            .interact_text()?;
+        let user: String = dialoguer::Input::new()
+            .with_prompt("User")
+            .default("root".to_string())
+            .interact_text()?;
+        let key_path: String = dialoguer::Input::new()
+            .with_prompt("Private key file (or blank to use ssh-agent)")
+            .allow_empty(true)
+            .interact_text()?;
+        let private_key = match key_path.as_str() {
+            "" => None,
+            path => Some(read_private_key(path)?),
+        };
+
+        let mut config = SshConfig {
+            host,
+            port,
+            user,
+            private_key,
+            passphrase: None,
+            host_key: None,
+            known_hosts: None,
+            files: LocalFileConfig::default(),
+        };
+
+        // Offer to trust the host key if it isn't known yet, like ssh does.
+        let session = config.handshake()?;
+        if let HostKeyStatus::Unknown(fingerprint) = config.host_key_status(&session)? {
+            let trusted = dialoguer::Confirm::new()
+                .with_prompt(format!(
+                    "{} is not a known host. Its key fingerprint is {fingerprint}. Trust it?",
+                    config.host
+                ))
+                .default(false)
+                .interact()?;
+            if !trusted {
+                return Err(eyre!("The host key was not trusted"));
+            }
+            config.host_key = Some(fingerprint);
+        }
+
+        config.files = LocalFileConfig::from_console()?;
+        Ok(config)
+    }
+
+    fn handshake(&self) -> Result<Session> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .wrap_err_with(|| format!("Failed to look up {}", self.host))?
+            .next()
+            .ok_or_else(|| eyre!("Failed to look up {}", self.host))?;
+        let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .wrap_err_with(|| format!("Failed to connect to {}:{}", self.host, self.port))?;
+
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+        session
+            .handshake()
+            .wrap_err_with(|| format!("SSH handshake with {} failed", self.host))?;
+        Ok(session)
+    }
+
+    fn known_hosts_path(&self) -> Result<PathBuf> {
+        match &self.known_hosts {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => dirs::home_dir()
+                .map(|home| home.join(".ssh").join("known_hosts"))
+                .ok_or_else(|| eyre!("Could not find the home directory")),
+        }
+    }
+
+    fn host_key_status(&self, session: &Session) -> Result<HostKeyStatus> {
+        let fingerprint = session
+            .host_key_hash(HashType::Sha256)
+            .map(|hash| {
+                format!(
+                    "SHA256:{}",
+                    base64::encode_config(hash, base64::STANDARD_NO_PAD)
+                )
+            })
+            .ok_or_else(|| eyre!("{} did not send a host key", self.host))?;
+
+        if let Some(expected) = &self.host_key {
+            if expected.trim() != fingerprint {
+                return Err(eyre!(
+                    "The host key for {} is {fingerprint}, but {expected} was expected. It might have been changed, or someone might be intercepting the connection.",
+                    self.host
+                ));
+            }
+            return Ok(HostKeyStatus::Trusted);
+        }
+
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| eyre!("{} did not send a host key", self.host))?;
+        let path = self.known_hosts_path()?;
+        let mut known_hosts = session.known_hosts()?;
+        if path.exists() {
+            known_hosts
+                .read_file(&path, KnownHostFileKind::OpenSSH)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        }
+
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => Ok(HostKeyStatus::Trusted),
+            CheckResult::NotFound => Ok(HostKeyStatus::Unknown(fingerprint)),
+            CheckResult::Mismatch => Err(eyre!(
+                "The host key for {} does not match the one in {}. It might have been changed, or someone might be intercepting the connection.",
+                self.host,
+                path.display()
+            )),
+            CheckResult::Failure => Err(eyre!("Failed to check the host key for {}", self.host)),
+        }
+    }
+
+    /// Connect to the host, check its key, and log in.
+    fn connect(&self) -> Result<Session> {
+        let session = self.handshake()?;
+
+        if let HostKeyStatus::Unknown(fingerprint) = self.host_key_status(&session)? {
+            return Err(eyre!(
+                "{} is not in {}. Add it there, or create the endpoint with --host-key {fingerprint} if that is the right key.",
+                self.host,
+                self.known_hosts_path()?.display()
+            ));
+        }
+
+        let result = match &self.private_key {
+            Some(key) => {
+                let key = format!("{key}\n");
+                session.userauth_pubkey_memory(&self.user, None, &key, self.passphrase.as_deref())
+            }
+            None => session.userauth_agent(&self.user),
+        };
+        result.wrap_err_with(|| format!("Failed to log in to {} as {}", self.host, self.user))?;
+
+        Ok(session)
+    }
+}
+
+/// Read a private key file for the configuration. References to secrets kept elsewhere are
+/// stored as they are.
+pub fn read_private_key(path: &str) -> Result<String> {
+    if crate::creds::is_reference(path) {
+        Ok(path.to_string())
+    } else {
+        std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {path}"))
+    }
+}
+
+/// Quote a value for the remote shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn quote_path(path: &Path) -> String {
+    quote(&path.to_string_lossy())
+}
+
+/// Run a command on the remote host and fail if it exits with an error.
+fn run(session: &Session, description: &str, command: &str) -> Result<()> {
+    let mut channel = session.channel_session()?;
+    // Read stderr along with stdout. Reading them one after the other would hang if the command
+    // filled the channel's window with output on the stream that isn't being read.
+    channel.handle_extended_data(ExtendedData::Merge)?;
+    channel.exec(command)?;
+
+    let mut output = String::new();
+    channel.read_to_string(&mut output)?;
+    channel.wait_close()?;
+
+    let status = channel.exit_status()?;
+    if status != 0 {
+        let details = output.trim();
+        let mut message = format!("{description} failed with exit status {status}");
+        if !details.is_empty() {
+            message = format!("{message}: {details}");
+        }
+        return Err(eyre!(message));
+    }
+
+    Ok(())
+}
+
+/// A certificate file to put on the remote host, along with the paths used while replacing it.
+struct RemoteFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    backup_path: PathBuf,
+    contents: Vec<u8>,
+    mode: u32,
+    existed: bool,
+}
+
+impl RemoteFile {
+    fn new(path: PathBuf, contents: Vec<u8>, mode: u32) -> Self {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        RemoteFile {
+            temp_path: path.with_file_name(format!(".{file_name}.tmp-{}", std::process::id())),
+            backup_path: path.with_file_name(format!(".{file_name}.backup")),
+            path,
+            contents,
+            mode,
+            existed: false,
+        }
+    }
+
+    /// Write the file to its temporary path.
+    fn upload(&self, sftp: &ssh2::Sftp) -> Result<()> {
+        let mut file = sftp.open_mode(
+            &self.temp_path,
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+            self.mode as i32,
+            OpenType::File,
+        )?;
+        file.write_all(&self.contents)?;
+        // The mode given to `open_mode` is reduced by the remote umask, so set it explicitly.
+        file.setstat(FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: Some(self.mode),
+            atime: None,
+            mtime: None,
+        })?;
+        Ok(())
+    }
+
+    /// The shell command that keeps a copy of the existing file and moves the new one into place.
+    fn install_command(&self, owner: Option<&str>) -> String {
+        let mut steps = vec![format!("rm -f {}", quote_path(&self.backup_path))];
+        if self.existed {
+            steps.push(format!(
+                "cp -p {} {}",
+                quote_path(&self.path),
+                quote_path(&self.backup_path)
+            ));
+        }
+        if let Some(owner) = owner {
+            steps.push(format!(
+                "chown -- {} {}",
+                quote(owner),
+                quote_path(&self.temp_path)
+            ));
+        }
+        steps.push(format!(
+            "mv -f {} {}",
+            quote_path(&self.temp_path),
+            quote_path(&self.path)
+        ));
+        steps.join(" && ")
+    }
+
+    /// The shell command that puts back the file as it was before [Self::install_command].
+    fn restore_command(&self) -> String {
+        let restore = if self.existed {
+            // If the backup is missing, the file was never replaced.
+            format!(
+                "if [ -e {backup} ]; then mv -f {backup} {path}; fi",
+                backup = quote_path(&self.backup_path),
+                path = quote_path(&self.path)
+            )
+        } else {
+            format!("rm -f {}", quote_path(&self.path))
+        };
+        format!("{restore}; rm -f {}", quote_path(&self.temp_path))
+    }
+}
+
+fn restore_all(session: &Session, files: &[RemoteFile]) -> Result<()> {
+    let command = files
+        .iter()
+        .map(|file| file.restore_command())
+        .collect::<Vec<_>>()
+        .join("; ");
+    run(
+        session,
+        "Restoring the previous certificate files",
+        &command,
+    )
+}
+
+pub struct Ssh {
+    state: Arc<State>,
+    config: Arc<SshConfig>,
+    subdomain: String,
+}
+
+impl Ssh {
+    pub fn new(state: Arc<State>, config: SshConfig, subdomain: String) -> Result<Self> {
+        config.files.check_paths()?;
+        Ok(Ssh {
+            state,
+            config: Arc::new(config),
+            subdomain,
+        })
+    }
+}
+
+/// Upload the files and run the reload command. This blocks, so it runs on its own thread.
+fn deploy(
+    config: &SshConfig,
+    subdomain: &str,
+    mut files: Vec<RemoteFile>,
+    endpoint_must_exist: bool,
+    progress: &ProgressBar,
+) -> Result<()> {
+    progress.set_message(format!("Connecting to {}", config.host));
+    let session = config.connect()?;
+    let sftp = session.sftp()?;
+
+    let mut dirs = files
+        .iter()
+        .filter_map(|file| file.path.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(quote_path)
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    if !dirs.is_empty() {
+        if endpoint_must_exist {
+            let command = dirs
+                .iter()
+                .map(|dir| {
+                    format!("[ -d {dir} ] || {{ echo \"Directory \"{dir}\" does not exist\" >&2; exit 1; }}")
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            run(&session, "Checking the remote directories", &command)?;
+        } else {
+            let command = format!("mkdir -p -- {}", dirs.join(" "));
+            run(&session, "Creating the remote directories", &command)?;
+        }
+    }
+
+    progress.set_message(format!("Uploading certificate files to {}", config.host));
+    for file in files.iter_mut() {
+        file.existed = sftp.stat(&file.path).is_ok();
+    }
+
+    let uploaded = files.iter().try_for_each(|file| {
+        file.upload(&sftp)
+            .wrap_err_with(|| format!("Failed to upload {}", file.path.display()))
+    });
+    let owner = config.files.owner.as_deref();
+    let installed = uploaded.and_then(|_| {
+        let command = files
+            .iter()
+            .map(|file| file.install_command(owner))
+            .collect::<Vec<_>>()
+            .join(" && ");
+        run(&session, "Replacing the certificate files", &command)
+    });
+    if let Err(e) = installed {
+        restore_all(&session, &files).wrap_err(format!("{e:#}"))?;
+        return Err(e);
+    }
+
+    if let Some(command) = &config.files.reload_command {
+        progress.set_message(format!("Running {command} on {}", config.host));
+        let command = format!(
+            "RSR_SUBDOMAIN={}; export RSR_SUBDOMAIN; {command}",
+            quote(subdomain)
+        );
+        if let Err(e) = run(&session, "Reload command", &command) {
+            restore_all(&session, &files).wrap_err(format!("{e:#}"))?;
+            return Err(
+                e.wrap_err("Reload failed, so the previous certificate files were restored")
+            );
+        }
+    }
+
+    let cleanup = files
+        .iter()
+        .map(|file| format!("rm -f {}", quote_path(&file.backup_path)))
+        .collect::<Vec<_>>()
+        .join("; ");
+    run(&session, "Removing the old certificate files", &cleanup)?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl DeployEndpoint for Ssh {
+    /// Upload the certificate files and run the reload command. If a file can't be replaced or
+    /// the reload command fails, the previous files are put back.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let files = self
+            .config
+            .files
+            .files(&self.subdomain, &cert)
+            .into_iter()
+            .map(|(path, contents, mode)| RemoteFile::new(path, contents, mode))
+            .collect::<Vec<_>>();
+
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Connecting"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        let config = self.config.clone();
+        let subdomain = self.subdomain.clone();
+        let task_progress = progress.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            deploy(
+                &config,
+                &subdomain,
+                files,
+                endpoint_must_exist,
+                &task_progress,
+            )
+        })
+        .await?;
+
+        match result {
+            Ok(()) => progress.finish_with_message("Done"),
+            Err(_) => progress.finish_and_clear(),
+        }
+        result
+    }
+
+    async fn plan_deploy(
+        &self,
+        _cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        let config = self.config.clone();
+        let subdomain = self.subdomain.clone();
+        tokio::task::spawn_blocking(move || {
+            let session = config.connect()?;
+            let sftp = session.sftp()?;
+            config.files.plan(
+                &subdomain,
+                Some(&config.host),
+                endpoint_must_exist,
+                |path| sftp.stat(path).is_ok(),
+            )
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::files::{PRIVATE_MODE, PUBLIC_MODE};
+
+    /// Run a command with the local shell, as the remote host would.
+    fn sh(command: &str) -> String {
+        let output = Command::new("sh").arg("-c").arg(command).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{command} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// A fresh directory for a test, with a space and a quote in its name to exercise quoting.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rsr-ssh-test {name} it's-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn quote_plain_value() {
+        assert_eq!(quote("/etc/ssl/cert.pem"), "'/etc/ssl/cert.pem'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn quote_single_quotes() {
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn quoted_values_reach_the_shell_unchanged() {
+        for value in [
+            "plain",
+            "two words",
+            "it's",
+            "''",
+            "$(touch /tmp/rsr-injected)",
+            "`id`",
+            "a;b && c | d",
+            "back\\slash",
+            "new\nline",
+            "*",
+        ] {
+            assert_eq!(sh(&format!("printf %s {}", quote(value))), value);
+        }
+    }
+
+    #[test]
+    fn install_command_for_new_file() {
+        let file = RemoteFile::new(
+            PathBuf::from("/etc/ssl/site/cert.pem"),
+            Vec::new(),
+            PUBLIC_MODE,
+        );
+        assert_eq!(
+            file.install_command(None),
+            format!(
+                "rm -f '/etc/ssl/site/.cert.pem.backup' && mv -f '/etc/ssl/site/.cert.pem.tmp-{pid}' '/etc/ssl/site/cert.pem'",
+                pid = std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn install_command_for_existing_file_with_owner() {
+        let mut file = RemoteFile::new(
+            PathBuf::from("/etc/ssl/site/key.pem"),
+            Vec::new(),
+            PRIVATE_MODE,
+        );
+        file.existed = true;
+        assert_eq!(
+            file.install_command(Some("root:ssl-cert")),
+            format!(
+                "rm -f '/etc/ssl/site/.key.pem.backup' && cp -p '/etc/ssl/site/key.pem' '/etc/ssl/site/.key.pem.backup' && chown -- 'root:ssl-cert' '/etc/ssl/site/.key.pem.tmp-{pid}' && mv -f '/etc/ssl/site/.key.pem.tmp-{pid}' '/etc/ssl/site/key.pem'",
+                pid = std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn install_command_quotes_owner() {
+        let file = RemoteFile::new(PathBuf::from("/tmp/cert.pem"), Vec::new(), PUBLIC_MODE);
+        assert!(file
+            .install_command(Some("x'; rm -rf /; '"))
+            .contains(r"chown -- 'x'\''; rm -rf /; '\''' "));
+    }
+
+    #[test]
+    fn restore_command_for_new_file() {
+        let file = RemoteFile::new(PathBuf::from("/etc/ssl/cert.pem"), Vec::new(), PUBLIC_MODE);
+        assert_eq!(
+            file.restore_command(),
+            format!(
+                "rm -f '/etc/ssl/cert.pem'; rm -f '/etc/ssl/.cert.pem.tmp-{}'",
+                std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn restore_command_for_existing_file() {
+        let mut file = RemoteFile::new(PathBuf::from("/etc/ssl/cert.pem"), Vec::new(), PUBLIC_MODE);
+        file.existed = true;
+        assert_eq!(
+            file.restore_command(),
+            format!(
+                "if [ -e '/etc/ssl/.cert.pem.backup' ]; then mv -f '/etc/ssl/.cert.pem.backup' '/etc/ssl/cert.pem'; fi; rm -f '/etc/ssl/.cert.pem.tmp-{}'",
+                std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn install_and_restore_existing_file() {
+        let dir = test_dir("existing");
+        let path = dir.join("cert $name.pem");
+        std::fs::write(&path, "old").unwrap();
+
+        let mut file = RemoteFile::new(path.clone(), Vec::new(), PUBLIC_MODE);
+        file.existed = true;
+        std::fs::write(&file.temp_path, "new").unwrap();
+
+        sh(&file.install_command(None));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(&file.backup_path).unwrap(), "old");
+        assert!(!file.temp_path.exists());
+
+        sh(&file.restore_command());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert!(!file.backup_path.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restore_new_file_removes_it() {
+        let dir = test_dir("new");
+        let path = dir.join("key.pem");
+
+        let file = RemoteFile::new(path.clone(), Vec::new(), PRIVATE_MODE);
+        std::fs::write(&file.temp_path, "new").unwrap();
+
+        sh(&file.install_command(None));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+
+        sh(&file.restore_command());
+        assert!(!path.exists());
+        assert!(!file.temp_path.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restore_before_install_keeps_existing_file() {
+        let dir = test_dir("not-installed");
+        let path = dir.join("cert.pem");
+        std::fs::write(&path, "old").unwrap();
+
+        // The upload failed, so only the temporary file was written.
+        let mut file = RemoteFile::new(path.clone(), Vec::new(), PUBLIC_MODE);
+        file.existed = true;
+        std::fs::write(&file.temp_path, "partial").unwrap();
+
+        sh(&file.restore_command());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert!(!file.temp_path.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// The settings for a real SSH server, from `RSR_TEST_SSH_HOST`, `RSR_TEST_SSH_PORT`,
+    /// `RSR_TEST_SSH_USER`, `RSR_TEST_SSH_KEY` (the path to a private key), and
+    /// `RSR_TEST_SSH_HOST_KEY` (the server's key fingerprint). The files go in
+    /// `RSR_TEST_SSH_DIR`, which defaults to `/tmp/rsr-ssh-test`. For example, with a
+    /// throwaway container:
+    ///
+    /// ```sh
+    /// ssh-keygen -t ed25519 -N '' -f /tmp/rsr-test-key
+    /// docker run -d --name rsr-sshd -p 2222:2222 -e USER_NAME=deploy \
+    ///   -e PUBLIC_KEY="$(cat /tmp/rsr-test-key.pub)" linuxserver/openssh-server
+    /// RSR_TEST_SSH_HOST=127.0.0.1 RSR_TEST_SSH_PORT=2222 RSR_TEST_SSH_USER=deploy \
+    ///   RSR_TEST_SSH_KEY=/tmp/rsr-test-key \
+    ///   RSR_TEST_SSH_HOST_KEY="$(ssh-keyscan -p 2222 127.0.0.1 2>/dev/null | ssh-keygen -lf - | awk '{print $2}' | head -1)" \
+    ///   cargo test -- --ignored ssh_server
+    /// ```
+    fn server_config(reload_command: Option<&str>) -> SshConfig {
+        let var = |name: &str| std::env::var(name).unwrap_or_else(|_| panic!("Set ${name}"));
+        let dir = std::env::var("RSR_TEST_SSH_DIR").unwrap_or("/tmp/rsr-ssh-test".to_string());
+
+        SshConfig {
+            host: var("RSR_TEST_SSH_HOST"),
+            port: std::env::var("RSR_TEST_SSH_PORT")
+                .map(|port| port.parse().unwrap())
+                .unwrap_or_else(|_| default_port()),
+            user: var("RSR_TEST_SSH_USER"),
+            private_key: Some(read_private_key(&var("RSR_TEST_SSH_KEY")).unwrap()),
+            passphrase: None,
+            host_key: Some(var("RSR_TEST_SSH_HOST_KEY")),
+            known_hosts: None,
+            files: LocalFileConfig {
+                fullchain_path: Some(format!("{dir}/{{subdomain}}/fullchain.pem")),
+                key_path: Some(format!("{dir}/{{subdomain}}/privkey.pem")),
+                reload_command: reload_command.map(|command| command.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn server_files(config: &SshConfig, cert: &Certificate) -> Vec<RemoteFile> {
+        config
+            .files
+            .files("ssh.example.com", cert)
+            .into_iter()
+            .map(|(path, contents, mode)| RemoteFile::new(path, contents, mode))
+            .collect()
+    }
+
+    fn read_remote(session: &Session, path: &Path) -> String {
+        let mut contents = String::new();
+        session
+            .sftp()
+            .unwrap()
+            .open(path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    #[ignore = "needs an SSH server, see server_config"]
+    fn ssh_server_deploys_and_restores() {
+        let first = Certificate {
+            cert: "-----BEGIN CERTIFICATE-----\nfirst\n-----END CERTIFICATE-----\n".to_string(),
+            key: "first key\n".to_string(),
+        };
+        let second = Certificate {
+            cert: "-----BEGIN CERTIFICATE-----\nsecond\n-----END CERTIFICATE-----\n".to_string(),
+            key: "second key\n".to_string(),
+        };
+        let progress = ProgressBar::hidden();
+
+        // A reload command that writes more than the channel window to stderr shouldn't hang.
+        let config = server_config(Some("head -c 4000000 /dev/zero >&2"));
+        let files = server_files(&config, &first);
+        let fullchain = files[0].path.clone();
+        deploy(&config, "ssh.example.com", files, false, &progress).unwrap();
+
+        let session = config.connect().unwrap();
+        assert_eq!(read_remote(&session, &fullchain), first.cert);
+
+        let config = server_config(Some("echo reload failed >&2; exit 3"));
+        let files = server_files(&config, &second);
+        let err = deploy(&config, "ssh.example.com", files, false, &progress).unwrap_err();
+        assert!(format!("{err:#}").contains("reload failed"));
+        assert_eq!(read_remote(&session, &fullchain), first.cert);
+    }
+}
@@ -0,0 +1,357 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use indicatif::ProgressBar;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{cmd::State, Certificate};
+
+use super::DeployEndpoint;
+
+#[derive(Serialize, Deserialize)]
+pub struct VercelCreds {
+    token: String,
+    /// The team that owns the project, for tokens that can access more than one scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+    /// The project the subdomain is added to as a custom domain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+impl VercelCreds {
+    pub fn new(token: String) -> VercelCreds {
+        VercelCreds {
+            token,
+            team_id: None,
+            project: None,
+        }
+    }
+
+    pub async fn from_string_or_env(creds: String) -> Result<Self> {
+        if creds.is_empty() {
+            Self::from_env()
+        } else {
+            let mut creds: Self = crate::creds::parse(&creds, Self::new).await?;
+            creds.token = crate::creds::resolve(&creds.token).await?;
+            Ok(creds)
+        }
+    }
+
+    pub fn from_env() -> Result<VercelCreds> {
+        let token = std::env::var("VERCEL_TOKEN")?;
+        Ok(VercelCreds::new(token))
+    }
+
+    fn request(&self, client: &Client, method: Method, path: &str) -> RequestBuilder {
+        let request = client
+            .request(method, format!("https://api.vercel.com{path}"))
+            .bearer_auth(&self.token);
+
+        match &self.team_id {
+            Some(team_id) => request.query(&[("teamId", team_id)]),
+            None => request,
+        }
+    }
+
+    /// Make a cheap authenticated request to check that the token works, and that it can see
+    /// the project if there is one.
+    pub async fn validate(&self) -> Result<()> {
+        let client = Client::builder().user_agent(crate::USER_AGENT).build()?;
+        let path = match &self.project {
+            Some(project) => format!("/v9/projects/{project}"),
+            None => "/v2/user".to_string(),
+        };
+
+        let response = self.request(&client, Method::GET, &path).send().await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            if let Some(project) = &self.project {
+                return Err(eyre!("Vercel project {project} does not exist"));
+            }
+        }
+
+        if !status.is_success() {
+            return Err(eyre!(
+                "Vercel rejected the API token: {status} {}",
+                response.text().await?
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn from_console() -> Result<Option<VercelCreds>> {
+        let token: String = dialoguer::Input::new()
+            .with_prompt("Vercel API token (or blank to use $VERCEL_TOKEN)")
+            .allow_empty(true)
+            .interact_text()?;
+
+        if token.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(VercelCreds::new(token)))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VercelCertificate {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ListedCertificate {
+    uid: String,
+    #[serde(default)]
+    cns: Vec<String>,
+    #[serde(default, rename = "autoRenew")]
+    auto_renew: bool,
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    next: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct CertificatesResponse {
+    certs: Vec<ListedCertificate>,
+    pagination: Option<Pagination>,
+}
+
+pub struct Vercel {
+    state: Arc<State>,
+    creds: VercelCreds,
+    subdomain: String,
+    client: Client,
+}
+
+impl Vercel {
+    pub fn new(state: Arc<State>, creds: VercelCreds, subdomain: String) -> Result<Self> {
+        Ok(Vercel {
+            state,
+            creds,
+            subdomain,
+            client: Client::builder().user_agent(crate::USER_AGENT).build()?,
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.creds.request(&self.client, method, path)
+    }
+
+    /// Upload the certificate and return its ID.
+    async fn upload_certificate(&self, cert: &Certificate) -> Result<String> {
+        let payload = json!({
+            "cert": cert.get_leaf_certificate(),
+            "key": cert.key,
+            "ca": cert.get_certificate_chain(),
+        });
+
+        let response = self
+            .request(Method::PUT, "/v7/certs")
+            .json(&payload)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!("Failed to upload certificate: {status} {body}"));
+        }
+
+        Ok(response.json::<VercelCertificate>().await?.id)
+    }
+
+    /// Check whether the subdomain is one of the project's custom domains.
+    async fn project_has_domain(&self, project: &str) -> Result<bool> {
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/v9/projects/{project}/domains/{}", self.subdomain),
+            )
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(true)
+        } else if status == StatusCode::NOT_FOUND {
+            Ok(false)
+        } else {
+            let body = response.text().await?;
+            Err(eyre!(
+                "Failed to look up {} in project {project}: {status} {body}",
+                self.subdomain
+            ))
+        }
+    }
+
+    /// Make sure the subdomain is one of the project's custom domains, adding it if allowed.
+    async fn ensure_project_domain(&self, project: &str, endpoint_must_exist: bool) -> Result<()> {
+        if self.project_has_domain(project).await? {
+            return Ok(());
+        } else if endpoint_must_exist {
+            return Err(eyre!(
+                "{} is not a domain of Vercel project {project}",
+                self.subdomain
+            ));
+        }
+
+        let response = self
+            .request(Method::POST, &format!("/v10/projects/{project}/domains"))
+            .json(&json!({ "name": self.subdomain }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(eyre!(
+                "Failed to add {} to project {project}: {status} {body}",
+                self.subdomain
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Return the IDs of the uploaded certificates that the new certificate replaces: those for
+    /// the subdomain whose names the new certificate all covers, other than the new one itself.
+    /// Certificates that Vercel issued and renews by itself are left alone. Without the new
+    /// certificate, every other one for the subdomain counts.
+    async fn find_previous_certificates(
+        &self,
+        cert: Option<&Certificate>,
+        new_id: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let mut previous = Vec::new();
+        let mut until = None;
+        loop {
+            let mut request = self
+                .request(Method::GET, "/v4/now/certs")
+                .query(&[("limit", 100)]);
+            if let Some(until) = until {
+                request = request.query(&[("until", until)]);
+            }
+
+            let certs = request
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<CertificatesResponse>()
+                .await?;
+
+            for old in certs.certs {
+                if Some(old.uid.as_str()) == new_id
+                    || old.auto_renew
+                    || !old
+                        .cns
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(&self.subdomain))
+                {
+                    continue;
+                }
+
+                let mut covered = true;
+                if let Some(cert) = cert {
+                    for name in &old.cns {
+                        covered &= cert.covers(name)?;
+                    }
+                }
+
+                if covered {
+                    previous.push(old.uid);
+                }
+            }
+
+            match certs.pagination.and_then(|pagination| pagination.next) {
+                Some(next) => until = Some(next),
+                None => return Ok(previous),
+            }
+        }
+    }
+
+    async fn remove_cert(&self, cert_id: &str) -> Result<()> {
+        self.request(Method::DELETE, &format!("/v7/certs/{cert_id}"))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for Vercel {
+    /// Upload the new certificate, which Vercel starts serving for the subdomain, and then delete
+    /// the certificates that it replaces.
+    async fn deploy_certificate(&self, cert: Certificate, endpoint_must_exist: bool) -> Result<()> {
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Uploading certificate"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        if let Some(project) = &self.creds.project {
+            self.ensure_project_domain(project, endpoint_must_exist)
+                .await?;
+        }
+
+        let cert_id = self.upload_certificate(&cert).await?;
+
+        progress.set_message("Removing old certificates");
+        for old in self
+            .find_previous_certificates(Some(&cert), Some(&cert_id))
+            .await?
+        {
+            self.remove_cert(&old).await?;
+        }
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    async fn plan_deploy(
+        &self,
+        cert: Option<&Certificate>,
+        endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        let mut plan = Vec::new();
+        if let Some(project) = &self.creds.project {
+            if !self.project_has_domain(project).await? {
+                if endpoint_must_exist {
+                    return Err(eyre!(
+                        "{} is not a domain of Vercel project {project}",
+                        self.subdomain
+                    ));
+                }
+                plan.push(format!(
+                    "Would add {} to Vercel project {project}",
+                    self.subdomain
+                ));
+            }
+        }
+
+        plan.push("Would upload the certificate".to_string());
+
+        let previous = self.find_previous_certificates(cert, None).await?;
+        if !previous.is_empty() {
+            plan.push(format!(
+                "Would delete certificate {} afterward",
+                previous.join(", ")
+            ));
+        }
+
+        Ok(plan)
+    }
+
+    fn serves_subdomain(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use backoff::{future::retry, ExponentialBackoffBuilder};
+use eyre::{eyre, Report, Result, WrapErr};
+use hmac::{Hmac, Mac};
+use indicatif::ProgressBar;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{cmd::State, Certificate};
+
+use super::DeployEndpoint;
+
+/// The header that carries the request signature, when there is a secret.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// How the certificate is sent to the webhook.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// A JSON object with a field for each part of the bundle
+    #[default]
+    Json,
+    /// A multipart/form-data body with the PEM files as file fields
+    Multipart,
+}
+
+pub fn default_max_retry_secs() -> u64 {
+    120
+}
+
+/// Where to send the certificate bundle, and how.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// The key to sign each request with, or a reference to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Extra headers to send, such as `Authorization`. Values can be references to secrets.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// How long to keep retrying when the webhook can't be reached or returns a server error
+    #[serde(default = "default_max_retry_secs")]
+    pub max_retry_secs: u64,
+    /// Allow an http URL, even though the request carries the private key
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_insecure: bool,
+}
+
+impl WebhookConfig {
+    pub async fn from_string(creds: String) -> Result<Self> {
+        let config: Self = serde_json::from_str(&crate::creds::resolve(&creds).await?)
+            .wrap_err("Failed to read the webhook endpoint configuration")?;
+        Ok(config)
+    }
+
+    /// Check that the settings make sense and any secrets can be read, without sending anything.
+    pub async fn validate(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.url)
+            .wrap_err_with(|| format!("{} is not a valid URL", self.url))?;
+        match url.scheme() {
+            "https" => {}
+            "http" if self.allow_insecure => {}
+            "http" => {
+                return Err(eyre!(
+                    "The webhook URL must use https, since the request carries the private key. Pass --allow-insecure to send it over http anyway."
+                ))
+            }
+            _ => return Err(eyre!("The webhook URL must use https")),
+        }
+
+        self.resolve_headers().await?;
+        self.resolve_secret().await?;
+        Ok(())
+    }
+
+    async fn resolve_headers(&self) -> Result<Vec<(String, String)>> {
+        let mut headers = Vec::with_capacity(self.headers.len());
+        for (name, value) in &self.headers {
+            let value = crate::creds::resolve(value)
+                .await
+                .wrap_err_with(|| format!("Failed to read the value of header {name}"))?;
+            headers.push((name.clone(), value));
+        }
+        Ok(headers)
+    }
+
+    async fn resolve_secret(&self) -> Result<Option<String>> {
+        crate::creds::resolve_option(self.secret.as_deref())
+            .await
+            .wrap_err("Failed to read the webhook signing secret")
+    }
+
+    pub fn from_console() -> Result<Self> {
+        let url: String = dialoguer::Input::new()
+            .with_prompt("Webhook URL")
+            .interact_text()?;
+        let allow_insecure = url.starts_with("http://")
+            && dialoguer::Confirm::new()
+                .with_prompt("This URL isn't https. Send the private key over it unencrypted?")
+                .default(false)
+                .interact()?;
+        let format = match dialoguer::Select::new()
+            .with_prompt("How should the certificate be sent?")
+            .items(&["JSON", "Multipart form"])
+            .default(0)
+            .interact()?
+        {
+            0 => WebhookFormat::Json,
+            _ => WebhookFormat::Multipart,
+        };
+        let secret: String = dialoguer::Input::new()
+            .with_prompt("Secret to sign requests with (or blank to not sign them)")
+            .allow_empty(true)
+            .interact_text()?;
+
+        Ok(WebhookConfig {
+            url,
+            format,
+            secret: Some(secret).filter(|s| !s.is_empty()),
+            headers: BTreeMap::new(),
+            max_retry_secs: default_max_retry_secs(),
+            allow_insecure,
+        })
+    }
+}
+
+/// Format bytes as lowercase hex.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The value of the signature header for a request body: its HMAC-SHA256 with the secret.
+pub fn sign(secret: &str, body: &[u8]) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    mac.update(body);
+    Ok(format!("sha256={}", to_hex(&mac.finalize().into_bytes())))
+}
+
+/// Encode text fields as multipart/form-data, returning the content type and the body. Fields
+/// with a file name are sent as files.
+fn multipart_body(fields: &[(&str, Option<&str>, String)]) -> (String, Vec<u8>) {
+    // The boundary only has to not appear in the content, and a hash of the content won't.
+    let mut hasher = Sha256::new();
+    for (_, _, value) in fields {
+        hasher.update(value.as_bytes());
+    }
+    let boundary = format!("rsr-{}", to_hex(&hasher.finalize()));
+
+    let mut body = String::new();
+    for (name, file_name, value) in fields {
+        body.push_str(&format!("--{boundary}\r\n"));
+        match file_name {
+            Some(file_name) => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{name}\"; filename=\"{file_name}\"\r\nContent-Type: application/x-pem-file\r\n\r\n"
+            )),
+            None => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{name}\"\r\n\r\n"
+            )),
+        }
+        body.push_str(value);
+        body.push_str("\r\n");
+    }
+    body.push_str(&format!("--{boundary}--\r\n"));
+
+    (
+        format!("multipart/form-data; boundary={boundary}"),
+        body.into_bytes(),
+    )
+}
+
+pub struct Webhook {
+    state: Arc<State>,
+    config: WebhookConfig,
+    subdomain: String,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(state: Arc<State>, config: WebhookConfig, subdomain: String) -> Result<Self> {
+        Ok(Webhook {
+            state,
+            config,
+            subdomain,
+            client: Client::builder()
+                .user_agent(crate::USER_AGENT)
+                .timeout(Duration::from_secs(30))
+                .build()?,
+        })
+    }
+
+    /// Encode the certificate bundle in the configured format, returning the content type and
+    /// the body.
+    fn encode(&self, cert: &Certificate) -> Result<(String, Vec<u8>)> {
+        let info = cert.info()?;
+        let expires_at =
+            time::OffsetDateTime::from_unix_timestamp(info.not_after)?.format(&Rfc3339)?;
+
+        let body = match self.config.format {
+            WebhookFormat::Json => {
+                let body = json!({
+                    "subdomain": self.subdomain,
+                    "serial": info.serial,
+                    "expires_at": expires_at,
+                    "certificate": cert.get_leaf_certificate(),
+                    "chain": cert.get_certificate_chain(),
+                    "fullchain": cert.cert,
+                    "private_key": cert.key,
+                });
+                ("application/json".to_string(), serde_json::to_vec(&body)?)
+            }
+            WebhookFormat::Multipart => multipart_body(&[
+                ("subdomain", None, self.subdomain.clone()),
+                ("serial", None, info.serial),
+                ("expires_at", None, expires_at),
+                (
+                    "certificate",
+                    Some("cert.pem"),
+                    cert.get_leaf_certificate().to_string(),
+                ),
+                (
+                    "chain",
+                    Some("chain.pem"),
+                    cert.get_certificate_chain().to_string(),
+                ),
+                ("fullchain", Some("fullchain.pem"), cert.cert.clone()),
+                ("private_key", Some("privkey.pem"), cert.key.clone()),
+            ]),
+        };
+
+        Ok(body)
+    }
+
+    /// Send the body once. Server errors and failures to connect are worth retrying, but the
+    /// webhook rejecting the request is not.
+    async fn send(
+        &self,
+        content_type: &str,
+        body: &[u8],
+        headers: &[(String, String)],
+        signature: Option<&str>,
+    ) -> Result<(), backoff::Error<Report>> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(CONTENT_TYPE, content_type)
+            .body(body.to_vec());
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(signature) = signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| backoff::Error::transient(Report::from(e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let err = eyre!("Webhook returned {status} {body}");
+        if status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT
+        {
+            Err(backoff::Error::transient(err))
+        } else {
+            Err(backoff::Error::permanent(err))
+        }
+    }
+}
+
+#[async_trait]
+impl DeployEndpoint for Webhook {
+    /// Send the certificate bundle to the webhook, retrying until it accepts it or the retry time
+    /// runs out. The webhook is responsible for the certificate from then on, so there's nothing
+    /// that has to exist beforehand.
+    async fn deploy_certificate(
+        &self,
+        cert: Certificate,
+        _endpoint_must_exist: bool,
+    ) -> Result<()> {
+        let progress = self
+            .state
+            .progress
+            .add(ProgressBar::new_spinner().with_message("Sending certificate to webhook"));
+        progress.enable_steady_tick(Duration::from_millis(125));
+
+        let (content_type, body) = self.encode(&cert)?;
+        let headers = self.config.resolve_headers().await?;
+        let signature = self
+            .config
+            .resolve_secret()
+            .await?
+            .map(|secret| sign(&secret, &body))
+            .transpose()?;
+
+        let boff = ExponentialBackoffBuilder::new()
+            .with_initial_interval(Duration::from_secs(1))
+            .with_max_interval(Duration::from_secs(30))
+            .with_max_elapsed_time(Some(Duration::from_secs(self.config.max_retry_secs)))
+            .build();
+
+        retry(boff, || {
+            self.send(&content_type, &body, &headers, signature.as_deref())
+        })
+        .await
+        .wrap_err("Failed to send the certificate to the webhook")?;
+
+        progress.finish_with_message("Done");
+
+        Ok(())
+    }
+
+    /// The webhook can't be asked what it would do, so this only checks that the configuration
+    /// and its secrets can be read.
+    async fn plan_deploy(
+        &self,
+        _cert: Option<&Certificate>,
+        _endpoint_must_exist: bool,
+    ) -> Result<Vec<String>> {
+        self.config.validate().await?;
+
+        let format = match self.config.format {
+            WebhookFormat::Json => "JSON",
+            WebhookFormat::Multipart => "a multipart form",
+        };
+        let signed = if self.config.secret.is_some() {
+            ", signed"
+        } else {
+            ""
+        };
+
+        Ok(vec![format!(
+            "Would POST the certificate as {format} to {}{signed}",
+            self.config.url
+        )])
+    }
+}
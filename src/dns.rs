@@ -7,7 +7,7 @@ use eyre::{eyre, Result};
 
 use self::vercel::VercelDnsCreds;
 
-#[derive(Debug, Display, EnumIter, EnumString, EnumVariantNames)]
+#[derive(Clone, Debug, Display, EnumIter, EnumString, EnumVariantNames)]
 pub enum DnsProviderType {
     Vercel,
 }
@@ -18,7 +18,7 @@ pub trait DnsProvider: Send + Sync {
     async fn cleanup(&self, record_id: &str) -> Result<()>;
 }
 
-pub fn get_dns_provider(
+pub async fn get_dns_provider(
     provider_type: DnsProviderType,
     subdomain: &str,
     creds: String,
@@ -35,10 +35,22 @@ pub fn get_dns_provider(
 
     let provider = match provider_type {
         DnsProviderType::Vercel => {
-            let creds = VercelDnsCreds::from_string_or_env(creds)?;
+            let creds = VercelDnsCreds::from_string_or_env(creds).await?;
             Box::new(vercel::VercelDns::new(creds, domain)?)
         }
     };
 
     Ok(provider)
 }
+
+/// Check that the credentials for a DNS provider work, without changing anything.
+pub async fn validate_creds(provider_type: DnsProviderType, creds: String) -> Result<()> {
+    match provider_type {
+        DnsProviderType::Vercel => {
+            VercelDnsCreds::from_string_or_env(creds)
+                .await?
+                .validate()
+                .await
+        }
+    }
+}
@@ -11,11 +11,16 @@ pub struct VercelDnsCreds {
 }
 
 impl VercelDnsCreds {
-    pub fn from_string_or_env(creds: String) -> Result<VercelDnsCreds> {
+    pub fn new(token: String) -> VercelDnsCreds {
+        VercelDnsCreds { token }
+    }
+
+    pub async fn from_string_or_env(creds: String) -> Result<VercelDnsCreds> {
         if creds.is_empty() {
             Self::from_env()
         } else {
-            let creds: Self = serde_json::from_str(&creds)?;
+            let mut creds: Self = crate::creds::parse(&creds, Self::new).await?;
+            creds.token = crate::creds::resolve(&creds.token).await?;
             Ok(creds)
         }
     }
@@ -25,6 +30,26 @@ impl VercelDnsCreds {
         Ok(VercelDnsCreds { token })
     }
 
+    /// Make a cheap authenticated request to check that the token works.
+    pub async fn validate(&self) -> Result<()> {
+        let res = Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .build()?
+            .get("https://api.vercel.com/v2/user")
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(eyre::eyre!(
+                "Vercel rejected the API token: {}",
+                res.text().await?
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn from_console() -> Result<Option<VercelDnsCreds>> {
         let token: String = dialoguer::Input::new()
             .with_prompt("Vercel API token (or blank to use $VERCEL_TOKEN)")
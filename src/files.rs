@@ -0,0 +1,259 @@
+//! Writing certificates and keys to local files.
+
+use std::{io::Write, path::Path};
+
+use eyre::{eyre, Result, WrapErr};
+
+/// The mode for files that anyone can read, such as certificates.
+pub const PUBLIC_MODE: u32 = 0o644;
+/// The mode for files that only the owner can read, such as private keys.
+pub const PRIVATE_MODE: u32 = 0o600;
+
+/// The user and group that should own a file. Each part that is `None` is left as it is, which
+/// means the file is owned by whoever runs the command.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl Owner {
+    /// Parse an owner given as `user`, `user:group`, or `:group`. Users and groups can be given
+    /// by name or by numeric ID.
+    #[cfg(unix)]
+    pub fn parse(owner: &str) -> Result<Owner> {
+        let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+
+        let uid = match user {
+            "" => None,
+            user => Some(match user.parse::<u32>() {
+                Ok(uid) => uid,
+                Err(_) => uzers::get_user_by_name(user)
+                    .ok_or_else(|| eyre!("User {user} does not exist"))?
+                    .uid(),
+            }),
+        };
+
+        let gid = match group {
+            "" => None,
+            group => Some(match group.parse::<u32>() {
+                Ok(gid) => gid,
+                Err(_) => uzers::get_group_by_name(group)
+                    .ok_or_else(|| eyre!("Group {group} does not exist"))?
+                    .gid(),
+            }),
+        };
+
+        Ok(Owner { uid, gid })
+    }
+
+    #[cfg(not(unix))]
+    pub fn parse(owner: &str) -> Result<Owner> {
+        if owner.is_empty() || owner == ":" {
+            return Ok(Owner::default());
+        }
+
+        Err(eyre!(
+            "Setting the owner of files is only supported on Unix"
+        ))
+    }
+
+    #[cfg(unix)]
+    fn is_set(&self) -> bool {
+        self.uid.is_some() || self.gid.is_some()
+    }
+}
+
+/// The mode and owner of an existing file, so that a replacement can be given the same ones.
+#[cfg(unix)]
+pub fn mode_and_owner(metadata: &std::fs::Metadata) -> (u32, Owner) {
+    use std::os::unix::fs::MetadataExt;
+
+    let owner = Owner {
+        uid: Some(metadata.uid()),
+        gid: Some(metadata.gid()),
+    };
+    (metadata.mode() & 0o7777, owner)
+}
+
+#[cfg(not(unix))]
+pub fn mode_and_owner(_metadata: &std::fs::Metadata) -> (u32, Owner) {
+    (PUBLIC_MODE, Owner::default())
+}
+
+/// A random suffix for a temporary file name, so that it can't be guessed ahead of time.
+fn random_suffix() -> Result<String> {
+    let mut bytes = [0u8; 8];
+    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes)
+        .map_err(|_| eyre!("Failed to generate a temporary file name"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Write a file by writing a temporary file next to it and renaming it into place, so that
+/// readers never see a partially-written file. The new file has the given mode and owner even if
+/// the file it replaces had different permissions.
+pub fn write_atomic(path: &Path, contents: &[u8], mode: u32, owner: Owner) -> Result<()> {
+    write_atomic_with_suffixes(
+        path,
+        contents,
+        mode,
+        owner,
+        std::iter::repeat_with(random_suffix),
+    )
+}
+
+/// Write a file like [`write_atomic`], trying each suffix for the temporary file until one names
+/// a file that doesn't exist yet.
+fn write_atomic_with_suffixes(
+    path: &Path,
+    contents: &[u8],
+    mode: u32,
+    owner: Owner,
+    suffixes: impl Iterator<Item = Result<String>>,
+) -> Result<()> {
+    // Only Unix has file modes and owners.
+    #[cfg(not(unix))]
+    let _ = (mode, owner);
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| eyre!("{} is not a file path", path.display()))?
+        .to_string_lossy()
+        .into_owned();
+
+    // The directory may be writable by others, so the temporary file must be a new file that
+    // this creates, never something already there or a symlink to somewhere else.
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode).custom_flags(libc::O_NOFOLLOW);
+    }
+
+    let (temp_path, mut file) = (|| -> Result<_> {
+        for suffix in suffixes.take(10) {
+            let temp_path = path.with_file_name(format!(".{file_name}.tmp-{}", suffix?));
+            match options.open(&temp_path) {
+                Ok(file) => return Ok((temp_path, file)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(eyre!("Could not find an unused temporary file name"))
+    })()
+    .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+
+    let result = (|| -> Result<()> {
+        // The mode given to `open` is reduced by the umask, so set it explicitly. Both this and
+        // the owner go through the open file, so they only ever apply to the file created above.
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(mode))?;
+        #[cfg(unix)]
+        if owner.is_set() {
+            std::os::unix::fs::fchown(&file, owner.uid, owner.gid)?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        std::fs::remove_file(&temp_path).ok();
+    }
+
+    result.wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        os::unix::fs::{symlink, PermissionsExt},
+        path::PathBuf,
+    };
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rsr-files-test-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::symlink_metadata(path)
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o7777
+    }
+
+    #[test]
+    fn replaces_file_with_mode() {
+        let dir = test_dir("replace");
+        let path = dir.join("key.pem");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, PermissionsExt::from_mode(0o644)).unwrap();
+
+        write_atomic(&path, b"new", PRIVATE_MODE, Owner::default()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(mode(&path), PRIVATE_MODE);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn leaves_symlinked_temp_file_target_alone() {
+        let dir = test_dir("symlink");
+        let target = dir.join("target");
+        std::fs::write(&target, "untouched").unwrap();
+        std::fs::set_permissions(&target, PermissionsExt::from_mode(0o644)).unwrap();
+        let planted = dir.join(".key.pem.tmp-a");
+        symlink(&target, &planted).unwrap();
+
+        let path = dir.join("key.pem");
+        let suffixes = ["a", "b"].into_iter().map(|s| Ok(s.to_string()));
+        write_atomic_with_suffixes(&path, b"secret", PRIVATE_MODE, Owner::default(), suffixes)
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "untouched");
+        assert_eq!(mode(&target), 0o644);
+        assert!(std::fs::symlink_metadata(&planted)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "secret");
+        assert_eq!(mode(&path), PRIVATE_MODE);
+        assert!(!dir.join(".key.pem.tmp-b").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn gives_up_when_every_temp_name_is_taken() {
+        let dir = test_dir("taken");
+        let target = dir.join("target");
+        std::fs::write(&target, "untouched").unwrap();
+        symlink(&target, dir.join(".key.pem.tmp-a")).unwrap();
+
+        let path = dir.join("key.pem");
+        let suffixes = std::iter::repeat_with(|| Ok("a".to_string()));
+        let err =
+            write_atomic_with_suffixes(&path, b"secret", PRIVATE_MODE, Owner::default(), suffixes)
+                .unwrap_err();
+
+        assert_eq!(
+            format!("{err:#}"),
+            format!(
+                "Failed to write {}: Could not find an unused temporary file name",
+                path.display()
+            )
+        );
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "untouched");
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
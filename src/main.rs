@@ -1,45 +1,29 @@
 mod acme;
+mod certificate;
 mod cli;
 mod cmd;
+mod creds;
+mod crypto;
 mod db;
 mod deploy;
 mod dns;
+mod files;
+mod notify;
+mod settings;
 mod tracing_config;
+mod verify;
 
 use eyre::Result;
-use serde::{Deserialize, Serialize};
 
-pub const USER_AGENT: &str = concat!("remote-ssl-renewal/", env!("CARGO_PKG_VERSION"));
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Certificate {
-    pub cert: String,
-    pub key: String,
-}
-
-impl Certificate {
-    pub fn get_leaf_certificate(&self) -> &str {
-        self.cert
-            .split_inclusive("-----END CERTIFICATE-----\n")
-            .next()
-            .unwrap()
-    }
+pub use certificate::Certificate;
 
-    pub fn get_certificate_chain(&self) -> &str {
-        self.cert
-            .split_once("-----END CERTIFICATE-----\n")
-            .map(|(_, chain)| chain)
-            .unwrap_or("")
-    }
-}
+pub const USER_AGENT: &str = concat!("remote-ssl-renewal/", env!("CARGO_PKG_VERSION"));
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     color_eyre::install()?;
     tracing_config::init_tracing();
 
-    let db = db::create_db().await?;
-
-    cmd::run(db).await?;
+    cmd::run().await?;
     Ok(())
 }
@@ -0,0 +1,567 @@
+//! Notifications about certificates, so that an unattended renewal that fails doesn't go
+//! unnoticed. Every channel is sent the same events, filtered by its own settings.
+
+mod chat;
+mod email;
+
+use std::time::Duration;
+
+use eyre::Report;
+use reqwest::Client;
+use serde::Deserialize;
+use strum::AsRefStr;
+use time::OffsetDateTime;
+
+use crate::{cli::describe_expiration, cmd::State};
+
+/// The kinds of event, for choosing which ones a channel is sent and the template for each.
+#[derive(AsRefStr, Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EventKind {
+    Issued,
+    Renewed,
+    RenewalFailed,
+    DeployFailed,
+    ChallengeCleanupFailed,
+    ExpiringSoon,
+    Summary,
+    Test,
+}
+
+/// Format an error and each of its causes.
+fn error_chain(error: &Report) -> Vec<String> {
+    error.chain().map(|cause| cause.to_string()).collect()
+}
+
+/// The outcome of trying to renew one subdomain.
+pub struct RenewalResult {
+    pub subdomain: String,
+    /// The error and each of its causes, if renewing failed
+    pub error: Option<Vec<String>>,
+    /// How many times in a row renewing has failed, including this time
+    pub failures: i64,
+    /// When the subdomain's current certificate expires
+    pub expires: Option<i64>,
+}
+
+impl RenewalResult {
+    pub fn new(
+        subdomain: String,
+        error: Option<&Report>,
+        failures: i64,
+        expires: Option<i64>,
+    ) -> Self {
+        RenewalResult {
+            subdomain,
+            error: error.map(error_chain),
+            failures,
+            expires,
+        }
+    }
+
+    /// Whether the certificate expires soon enough that a failure needs attention right away.
+    pub fn expiring_soon(&self, warning_days: i64) -> bool {
+        let warning = OffsetDateTime::now_utc() + time::Duration::days(warning_days);
+        self.expires
+            .map(|expires| expires < warning.unix_timestamp())
+            .unwrap_or(false)
+    }
+
+    fn describe_failure(&self, body: &mut String) {
+        if let Some(error) = &self.error {
+            describe_error(error, body);
+        }
+
+        let times = if self.failures == 1 {
+            "once".to_string()
+        } else {
+            format!("{} times in a row", self.failures)
+        };
+        body.push_str(&format!(
+            "  Renewing has failed {times}. The current certificate expires {}.\n",
+            describe_expiration(self.expires)
+        ));
+    }
+}
+
+fn describe_error(error: &[String], body: &mut String) {
+    if let Some((error, causes)) = error.split_first() {
+        body.push_str(&format!("  Error: {error}\n"));
+        for cause in causes {
+            body.push_str(&format!("  Caused by: {cause}\n"));
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Event<'a> {
+    /// A subdomain's first certificate was issued and deployed.
+    Issued {
+        subdomain: &'a str,
+        serial: &'a str,
+        expires: i64,
+    },
+    /// A certificate replaced the subdomain's earlier one and was deployed.
+    Renewed {
+        subdomain: &'a str,
+        serial: &'a str,
+        expires: i64,
+    },
+    /// A certificate was issued, but deploying it to at least one host failed.
+    DeployFailed {
+        subdomain: &'a str,
+        serial: &'a str,
+        error: &'a Report,
+    },
+    /// The challenge record couldn't be removed from DNS once the challenge was done.
+    ChallengeCleanupFailed {
+        subdomain: &'a str,
+        error: &'a Report,
+    },
+    /// Renewing a subdomain failed. This is sent right away, rather than waiting for the rest of
+    /// the batch.
+    RenewalFailed(&'a RenewalResult),
+    /// Renewing a subdomain failed, and its certificate expires within the warning period.
+    ExpiringSoon(&'a RenewalResult),
+    /// The results of renewing every subdomain that was due.
+    Summary(Vec<&'a RenewalResult>),
+    /// A message to check that notifications can be sent.
+    Test,
+}
+
+impl<'a> Event<'a> {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Issued { .. } => EventKind::Issued,
+            Event::Renewed { .. } => EventKind::Renewed,
+            Event::DeployFailed { .. } => EventKind::DeployFailed,
+            Event::ChallengeCleanupFailed { .. } => EventKind::ChallengeCleanupFailed,
+            Event::RenewalFailed(_) => EventKind::RenewalFailed,
+            Event::ExpiringSoon(_) => EventKind::ExpiringSoon,
+            Event::Summary(_) => EventKind::Summary,
+            Event::Test => EventKind::Test,
+        }
+    }
+
+    /// Whether the event means something needs looking at.
+    fn is_problem(&self) -> bool {
+        match self {
+            Event::Issued { .. } | Event::Renewed { .. } | Event::Test => false,
+            Event::Summary(results) => results.iter().any(|r| r.error.is_some()),
+            _ => true,
+        }
+    }
+
+    fn subdomain(&self) -> Option<&'a str> {
+        match self {
+            Event::Issued { subdomain, .. }
+            | Event::Renewed { subdomain, .. }
+            | Event::DeployFailed { subdomain, .. }
+            | Event::ChallengeCleanupFailed { subdomain, .. } => Some(subdomain),
+            Event::RenewalFailed(result) | Event::ExpiringSoon(result) => Some(&result.subdomain),
+            Event::Summary(_) | Event::Test => None,
+        }
+    }
+
+    /// Narrow the event to what a channel should be sent, given the events and subdomains it's
+    /// limited to. Either list being empty means there's no limit. A summary only includes the
+    /// channel's subdomains.
+    fn for_channel(&self, events: &[EventKind], subdomains: &[String]) -> Option<Event<'a>> {
+        let kind = self.kind();
+        if kind != EventKind::Test && !events.is_empty() && !events.contains(&kind) {
+            return None;
+        }
+
+        let included = |subdomain: &str| {
+            subdomains.is_empty()
+                || subdomains
+                    .iter()
+                    .any(|pattern| matches_subdomain(pattern, subdomain))
+        };
+
+        match self {
+            Event::Summary(results) => {
+                let results = results
+                    .iter()
+                    .copied()
+                    .filter(|r| included(&r.subdomain))
+                    .collect::<Vec<_>>();
+                (!results.is_empty()).then_some(Event::Summary(results))
+            }
+            _ => match self.subdomain() {
+                Some(subdomain) if !included(subdomain) => None,
+                _ => Some(self.clone()),
+            },
+        }
+    }
+
+    fn subject(&self) -> String {
+        match self {
+            Event::Issued { subdomain, .. } => format!("Issued a certificate for {subdomain}"),
+            Event::Renewed { subdomain, .. } => format!("Renewed the certificate for {subdomain}"),
+            Event::DeployFailed { subdomain, .. } => {
+                format!("Deploying the new certificate for {subdomain} failed")
+            }
+            Event::ChallengeCleanupFailed { subdomain, .. } => {
+                format!("Removing the challenge record for {subdomain} failed")
+            }
+            Event::RenewalFailed(result) => format!("Renewing {} failed", result.subdomain),
+            Event::ExpiringSoon(result) => {
+                let days = (result.expires.unwrap_or_default()
+                    - OffsetDateTime::now_utc().unix_timestamp())
+                    / 86400;
+                let expires = if days < 0 {
+                    "has expired".to_string()
+                } else {
+                    format!("expires in {days} days")
+                };
+                let failed = if result.failures == 1 {
+                    "failed".to_string()
+                } else {
+                    format!("has failed {} times in a row", result.failures)
+                };
+                format!("{} {expires} and renewing it {failed}", result.subdomain)
+            }
+            Event::Summary(results) => {
+                let failed = results.iter().filter(|r| r.error.is_some()).count();
+                if failed == 0 {
+                    format!("Renewed {} certificates", results.len())
+                } else {
+                    format!(
+                        "Renewed {} of {} certificates, {failed} failed",
+                        results.len() - failed,
+                        results.len()
+                    )
+                }
+            }
+            Event::Test => "Test notification from remote-ssl-renewal".to_string(),
+        }
+    }
+
+    fn body(&self) -> String {
+        let mut body = String::new();
+        match self {
+            Event::Issued {
+                subdomain,
+                serial,
+                expires,
+            }
+            | Event::Renewed {
+                subdomain,
+                serial,
+                expires,
+            } => {
+                body.push_str(&format!(
+                    "Certificate {serial} for {subdomain} was deployed. It expires {}.\n",
+                    describe_expiration(Some(*expires))
+                ));
+            }
+            Event::DeployFailed {
+                subdomain,
+                serial,
+                error,
+            } => {
+                body.push_str(&format!(
+                    "Certificate {serial} for {subdomain} was issued, but deploying it failed.\n\n"
+                ));
+                describe_error(&error_chain(error), &mut body);
+            }
+            Event::ChallengeCleanupFailed { subdomain, error } => {
+                body.push_str(&format!(
+                    "The _acme-challenge record for {subdomain} may have been left in DNS.\n\n"
+                ));
+                describe_error(&error_chain(error), &mut body);
+            }
+            Event::RenewalFailed(result) | Event::ExpiringSoon(result) => {
+                body.push_str(&format!(
+                    "Renewing the certificate for {} failed.\n\n",
+                    result.subdomain
+                ));
+                result.describe_failure(&mut body);
+            }
+            Event::Summary(results) => {
+                let (failed, renewed): (Vec<&RenewalResult>, Vec<&RenewalResult>) =
+                    results.iter().partition(|r| r.error.is_some());
+
+                if !renewed.is_empty() {
+                    body.push_str("Renewed:\n");
+                    for result in renewed {
+                        body.push_str(&format!(
+                            "- {}, which now expires {}\n",
+                            result.subdomain,
+                            describe_expiration(result.expires)
+                        ));
+                    }
+                }
+
+                if !failed.is_empty() {
+                    if !body.is_empty() {
+                        body.push('\n');
+                    }
+                    body.push_str("Failed:\n");
+                    for result in failed {
+                        body.push_str(&format!("- {}\n", result.subdomain));
+                        result.describe_failure(&mut body);
+                    }
+                }
+            }
+            Event::Test => {
+                body.push_str("Notifications from remote-ssl-renewal will be sent here.\n");
+            }
+        }
+
+        body
+    }
+
+    /// The values that templates can use, by name.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("event", self.kind().as_ref().to_string()),
+            ("title", self.subject()),
+            ("details", self.body().trim_end().to_string()),
+            (
+                "subdomain",
+                self.subdomain().unwrap_or_default().to_string(),
+            ),
+        ];
+
+        match self {
+            Event::Issued {
+                serial, expires, ..
+            }
+            | Event::Renewed {
+                serial, expires, ..
+            } => {
+                fields.push(("serial", serial.to_string()));
+                fields.push(("expires", describe_expiration(Some(*expires))));
+            }
+            Event::DeployFailed { serial, error, .. } => {
+                fields.push(("serial", serial.to_string()));
+                fields.push(("error", format!("{error:#}")));
+            }
+            Event::ChallengeCleanupFailed { error, .. } => {
+                fields.push(("error", format!("{error:#}")));
+            }
+            Event::RenewalFailed(result) | Event::ExpiringSoon(result) => {
+                fields.push(("expires", describe_expiration(result.expires)));
+                fields.push(("failures", result.failures.to_string()));
+                fields.push((
+                    "error",
+                    result.error.as_deref().unwrap_or_default().join(": "),
+                ));
+            }
+            Event::Summary(_) | Event::Test => {}
+        }
+
+        fields
+    }
+
+    /// Fill in a template's `{field}` placeholders. Fields that don't apply to the event are left
+    /// empty.
+    fn render(&self, template: &str) -> String {
+        let mut message = template.to_string();
+        for (name, value) in self.fields() {
+            message = message.replace(&format!("{{{name}}}"), &value);
+        }
+        for name in ["serial", "expires", "error", "failures"] {
+            message = message.replace(&format!("{{{name}}}"), "");
+        }
+        message
+    }
+}
+
+/// Whether a subdomain matches a name from the settings, which may be a wildcard like
+/// `*.example.com`.
+fn matches_subdomain(pattern: &str, subdomain: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some(suffix) if suffix.starts_with('.') => subdomain
+            .to_ascii_lowercase()
+            .ends_with(&suffix.to_ascii_lowercase()),
+        _ => pattern.eq_ignore_ascii_case(subdomain),
+    }
+}
+
+/// Send an event through each configured channel, returning whether there are any. A
+/// notification that can't be sent is reported, but isn't an error, so that it doesn't hide the
+/// result of the renewal itself.
+pub async fn send(state: &State, event: Event<'_>) -> bool {
+    let settings = &state.settings.notify;
+
+    if let Some(email) = &settings.email {
+        if let Some(event) = event.for_channel(&email.events, &email.subdomains) {
+            if let Err(e) = email::send(email, &event.subject(), &event.body()).await {
+                eprintln!("Failed to send email notification: {e:#}");
+            }
+        }
+    }
+
+    if !settings.notifiers.is_empty() {
+        let client = match Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .timeout(Duration::from_secs(30))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("Failed to send notifications: {e:#}");
+                return true;
+            }
+        };
+
+        for (name, notifier) in &settings.notifiers {
+            let Some(event) = event.for_channel(&notifier.events, &notifier.subdomains) else {
+                continue;
+            };
+            if let Err(e) = chat::send(&client, notifier, &event).await {
+                eprintln!("Failed to send notification through {name}: {e:#}");
+            }
+        }
+    }
+
+    settings.email.is_some() || !settings.notifiers.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::eyre;
+
+    use super::*;
+
+    fn result(subdomain: &str, error: Option<&Report>, expires_in_days: i64) -> RenewalResult {
+        let expires = OffsetDateTime::now_utc() + time::Duration::days(expires_in_days);
+        RenewalResult::new(
+            subdomain.to_string(),
+            error,
+            1,
+            Some(expires.unix_timestamp()),
+        )
+    }
+
+    #[test]
+    fn matches_subdomain_patterns() {
+        assert!(matches_subdomain("a.example.com", "A.Example.com"));
+        assert!(!matches_subdomain("a.example.com", "b.example.com"));
+        assert!(matches_subdomain("*.example.com", "a.example.com"));
+        assert!(matches_subdomain("*.Example.com", "a.b.example.COM"));
+        assert!(!matches_subdomain("*.example.com", "example.com"));
+        assert!(!matches_subdomain("*.example.com", "a.badexample.com"));
+        // Only `*.` makes a wildcard.
+        assert!(!matches_subdomain("*example.com", "a.example.com"));
+    }
+
+    #[test]
+    fn channel_event_filter() {
+        let error = eyre!("boom");
+        let failed = result("a.example.com", Some(&error), 30);
+        let event = Event::RenewalFailed(&failed);
+
+        assert!(event.for_channel(&[], &[]).is_some());
+        assert!(event
+            .for_channel(&[EventKind::RenewalFailed], &[])
+            .is_some());
+        assert!(event.for_channel(&[EventKind::DeployFailed], &[]).is_none());
+        // A test notification goes everywhere, so that every channel can be checked.
+        assert!(Event::Test
+            .for_channel(&[EventKind::DeployFailed], &[])
+            .is_some());
+    }
+
+    #[test]
+    fn channel_subdomain_filter() {
+        let error = eyre!("boom");
+        let failed = result("a.example.com", Some(&error), 30);
+        let event = Event::RenewalFailed(&failed);
+
+        assert!(event
+            .for_channel(&[], &["*.example.com".to_string()])
+            .is_some());
+        assert!(event
+            .for_channel(&[], &["b.example.com".to_string()])
+            .is_none());
+        // Events without a subdomain aren't limited by it.
+        assert!(Event::Test
+            .for_channel(&[], &["b.example.com".to_string()])
+            .is_some());
+    }
+
+    #[test]
+    fn channel_summary_only_has_its_subdomains() {
+        let a = result("a.example.com", None, 90);
+        let b = result("b.example.org", None, 90);
+        let event = Event::Summary(vec![&a, &b]);
+
+        match event.for_channel(&[], &["*.example.com".to_string()]) {
+            Some(Event::Summary(results)) => {
+                assert_eq!(results.len(), 1);
+                assert_eq!(results[0].subdomain, "a.example.com");
+            }
+            _ => panic!("expected a summary"),
+        }
+        assert!(event
+            .for_channel(&[], &["c.example.net".to_string()])
+            .is_none());
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let event = Event::Renewed {
+            subdomain: "a.example.com",
+            serial: "0A1B",
+            expires: OffsetDateTime::now_utc().unix_timestamp() + 90 * 86400,
+        };
+
+        assert_eq!(
+            event.render("{event}: {subdomain} {serial}"),
+            "renewed: a.example.com 0A1B"
+        );
+        // Fields that don't apply are left empty, and unknown names are left alone.
+        assert_eq!(event.render("[{error}{failures}] {other}"), "[] {other}");
+        assert_eq!(
+            event.render("{title}"),
+            "Renewed the certificate for a.example.com"
+        );
+    }
+
+    #[test]
+    fn render_failure_fields() {
+        let error = eyre!("DNS record never appeared").wrap_err("Renewing failed");
+        let failed = result("a.example.com", Some(&error), 30);
+        let event = Event::RenewalFailed(&failed);
+
+        assert_eq!(
+            event.render("{failures} {error}"),
+            "1 Renewing failed: DNS record never appeared"
+        );
+        assert_eq!(event.render("{serial}"), "");
+    }
+
+    #[test]
+    fn expiring_soon() {
+        assert!(result("a.example.com", None, 3).expiring_soon(7));
+        assert!(result("a.example.com", None, -1).expiring_soon(7));
+        assert!(!result("a.example.com", None, 30).expiring_soon(7));
+
+        let unknown = RenewalResult::new("a.example.com".to_string(), None, 1, None);
+        assert!(!unknown.expiring_soon(7));
+    }
+
+    #[test]
+    fn summary_subject_counts() {
+        let error = eyre!("boom");
+        let a = result("a.example.com", None, 90);
+        let b = result("b.example.com", None, 90);
+        let c = result("c.example.com", Some(&error), 10);
+
+        assert_eq!(
+            Event::Summary(vec![&a, &b]).subject(),
+            "Renewed 2 certificates"
+        );
+        assert_eq!(
+            Event::Summary(vec![&a, &b, &c]).subject(),
+            "Renewed 2 of 3 certificates, 1 failed"
+        );
+        assert!(Event::Summary(vec![&a, &c]).is_problem());
+        assert!(!Event::Summary(vec![&a]).is_problem());
+    }
+}
@@ -0,0 +1,101 @@
+use eyre::{eyre, Result, WrapErr};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde_json::json;
+
+use crate::{
+    deploy::webhook::{sign, SIGNATURE_HEADER},
+    settings::{NotifierKind, NotifierSettings},
+};
+
+use super::Event;
+
+/// Discord rejects messages longer than this.
+const DISCORD_MAX_LENGTH: usize = 2000;
+
+/// The template used for an event when the settings don't give one. ntfy shows the title
+/// separately, so only the details go in the message.
+fn default_template(kind: NotifierKind) -> &'static str {
+    match kind {
+        NotifierKind::Slack => "*{title}*\n{details}",
+        NotifierKind::Discord => "**{title}**\n{details}",
+        NotifierKind::Ntfy => "{details}",
+        NotifierKind::Webhook => "{title}\n{details}",
+    }
+}
+
+/// Cut a message down to a length in characters, marking that it was cut.
+fn truncate(message: String, max: usize) -> String {
+    if message.chars().count() <= max {
+        return message;
+    }
+
+    let mut truncated = message.chars().take(max - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+/// Send an event to a chat service or webhook.
+pub async fn send(client: &Client, settings: &NotifierSettings, event: &Event<'_>) -> Result<()> {
+    let url = crate::creds::resolve(&settings.url)
+        .await
+        .wrap_err("Failed to read the notifier URL")?;
+    let template = settings
+        .templates
+        .get(&event.kind())
+        .map(|t| t.as_str())
+        .unwrap_or_else(|| default_template(settings.kind));
+    let message = event.render(template);
+
+    let request = match settings.kind {
+        NotifierKind::Slack => client.post(&url).json(&json!({ "text": message })),
+        NotifierKind::Discord => client
+            .post(&url)
+            .json(&json!({ "content": truncate(message, DISCORD_MAX_LENGTH) })),
+        NotifierKind::Ntfy => {
+            let (priority, tags) = if event.is_problem() {
+                ("high", "warning")
+            } else {
+                ("default", "lock")
+            };
+            let mut request = client
+                .post(&url)
+                .header("Title", event.subject())
+                .header("Priority", priority)
+                .header("Tags", tags)
+                .body(message);
+            if let Some(token) = &settings.token {
+                let token = crate::creds::resolve(token)
+                    .await
+                    .wrap_err("Failed to read the ntfy token")?;
+                request = request.bearer_auth(token);
+            }
+            request
+        }
+        NotifierKind::Webhook => {
+            let mut payload = serde_json::Map::new();
+            for (name, value) in event.fields() {
+                payload.insert(name.to_string(), value.into());
+            }
+            payload.insert("message".to_string(), message.into());
+            let body = serde_json::to_vec(&payload)?;
+
+            let mut request = client.post(&url).header(CONTENT_TYPE, "application/json");
+            if let Some(secret) = &settings.secret {
+                let secret = crate::creds::resolve(secret)
+                    .await
+                    .wrap_err("Failed to read the webhook signing secret")?;
+                request = request.header(SIGNATURE_HEADER, sign(&secret, &body)?);
+            }
+            request.body(body)
+        }
+    };
+
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(eyre!("{} returned {status} {body}", settings.kind.label()));
+    }
+
+    Ok(())
+}
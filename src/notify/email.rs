@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use eyre::{eyre, Result, WrapErr};
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::settings::{EmailSettings, SmtpTls};
+
+fn mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .wrap_err_with(|| format!("{address} is not a valid email address"))
+}
+
+/// Send a plain text email to each of the configured addresses.
+pub async fn send(settings: &EmailSettings, subject: &str, body: &str) -> Result<()> {
+    if settings.to.is_empty() {
+        return Err(eyre!("notify.email.to has no addresses to send to"));
+    }
+
+    let mut message = Message::builder()
+        .from(mailbox(&settings.from)?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for to in &settings.to {
+        message = message.to(mailbox(to)?);
+    }
+    let message = message.body(body.to_string())?;
+
+    let host = settings.smtp_host.as_str();
+    let mut transport = match settings.tls {
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+    }
+    .timeout(Some(Duration::from_secs(30)));
+
+    if let Some(port) = settings.smtp_port {
+        transport = transport.port(port);
+    }
+
+    if let Some(username) = &settings.username {
+        let password = crate::creds::resolve_option(settings.password.as_deref())
+            .await
+            .wrap_err("Failed to read the SMTP password")?
+            .unwrap_or_default();
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .wrap_err_with(|| format!("Failed to send email through {host}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    use super::*;
+
+    /// A minimal SMTP server that accepts one message, and the lines it received.
+    fn smtp_sink() -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            let mut in_data = false;
+
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches(['\r', '\n']).to_string();
+
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        stream.write_all(b"250 Queued\r\n").unwrap();
+                    } else {
+                        received.push(line);
+                    }
+                    continue;
+                }
+
+                let command = line.to_ascii_uppercase();
+                received.push(line);
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-localhost\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    stream.write_all(b"221 Bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                stream.write_all(reply).unwrap();
+            }
+
+            received
+        });
+
+        (port, handle)
+    }
+
+    fn settings(port: u16, to: &[&str]) -> EmailSettings {
+        EmailSettings {
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: Some(port),
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "certs@example.com".to_string(),
+            to: to.iter().map(|to| to.to_string()).collect(),
+            events: Vec::new(),
+            subdomains: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_to_each_address() {
+        let (port, sink) = smtp_sink();
+
+        send(
+            &settings(port, &["ops@example.com", "oncall@example.com"]),
+            "Renewing a.example.com failed",
+            "Renewing the certificate for a.example.com failed.\n",
+        )
+        .await
+        .unwrap();
+
+        let received = sink.join().unwrap();
+        let has = |prefix: &str| received.iter().any(|line| line.starts_with(prefix));
+        assert!(has("MAIL FROM:<certs@example.com>"));
+        assert!(has("RCPT TO:<ops@example.com>"));
+        assert!(has("RCPT TO:<oncall@example.com>"));
+        assert!(has("Subject: Renewing a.example.com failed"));
+        assert!(has("Content-Type: text/plain"));
+        assert!(has("Renewing the certificate for a.example.com failed."));
+    }
+
+    #[tokio::test]
+    async fn needs_an_address() {
+        let err = send(&settings(25, &[]), "Subject", "Body")
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "notify.email.to has no addresses to send to"
+        );
+    }
+}
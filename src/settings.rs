@@ -0,0 +1,306 @@
+//! Settings that tune how certificates are issued and renewed.
+//!
+//! Settings are read from `config.toml` in the config directory, with a `[profiles.<name>]`
+//! section to override them for a profile, and then from `RSR_*` environment variables, where
+//! nested settings are separated by a double underscore, as in `RSR_DNS_CHECK__TIMEOUT_SECS`.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use config::{Config, ConfigError, Environment, File, FileFormat, Map, Source, Value};
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+
+use crate::notify::EventKind;
+
+/// Every setting, with its default value if it has one.
+const SETTINGS: &[(&str, Option<i64>)] = &[
+    ("renewal_threshold_days", Some(30)),
+    ("concurrency", Some(4)),
+    ("propagation_delay_secs", Some(15)),
+    ("dns_check.initial_interval_secs", Some(2)),
+    ("dns_check.max_interval_secs", Some(60)),
+    ("dns_check.timeout_secs", Some(600)),
+    ("verify.timeout_secs", Some(300)),
+    ("verify.interval_secs", Some(10)),
+    ("verify.port", Some(443)),
+    ("notify.expiry_warning_days", Some(7)),
+    ("notify.email.smtp_host", None),
+    ("notify.email.smtp_port", None),
+    ("notify.email.tls", None),
+    ("notify.email.username", None),
+    ("notify.email.password", None),
+    ("notify.email.from", None),
+    ("notify.email.to", None),
+    ("defaults.account", None),
+    ("defaults.dns", None),
+    ("defaults.endpoint", None),
+];
+
+#[derive(Debug, Deserialize)]
+pub struct Settings {
+    /// Renew certificates that expire within this many days.
+    pub renewal_threshold_days: i64,
+    /// How many certificates to renew at the same time.
+    pub concurrency: usize,
+    /// How long to wait after the challenge record is visible, to give it time to reach the
+    /// rest of the provider's DNS servers.
+    pub propagation_delay_secs: u64,
+    pub dns_check: DnsCheckSettings,
+    pub verify: VerifySettings,
+    pub notify: NotifySettings,
+    #[serde(default)]
+    pub defaults: DefaultObjects,
+}
+
+/// How often to look for the challenge record while waiting for it to appear.
+#[derive(Debug, Deserialize)]
+pub struct DnsCheckSettings {
+    pub initial_interval_secs: u64,
+    pub max_interval_secs: u64,
+    /// Give up if the record hasn't appeared after this long.
+    pub timeout_secs: u64,
+}
+
+/// How to check that a subdomain serves its certificate after it's deployed.
+#[derive(Debug, Deserialize)]
+pub struct VerifySettings {
+    /// Keep checking for this long, to give the certificate time to reach every server. Zero
+    /// turns the check off.
+    pub timeout_secs: u64,
+    pub interval_secs: u64,
+    /// The port that the subdomain serves HTTPS on
+    pub port: u16,
+}
+
+/// Where to send notifications about renewals.
+#[derive(Debug, Deserialize)]
+pub struct NotifySettings {
+    /// Warn that a certificate is about to expire when it's within this many days of expiring
+    /// and renewing it fails.
+    pub expiry_warning_days: i64,
+    #[serde(default)]
+    pub email: Option<EmailSettings>,
+    /// Chat services and webhooks to notify, by name
+    #[serde(default)]
+    pub notifiers: BTreeMap<String, NotifierSettings>,
+}
+
+/// How to connect to the SMTP server, from the start or after STARTTLS.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// Upgrade to TLS with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// No encryption, for a local relay or test server
+    None,
+}
+
+/// The SMTP server and addresses to send email notifications with.
+#[derive(Debug, Deserialize)]
+pub struct EmailSettings {
+    pub smtp_host: String,
+    /// Defaults to the usual port for the TLS mode.
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    /// The password, or a reference to it such as `env:SMTP_PASSWORD`
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Only send these events, instead of all of them
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// Only send events for these subdomains, which may be wildcards like `*.example.com`
+    #[serde(default)]
+    pub subdomains: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifierKind {
+    /// A Slack incoming webhook
+    Slack,
+    /// A Discord channel webhook
+    Discord,
+    /// An ntfy topic
+    Ntfy,
+    /// A JSON POST to any URL
+    Webhook,
+}
+
+impl NotifierKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotifierKind::Slack => "Slack",
+            NotifierKind::Discord => "Discord",
+            NotifierKind::Ntfy => "ntfy",
+            NotifierKind::Webhook => "Webhook",
+        }
+    }
+}
+
+/// A chat service or webhook to send notifications to.
+#[derive(Debug, Deserialize)]
+pub struct NotifierSettings {
+    pub kind: NotifierKind,
+    /// The webhook or topic URL, or a reference to it
+    pub url: String,
+    /// The access token for an ntfy topic, or a reference to it
+    pub token: Option<String>,
+    /// The key to sign generic webhook requests with, or a reference to it
+    pub secret: Option<String>,
+    /// Only send these events, instead of all of them
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// Only send events for these subdomains, which may be wildcards like `*.example.com`
+    #[serde(default)]
+    pub subdomains: Vec<String>,
+    /// Message templates by event, replacing the default ones
+    #[serde(default)]
+    pub templates: BTreeMap<EventKind, String>,
+}
+
+/// The objects that new subdomains use when none are given.
+#[derive(Debug, Default, Deserialize)]
+pub struct DefaultObjects {
+    pub account: Option<String>,
+    pub dns: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+impl Settings {
+    pub fn load(path: &Path, profile: Option<&str>) -> Result<Settings> {
+        SettingsLayers::load(path, profile)?.merge()
+    }
+
+    pub fn propagation_delay(&self) -> Duration {
+        Duration::from_secs(self.propagation_delay_secs)
+    }
+}
+
+/// A profile's section of the config file, as a source of its own.
+#[derive(Clone, Debug)]
+struct TableSource(Map<String, Value>);
+
+impl Source for TableSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Each place that settings come from, from lowest to highest precedence after the defaults.
+pub struct SettingsLayers {
+    path: PathBuf,
+    profile: Option<String>,
+    file: Config,
+    profile_section: Config,
+    env: Config,
+}
+
+impl SettingsLayers {
+    pub fn load(path: &Path, profile: Option<&str>) -> Result<SettingsLayers> {
+        let file = Config::builder()
+            .add_source(File::from(path).format(FileFormat::Toml).required(false))
+            .build()
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+        let section = profile
+            .and_then(|profile| file.get_table(&format!("profiles.{profile}")).ok())
+            .unwrap_or_default();
+        let profile_section = Config::builder().add_source(TableSource(section)).build()?;
+
+        let env = Config::builder()
+            .add_source(
+                Environment::with_prefix("RSR")
+                    .prefix_separator("_")
+                    .separator("__"),
+            )
+            .build()?;
+
+        Ok(SettingsLayers {
+            path: path.to_path_buf(),
+            profile: profile.map(|p| p.to_string()),
+            file,
+            profile_section,
+            env,
+        })
+    }
+
+    fn merged(&self) -> Result<Config> {
+        let mut builder = Config::builder();
+        for (key, default) in SETTINGS {
+            if let Some(default) = default {
+                builder = builder.set_default(*key, *default)?;
+            }
+        }
+
+        Ok(builder
+            .add_source(self.file.clone())
+            .add_source(self.profile_section.clone())
+            .add_source(self.env.clone())
+            .build()?)
+    }
+
+    pub fn merge(&self) -> Result<Settings> {
+        self.merged()?
+            .try_deserialize()
+            .wrap_err("Invalid settings")
+    }
+
+    /// Return each setting's effective value, and a description of where it came from.
+    pub fn describe(&self) -> Result<Vec<(&'static str, Option<String>, String)>> {
+        let merged = self.merged()?;
+        let has = |config: &Config, key: &str| config.get::<Value>(key).is_ok();
+
+        let described = SETTINGS
+            .iter()
+            .map(|(key, default)| {
+                let source = if has(&self.env, key) {
+                    format!(
+                        "environment (RSR_{})",
+                        key.replace('.', "__").to_uppercase()
+                    )
+                } else if has(&self.profile_section, key) {
+                    format!(
+                        "profile {} in {}",
+                        self.profile.as_deref().unwrap_or_default(),
+                        self.path.display()
+                    )
+                } else if has(&self.file, key) {
+                    self.path.display().to_string()
+                } else if default.is_some() {
+                    "default".to_string()
+                } else {
+                    "not set".to_string()
+                };
+
+                let mut value = merged.get::<Value>(key).ok().map(|v| v.to_string());
+                // Only show a password if it's a reference to where it's kept.
+                if key.ends_with("password") {
+                    value = value.map(|v| {
+                        if crate::creds::is_reference(&v) {
+                            v
+                        } else {
+                            "(hidden)".to_string()
+                        }
+                    });
+                }
+                (*key, value, source)
+            })
+            .collect();
+
+        Ok(described)
+    }
+}
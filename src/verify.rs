@@ -0,0 +1,172 @@
+//! Checking that a subdomain actually serves a certificate after it has been deployed, since
+//! some providers accept an update and then keep serving the old certificate.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Result, WrapErr};
+use indicatif::ProgressBar;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::{
+    certificate::{Certificate, CertificateInfo},
+    cmd::State,
+};
+
+/// How long to wait for each connection and handshake.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accepts whatever certificate the server presents, since the point is to see which one it is
+/// rather than to decide whether to trust it. Handshake signatures are still checked, so the
+/// server has to hold the certificate's key.
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Connect to the host with its name as the SNI, and return the leaf certificate it presents.
+async fn served_certificate(host: &str, port: u16) -> Result<Vec<u8>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| eyre!("Timed out connecting to {host}:{port}"))?
+        .wrap_err_with(|| format!("Failed to connect to {host}:{port}"))?;
+
+    let server_name = ServerName::try_from(host.to_string())?;
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, connector.connect(server_name, stream))
+        .await
+        .map_err(|_| eyre!("Timed out during the TLS handshake with {host}:{port}"))?
+        .wrap_err_with(|| format!("TLS handshake with {host}:{port} failed"))?;
+
+    let (_, connection) = stream.get_ref();
+    connection
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .map(|cert| cert.to_vec())
+        .ok_or_else(|| eyre!("{host}:{port} did not present a certificate"))
+}
+
+/// Describe a served certificate for an error message.
+fn describe(der: &[u8]) -> String {
+    let fingerprint = Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+
+    match CertificateInfo::from_der(der) {
+        Ok(info) => format!("certificate {} (SHA-256 {fingerprint})", info.serial),
+        Err(_) => format!("an unreadable certificate (SHA-256 {fingerprint})"),
+    }
+}
+
+/// Wait until the subdomain serves the certificate, checking again until the configured time
+/// runs out.
+pub async fn verify_served_certificate(
+    state: &State,
+    subdomain: &str,
+    cert: &Certificate,
+) -> Result<()> {
+    let settings = &state.settings.verify;
+
+    // There's no single host to connect to for a wildcard.
+    if settings.timeout_secs == 0 || subdomain.starts_with("*.") {
+        return Ok(());
+    }
+
+    let expected = cert.leaf_der()?;
+    let serial = cert.info()?.serial;
+
+    let progress = state
+        .progress
+        .add(ProgressBar::new_spinner().with_message(format!(
+            "Checking that {subdomain} serves the new certificate"
+        )));
+    progress.enable_steady_tick(Duration::from_millis(125));
+
+    let deadline = Instant::now() + Duration::from_secs(settings.timeout_secs);
+    loop {
+        let problem = match served_certificate(subdomain, settings.port).await {
+            Ok(served) if served == expected => break,
+            Ok(served) => eyre!(
+                "{subdomain} is serving {} instead of the new certificate {serial}",
+                describe(&served)
+            ),
+            Err(e) => e,
+        };
+
+        if Instant::now() >= deadline {
+            progress.finish_and_clear();
+            return Err(problem.wrap_err(format!(
+                "{subdomain} did not serve the new certificate within {} seconds",
+                settings.timeout_secs
+            )));
+        }
+
+        tokio::time::sleep(Duration::from_secs(settings.interval_secs.max(1))).await;
+    }
+
+    progress.finish_with_message("Done");
+
+    Ok(())
+}